@@ -1,10 +1,89 @@
 // 这个模块包含所有Tauri命令
 
+use crate::settings::SettingsStore;
 use crate::tools::edit::EditCommand;
-use crate::tools::{BashTool, ComputerAction, ComputerTool, EditTool, ScrollDirection, ToolResult};
+use crate::tools::{
+    BackupMode, BashAction, BashTool, CommandHistory, CommandPolicy, CommandSignal, ComputerAction, ComputerTool, EditHistory,
+    EditRecord, EditTool, EnvProfile, ExecutionTarget, HistoryEntry, HistoryQuery, Interpreter, ListOptions,
+    ReplaceOptions, ResourceLimits, SandboxConfig, ScrollDirection, SearchOptions, SessionStateStore, StrEdit,
+    ToolResult,
+};
 use log::{error, info};
 use serde::Deserialize;
-use tauri::command;
+use std::{collections::HashMap, sync::Arc};
+use tauri::{command, State};
+use tokio::sync::Mutex;
+
+const DEFAULT_SESSION_ID: &str = "default";
+
+/// 在多次命令调用之间共享的工具状态
+#[derive(Default)]
+pub struct ToolState {
+    /// 按会话ID保存的Bash工具实例
+    bash_tools: Mutex<HashMap<String, Arc<BashTool>>>,
+    /// 按会话ID和解释器保存的 REPL 工具实例
+    repl_tools: Mutex<HashMap<(String, Interpreter), Arc<BashTool>>>,
+    /// Bash命令历史，数据库无法打开时为 None
+    history: Option<Arc<CommandHistory>>,
+    /// Bash会话状态存储，数据库无法打开时为 None
+    state_store: Option<Arc<SessionStateStore>>,
+    /// 所有会话共用的编辑历史存储
+    edit_history: Arc<EditHistory>,
+}
+
+impl ToolState {
+    pub fn new(
+        history: Option<Arc<CommandHistory>>,
+        state_store: Option<Arc<SessionStateStore>>,
+        edit_history: Arc<EditHistory>,
+    ) -> Self {
+        Self {
+            bash_tools: Mutex::new(HashMap::new()),
+            repl_tools: Mutex::new(HashMap::new()),
+            history,
+            state_store,
+            edit_history,
+        }
+    }
+
+    /// 获取会话对应的Bash工具，不存在时创建
+    async fn bash_tool(&self, session_id: Option<&str>) -> Arc<BashTool> {
+        let session_id = session_id.unwrap_or(DEFAULT_SESSION_ID);
+        let mut tools = self.bash_tools.lock().await;
+        Arc::clone(tools.entry(session_id.to_string()).or_insert_with(|| {
+            let mut tool = BashTool::with_session(session_id).with_edit_history(Arc::clone(&self.edit_history));
+            if let Some(history) = &self.history {
+                tool = tool.with_history(Arc::clone(history));
+            }
+            if let Some(store) = &self.state_store {
+                tool = tool.with_state_store(Arc::clone(store));
+            }
+            Arc::new(tool)
+        }))
+    }
+
+    /// 获取会话对应的 REPL 工具，不存在时从会话的Bash工具创建
+    async fn repl_tool(&self, session_id: Option<&str>, interpreter: Interpreter) -> Result<Arc<BashTool>, String> {
+        let bash_tool = self.bash_tool(session_id).await;
+        let session_id = session_id.unwrap_or(DEFAULT_SESSION_ID);
+        let mut tools = self.repl_tools.lock().await;
+        let key = (session_id.to_string(), interpreter);
+        if let Some(tool) = tools.get(&key) {
+            return Ok(Arc::clone(tool));
+        }
+        let tool = Arc::new(bash_tool.repl(interpreter).map_err(|e| e.to_string())?);
+        tools.insert(key, Arc::clone(&tool));
+        Ok(tool)
+    }
+
+    /// 丢弃会话的 REPL 工具，解释器进程随之终止
+    ///
+    /// 工作区、沙箱或执行目标变化后调用，下次使用时在新的配置下启动。
+    async fn reset_repl_tools(&self, session_id: Option<&str>) {
+        let session_id = session_id.unwrap_or(DEFAULT_SESSION_ID);
+        self.repl_tools.lock().await.retain(|(id, _), _| id != session_id);
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct ComputerCommandArgs {
@@ -124,70 +203,263 @@ pub async fn take_screenshot(
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct BashCommandArgs {
+    #[serde(default)]
+    action: BashAction,
+    session_id: Option<String>,
     command: Option<String>,
     restart: Option<bool>,
+    run_in_background: Option<bool>,
+    job_id: Option<String>,
+    since_offset: Option<u64>,
+    env_name: Option<String>,
+    env_value: Option<String>,
+    secret: Option<bool>,
+    env_profile: Option<String>,
+    /// 写入正在执行的命令的标准输入的内容
+    input: Option<String>,
+    /// 发送给正在执行的命令的信号
+    signal: Option<CommandSignal>,
+    /// 命令没有输出并在等待输入多少秒后提前返回
+    idle_timeout: Option<u64>,
+    /// 当前的工具调用，命令造成的文件变更会关联到它
+    tool_use_id: Option<String>,
+}
+
+impl BashCommandArgs {
+    /// 用于日志输出的副本，隐藏敏感的环境变量值和标准输入内容
+    ///
+    /// 命令、输入和变量值中出现的已知敏感值同样替换为掩码。
+    async fn redacted(&self, bash_tool: &BashTool) -> Self {
+        let mut args = self.clone();
+        if args.secret.unwrap_or(false) {
+            if args.env_value.is_some() {
+                args.env_value = Some("******".to_string());
+            }
+            if args.input.is_some() {
+                args.input = Some("******".to_string());
+            }
+        }
+        for text in [&mut args.command, &mut args.input, &mut args.env_value].into_iter().flatten() {
+            *text = bash_tool.mask_secrets(text).await;
+        }
+        args
+    }
 }
 
 /// 执行Bash命令
 #[command]
-pub async fn execute_bash_command(args: BashCommandArgs) -> Result<ToolResult, String> {
-    info!("接收到Bash命令: {:?}", args);
+pub async fn execute_bash_command(
+    state: State<'_, ToolState>,
+    settings: State<'_, SettingsStore>,
+    args: BashCommandArgs,
+) -> Result<ToolResult, String> {
+    let bash_tool = state.bash_tool(args.session_id.as_deref()).await;
+    info!("接收到Bash命令: {:?}", args.redacted(&bash_tool).await);
 
-    // 创建一个新的 BashTool 实例
-    let bash_tool = BashTool::new();
+    if let Err(e) = bash_tool.set_shell(settings.get().shell).await {
+        let err_msg = e.to_string();
+        error!("切换shell失败: {}", err_msg);
+        return Err(err_msg);
+    }
+    run_bash_tool(&bash_tool, &settings, args, None).await
+}
 
-    // 添加超时处理
-    let execution_result = tokio::time::timeout(
-        std::time::Duration::from_secs(35), // 比工具内部超时稍长一些
-        bash_tool.execute(args.command.clone(), args.restart.unwrap_or(false)),
-    )
-    .await;
+/// 用户对等待批准的命令的答复
+///
+/// 不属于工具参数，只能由界面通过 `approve_bash_command` 提交。
+struct Approval {
+    token: String,
+    approved: bool,
+}
 
-    // 处理超时和执行结果
-    match execution_result {
-        Ok(result) => {
-            match result {
-                Ok(tool_result) => {
-                    info!("Bash命令执行成功");
-                    Ok(tool_result)
-                }
-                Err(e) => {
-                    let err_msg = e.to_string();
-                    error!("Bash命令执行失败: {}", err_msg);
-
-                    // 如果是超时错误，返回更友好的错误信息
-                    if err_msg.contains("超时") {
-                        Ok(ToolResult {
-                            output: None,
-                            error: Some(format!(
-                                "命令执行超时: {}",
-                                args.command.unwrap_or_default()
-                            )),
-                            base64_image: None,
-                            system: Some(
-                                "请尝试使用 restart: true 重启 Bash 会话，或者使用更简单的命令"
-                                    .to_string(),
-                            ),
-                        })
-                    } else {
-                        Err(err_msg)
-                    }
-                }
+/// 批准或拒绝一条等待用户批准的命令
+///
+/// `token` 来自命令结果中的 `approval_token`；未指定解释器时在Bash会话中查找。
+#[command]
+pub async fn approve_bash_command(
+    state: State<'_, ToolState>,
+    settings: State<'_, SettingsStore>,
+    session_id: Option<String>,
+    interpreter: Option<Interpreter>,
+    token: String,
+    approved: bool,
+) -> Result<ToolResult, String> {
+    info!("用户{}会话 {:?} 中等待批准的命令", if approved { "批准" } else { "拒绝" }, session_id);
+    let bash_tool = match interpreter {
+        Some(interpreter) if !interpreter.is_shell() => {
+            ensure_repl_tools(&settings)?;
+            state.repl_tool(session_id.as_deref(), interpreter).await.map_err(|err_msg| {
+                error!("创建 REPL 工具失败: {}", err_msg);
+                err_msg
+            })?
+        }
+        _ => {
+            let bash_tool = state.bash_tool(session_id.as_deref()).await;
+            if let Err(e) = bash_tool.set_shell(settings.get().shell).await {
+                let err_msg = e.to_string();
+                error!("切换shell失败: {}", err_msg);
+                return Err(err_msg);
             }
+            bash_tool
         }
-        Err(_) => {
-            // Tauri 命令本身超时
-            error!("Bash命令执行超时（Tauri 命令级别）");
-            Ok(ToolResult {
+    };
+    let args = BashCommandArgs {
+        session_id,
+        ..Default::default()
+    };
+    run_bash_tool(&bash_tool, &settings, args, Some(Approval { token, approved })).await
+}
+
+/// 在持久的 Python 或 Node.js REPL 中执行代码
+///
+/// 参数与Bash命令相同，`command` 为要执行的代码；解释器中的变量和导入的模块在多次调用之间保留。
+#[command]
+pub async fn execute_repl_command(
+    state: State<'_, ToolState>,
+    settings: State<'_, SettingsStore>,
+    interpreter: Interpreter,
+    args: BashCommandArgs,
+) -> Result<ToolResult, String> {
+    ensure_repl_tools(&settings)?;
+    let repl_tool = state.repl_tool(args.session_id.as_deref(), interpreter).await.map_err(|err_msg| {
+        error!("创建 REPL 工具失败: {}", err_msg);
+        err_msg
+    })?;
+    info!("接收到 {} REPL 命令: {:?}", interpreter.name(), args.redacted(&repl_tool).await);
+    run_bash_tool(&repl_tool, &settings, args, None).await
+}
+
+/// 设置中未启用 REPL 工具时返回错误
+fn ensure_repl_tools(settings: &SettingsStore) -> Result<(), String> {
+    if settings.get().repl_tools {
+        return Ok(());
+    }
+    let err_msg = "REPL 工具未启用：命令策略无法检查 REPL 中执行的代码，需要在设置中明确启用".to_string();
+    error!("{}", err_msg);
+    Err(err_msg)
+}
+
+/// 用Bash工具或 REPL 工具执行一次操作
+///
+/// 提供 `approval` 时处理用户对等待批准的命令的答复，忽略 `args` 中的操作。
+async fn run_bash_tool(
+    bash_tool: &BashTool,
+    settings: &SettingsStore,
+    args: BashCommandArgs,
+    approval: Option<Approval>,
+) -> Result<ToolResult, String> {
+    let current_settings = settings.get();
+    bash_tool.set_policy(current_settings.command_policy).await;
+    bash_tool.set_resource_limits(current_settings.resource_limits).await;
+    bash_tool.set_change_tracking(current_settings.track_file_changes).await;
+    bash_tool.set_tool_use_id(args.tool_use_id.clone()).await;
+
+    // 后台任务、环境变量、标准输入和信号操作不经过下面的超时处理，直接返回
+    let quick_result = match args.action {
+        _ if approval.is_some() => None,
+        BashAction::Run if args.run_in_background.unwrap_or(false) && !args.restart.unwrap_or(false) => {
+            Some(bash_tool.run_in_background(args.command.clone()).await)
+        }
+        BashAction::Run => None,
+        BashAction::JobStatus => Some(bash_tool.job_status(args.job_id.clone()).await),
+        BashAction::JobOutput => Some(bash_tool.job_output(args.job_id.clone(), args.since_offset).await),
+        BashAction::JobKill => Some(bash_tool.job_kill(args.job_id.clone()).await),
+        BashAction::EnvSet => Some(
+            bash_tool
+                .env_set(args.env_name.clone(), args.env_value.clone(), args.secret.unwrap_or(false))
+                .await,
+        ),
+        BashAction::EnvUnset => Some(bash_tool.env_unset(args.env_name.clone()).await),
+        BashAction::EnvList => Some(bash_tool.env_list().await),
+        BashAction::Stdin => Some(bash_tool.write_stdin(args.input.clone(), args.idle_timeout).await),
+        BashAction::Signal => Some(bash_tool.send_signal(args.signal).await),
+    };
+    if let Some(result) = quick_result {
+        return match result {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                let err_msg = bash_tool.mask_secrets(&e.to_string()).await;
+                error!("Bash工具操作失败: {}", err_msg);
+                Err(err_msg)
+            }
+        };
+    }
+
+    // 解析需要应用的环境变量配置
+    let env_profile = match args.env_profile.as_deref() {
+        Some(name) => match settings.env_profile(name) {
+            Some(profile) => Some(profile),
+            None => {
+                let err_msg = format!("未找到环境变量配置: {}", name);
+                error!("{}", err_msg);
+                return Err(err_msg);
+            }
+        },
+        None => None,
+    };
+
+    // 工具内部为启动会话、执行命令和记录状态设置了共同的期限，这里不再另加超时：
+    // 从外部取消会使会话停在命令执行的中途
+    let execution_result = async {
+        if let Some(approval) = &approval {
+            return bash_tool.approve(&approval.token, approval.approved).await;
+        }
+        let restart = args.restart.unwrap_or(false);
+        if restart {
+            bash_tool.execute(None, true, None).await?;
+        }
+        if let Some(profile) = &env_profile {
+            bash_tool.apply_env_profile(profile).await?;
+        }
+        if restart {
+            let message = match &env_profile {
+                Some(profile) => format!("工具已重启，已应用环境变量配置 {}", profile.name),
+                None => "工具已重启".to_string(),
+            };
+            return Ok(ToolResult {
                 output: None,
-                error: Some("命令执行超时（Tauri 命令级别）".to_string()),
+                error: None,
                 base64_image: None,
-                system: Some(
-                    "请尝试使用 restart: true 重启 Bash 会话，或者使用更简单的命令".to_string(),
-                ),
-            })
+                system: Some(message),
+                ..Default::default()
+            });
+        }
+        bash_tool
+            .execute(args.command.clone(), false, args.idle_timeout)
+            .await
+    }
+    .await;
+
+    // 处理执行结果
+    match execution_result {
+        Ok(tool_result) => {
+            info!("Bash命令执行成功");
+            Ok(tool_result)
+        }
+        Err(e) => {
+            let err_msg = bash_tool.mask_secrets(&e.to_string()).await;
+            error!("Bash命令执行失败: {}", err_msg);
+
+            // 如果是超时错误，返回更友好的错误信息
+            if err_msg.contains("超时") {
+                Ok(ToolResult {
+                    output: None,
+                    error: Some(format!(
+                        "命令执行超时: {}",
+                        bash_tool.mask_secrets(&args.command.unwrap_or_default()).await
+                    )),
+                    base64_image: None,
+                    system: Some(
+                        "请尝试使用 restart: true 重启 Bash 会话，或者使用更简单的命令"
+                            .to_string(),
+                    ),
+                    ..Default::default()
+                })
+            } else {
+                Err(err_msg)
+            }
         }
     }
 }
@@ -195,23 +467,87 @@ pub async fn execute_bash_command(args: BashCommandArgs) -> Result<ToolResult, S
 #[derive(Debug, Deserialize)]
 pub struct EditCommandArgs {
     command: EditCommand,
+    /// 文件操作在该会话的执行目标上进行
+    session_id: Option<String>,
     path: String,
     file_text: Option<String>,
     view_range: Option<Vec<i32>>,
     old_str: Option<String>,
     new_str: Option<String>,
     insert_line: Option<i32>,
+    /// 撤销或重做的步数（默认 1），或列出历史的条数
+    steps: Option<u32>,
+    /// 只返回修改的差异，不写入文件
+    dry_run: Option<bool>,
+    /// str_replace 的原字符串或 search 的模式是正则表达式
+    regex: Option<bool>,
+    /// str_replace 替换所有匹配
+    replace_all: Option<bool>,
+    /// str_replace 期望的匹配数
+    expected_count: Option<usize>,
+    /// multi_edit 的修改列表，按顺序在原始内容中查找
+    edits: Option<Vec<StrEdit>>,
+    /// apply_patch 的补丁内容，路径相对于 path 指定的目录
+    patch: Option<String>,
+    /// search 的模式或 glob 的模式
+    pattern: Option<String>,
+    /// search 时忽略大小写
+    case_insensitive: Option<bool>,
+    /// search 只搜索匹配这些 glob 的文件
+    include: Option<Vec<String>>,
+    /// search 跳过匹配这些 glob 的文件和目录
+    exclude: Option<Vec<String>>,
+    /// search 结果中每个匹配前后显示的行数
+    context: Option<usize>,
+    /// search 最多返回的匹配数
+    max_results: Option<usize>,
+    /// 查看目录时列出的深度
+    depth: Option<usize>,
+    /// 查看目录和 glob 时包括隐藏文件
+    hidden: Option<bool>,
+    /// 查看目录和 glob 最多列出的条目数
+    max_entries: Option<usize>,
+    /// 当前的工具调用，记录在编辑历史中
+    tool_use_id: Option<String>,
 }
 
 /// 执行文本编辑命令
 #[command]
-pub async fn execute_edit_command(args: EditCommandArgs) -> Result<ToolResult, String> {
+pub async fn execute_edit_command(
+    state: State<'_, ToolState>,
+    settings: State<'_, SettingsStore>,
+    args: EditCommandArgs,
+) -> Result<ToolResult, String> {
     info!(
         "接收到编辑命令: {:?}, 文件路径: {}",
         args.command, args.path
     );
 
-    let edit_tool = EditTool::new();
+    // 与同一会话的Bash工具共用执行目标和编辑历史
+    let bash_tool = state.bash_tool(args.session_id.as_deref()).await;
+    let edit_tool = EditTool::with_target(bash_tool.target().await)
+        .with_history(bash_tool.edit_history(), bash_tool.session_id())
+        .with_tool_use_id(args.tool_use_id)
+        .with_dry_run(args.dry_run.unwrap_or(false))
+        .with_backup(settings.get().edit_backup)
+        .with_replace_options(ReplaceOptions {
+            regex: args.regex.unwrap_or(false),
+            replace_all: args.replace_all.unwrap_or(false),
+            expected_count: args.expected_count,
+        })
+        .with_search_options(SearchOptions {
+            regex: args.regex.unwrap_or(false),
+            case_insensitive: args.case_insensitive.unwrap_or(false),
+            include: args.include.unwrap_or_default(),
+            exclude: args.exclude.unwrap_or_default(),
+            context: args.context.unwrap_or(0),
+            max_results: args.max_results,
+        })
+        .with_list_options(ListOptions {
+            depth: args.depth,
+            hidden: args.hidden.unwrap_or(false),
+            max_entries: args.max_entries,
+        });
 
     match edit_tool
         .execute(
@@ -222,6 +558,10 @@ pub async fn execute_edit_command(args: EditCommandArgs) -> Result<ToolResult, S
             args.old_str,
             args.new_str,
             args.insert_line,
+            args.steps,
+            args.edits,
+            args.patch,
+            args.pattern,
         )
         .await
     {
@@ -237,6 +577,316 @@ pub async fn execute_edit_command(args: EditCommandArgs) -> Result<ToolResult, S
     }
 }
 
+/// 获取文件在会话中的编辑历史，最新的在前
+#[command]
+pub fn get_edit_history(
+    state: State<'_, ToolState>,
+    session_id: Option<String>,
+    path: String,
+    limit: Option<u32>,
+) -> Result<Vec<EditRecord>, String> {
+    info!("获取会话 {:?} 中文件 {} 的编辑历史", session_id, path);
+
+    let session_id = session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID);
+    state
+        .edit_history
+        .list(session_id, std::path::Path::new(&path), limit)
+        .map_err(|e| {
+            let err_msg = e.to_string();
+            error!("获取编辑历史失败: {}", err_msg);
+            err_msg
+        })
+}
+
+/// 设置会话的工作区根目录，Bash会话将从该目录启动
+#[command]
+pub async fn set_workspace_root(
+    state: State<'_, ToolState>,
+    session_id: Option<String>,
+    workspace_root: String,
+) -> Result<ToolResult, String> {
+    info!("设置会话 {:?} 的工作区根目录: {}", session_id, workspace_root);
+
+    let bash_tool = state.bash_tool(session_id.as_deref()).await;
+    state.reset_repl_tools(session_id.as_deref()).await;
+    bash_tool.set_workspace_root(workspace_root).await.map_err(|e| {
+        let err_msg = e.to_string();
+        error!("设置工作区根目录失败: {}", err_msg);
+        err_msg
+    })
+}
+
+/// 设置会话的沙箱配置，下一条命令将在新的沙箱配置下执行
+#[command]
+pub async fn set_sandbox(
+    state: State<'_, ToolState>,
+    session_id: Option<String>,
+    sandbox: SandboxConfig,
+) -> Result<ToolResult, String> {
+    info!("设置会话 {:?} 的沙箱配置: {:?}", session_id, sandbox);
+
+    let bash_tool = state.bash_tool(session_id.as_deref()).await;
+    state.reset_repl_tools(session_id.as_deref()).await;
+    bash_tool.set_sandbox(sandbox).await.map_err(|e| {
+        let err_msg = e.to_string();
+        error!("设置沙箱配置失败: {}", err_msg);
+        err_msg
+    })
+}
+
+/// 设置会话的命令执行目标，Bash命令和文件编辑都将在该目标上进行
+#[command]
+pub async fn set_execution_target(
+    state: State<'_, ToolState>,
+    session_id: Option<String>,
+    target: ExecutionTarget,
+) -> Result<ToolResult, String> {
+    info!("设置会话 {:?} 的执行目标: {:?}", session_id, target);
+
+    let bash_tool = state.bash_tool(session_id.as_deref()).await;
+    state.reset_repl_tools(session_id.as_deref()).await;
+    bash_tool.set_target(target).await.map_err(|e| {
+        let err_msg = e.to_string();
+        error!("设置执行目标失败: {}", err_msg);
+        err_msg
+    })
+}
+
+/// 获取所有环境变量配置
+#[command]
+pub fn get_env_profiles(settings: State<'_, SettingsStore>) -> Vec<EnvProfile> {
+    settings.get().env_profiles
+}
+
+/// 保存环境变量配置，同名配置会被覆盖
+#[command]
+pub fn save_env_profile(
+    settings: State<'_, SettingsStore>,
+    profile: EnvProfile,
+) -> Result<Vec<EnvProfile>, String> {
+    info!("保存环境变量配置: {}", profile.name);
+
+    settings
+        .update(|settings| {
+            settings.env_profiles.retain(|p| p.name != profile.name);
+            settings.env_profiles.push(profile);
+        })
+        .map(|settings| settings.env_profiles)
+        .map_err(|e| {
+            let err_msg = format!("保存环境变量配置失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 删除环境变量配置
+#[command]
+pub fn delete_env_profile(
+    settings: State<'_, SettingsStore>,
+    name: String,
+) -> Result<Vec<EnvProfile>, String> {
+    info!("删除环境变量配置: {}", name);
+
+    settings
+        .update(|settings| settings.env_profiles.retain(|p| p.name != name))
+        .map(|settings| settings.env_profiles)
+        .map_err(|e| {
+            let err_msg = format!("删除环境变量配置失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 获取Bash命令策略
+#[command]
+pub fn get_command_policy(settings: State<'_, SettingsStore>) -> CommandPolicy {
+    settings.get().command_policy
+}
+
+/// 保存Bash命令策略
+#[command]
+pub fn save_command_policy(
+    settings: State<'_, SettingsStore>,
+    policy: CommandPolicy,
+) -> Result<CommandPolicy, String> {
+    info!("保存Bash命令策略，共 {} 条规则", policy.rules.len());
+
+    settings
+        .update(|settings| settings.command_policy = policy)
+        .map(|settings| settings.command_policy)
+        .map_err(|e| {
+            let err_msg = format!("保存Bash命令策略失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 获取Bash会话的资源限制
+#[command]
+pub fn get_resource_limits(settings: State<'_, SettingsStore>) -> ResourceLimits {
+    settings.get().resource_limits
+}
+
+/// 保存Bash会话的资源限制，下一条命令执行时生效
+#[command]
+pub fn save_resource_limits(
+    settings: State<'_, SettingsStore>,
+    limits: ResourceLimits,
+) -> Result<ResourceLimits, String> {
+    info!("保存Bash资源限制: {:?}", limits);
+
+    settings
+        .update(|settings| settings.resource_limits = limits)
+        .map(|settings| settings.resource_limits)
+        .map_err(|e| {
+            let err_msg = format!("保存Bash资源限制失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 获取是否报告Bash命令造成的文件变更
+#[command]
+pub fn get_file_change_tracking(settings: State<'_, SettingsStore>) -> bool {
+    settings.get().track_file_changes
+}
+
+/// 设置是否报告Bash命令造成的文件变更，只在本地会话中生效
+#[command]
+pub fn save_file_change_tracking(
+    settings: State<'_, SettingsStore>,
+    enabled: bool,
+) -> Result<bool, String> {
+    info!("设置Bash文件变更跟踪: {}", enabled);
+
+    settings
+        .update(|settings| settings.track_file_changes = enabled)
+        .map(|settings| settings.track_file_changes)
+        .map_err(|e| {
+            let err_msg = format!("保存文件变更跟踪设置失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 获取Bash工具使用的 shell
+#[command]
+pub fn get_shell(settings: State<'_, SettingsStore>) -> Interpreter {
+    settings.get().shell
+}
+
+/// 设置Bash工具使用的 shell，下一条命令执行时切换
+#[command]
+pub fn save_shell(settings: State<'_, SettingsStore>, shell: Interpreter) -> Result<Interpreter, String> {
+    info!("设置Bash工具使用的 shell: {}", shell.name());
+
+    shell.validate_shell().map_err(|e| e.to_string())?;
+    settings
+        .update(|settings| settings.shell = shell)
+        .map(|settings| settings.shell)
+        .map_err(|e| {
+            let err_msg = format!("保存 shell 设置失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 获取是否启用 REPL 工具
+#[command]
+pub fn get_repl_tools(settings: State<'_, SettingsStore>) -> bool {
+    settings.get().repl_tools
+}
+
+/// 设置是否启用 REPL 工具，下一次调用时生效
+#[command]
+pub fn save_repl_tools(settings: State<'_, SettingsStore>, enabled: bool) -> Result<bool, String> {
+    info!("设置 REPL 工具: {}", enabled);
+
+    settings
+        .update(|settings| settings.repl_tools = enabled)
+        .map(|settings| settings.repl_tools)
+        .map_err(|e| {
+            let err_msg = format!("保存 REPL 工具设置失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 获取编辑工具修改文件前备份原文件的方式
+#[command]
+pub fn get_edit_backup(settings: State<'_, SettingsStore>) -> BackupMode {
+    settings.get().edit_backup
+}
+
+/// 设置编辑工具修改文件前备份原文件的方式，下一次编辑时生效
+#[command]
+pub fn save_edit_backup(settings: State<'_, SettingsStore>, backup: BackupMode) -> Result<BackupMode, String> {
+    info!("设置编辑备份方式: {:?}", backup);
+
+    settings
+        .update(|settings| settings.edit_backup = backup)
+        .map(|settings| settings.edit_backup)
+        .map_err(|e| {
+            let err_msg = format!("保存编辑备份设置失败: {}", e);
+            error!("{}", err_msg);
+            err_msg
+        })
+}
+
+/// 搜索Bash命令历史，最新的记录在前
+#[command]
+pub fn search_bash_history(
+    state: State<'_, ToolState>,
+    query: HistoryQuery,
+) -> Result<Vec<HistoryEntry>, String> {
+    info!("搜索Bash命令历史: {:?}", query);
+
+    let history = state.history.as_ref().ok_or("命令历史不可用")?;
+    history.search(&query).map_err(|e| {
+        let err_msg = e.to_string();
+        error!("搜索Bash命令历史失败: {}", err_msg);
+        err_msg
+    })
+}
+
+/// 在指定会话中重新执行一条历史命令
+///
+/// `in_original_cwd` 为 true 时先切换到命令原来执行时的目录。
+#[command]
+pub async fn rerun_bash_history(
+    state: State<'_, ToolState>,
+    settings: State<'_, SettingsStore>,
+    id: i64,
+    session_id: Option<String>,
+    in_original_cwd: Option<bool>,
+) -> Result<ToolResult, String> {
+    info!("在会话 {:?} 中重新执行历史命令 {}", session_id, id);
+
+    let history = state.history.as_ref().ok_or("命令历史不可用")?;
+    let entry = history.get(id).map_err(|e| {
+        let err_msg = e.to_string();
+        error!("读取历史命令失败: {}", err_msg);
+        err_msg
+    })?;
+
+    // 敏感值被隐藏的命令使用内存中的原文，原文已丢弃时拒绝执行
+    let command = history.runnable_command(&entry).map_err(|e| {
+        let err_msg = e.to_string();
+        error!("{}", err_msg);
+        err_msg
+    })?;
+    let command = match (&entry.cwd, in_original_cwd.unwrap_or(false)) {
+        (Some(cwd), true) => format!("cd {} && {}", shell_escape::unix::escape(cwd.into()), command),
+        _ => command,
+    };
+    let args = BashCommandArgs {
+        session_id,
+        command: Some(command),
+        ..Default::default()
+    };
+    execute_bash_command(state, settings, args).await
+}
+
 #[tauri::command]
 pub fn greet(name: &str) -> String {
     info!("接收到问候请求，用户名: {}", name);
@@ -3,6 +3,7 @@ use tauri::Manager;
 // 导出tools模块
 pub mod tools;
 pub mod commands;
+pub mod settings;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -12,6 +13,8 @@ pub enum Error {
     Tauri(#[from] tauri::Error),
     #[error(transparent)]
     Tool(#[from] tools::base::ToolError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -3,10 +3,13 @@
 
 // 导入命令模块
 use maestro::commands;
+use maestro::settings::{SettingsStore, SETTINGS_FILE};
+use maestro::tools::{terminate_all_processes, CommandHistory, EditHistory, SessionStateStore, DATABASE_FILE};
 // 导入日志模块
 mod logger;
 use log::{info, warn, error};
-use std::fs;
+use std::{fs, sync::Arc};
+use tauri::{Manager, RunEvent};
 
 // 添加一个新的命令来获取屏幕尺寸
 #[tauri::command]
@@ -45,12 +48,67 @@ fn main() {
     
     // 使用命令模块中的命令
     let app = tauri::Builder::default()
+        .setup(|app| {
+            // 从应用配置目录加载设置
+            let config_dir = app.path().app_config_dir()?;
+            app.manage(SettingsStore::load(config_dir.join(SETTINGS_FILE)));
+
+            // 命令历史和会话状态保存在应用数据目录的数据库中，打开失败时不记录
+            let database = app.path().app_data_dir()?.join(DATABASE_FILE);
+            let history = match CommandHistory::open(&database) {
+                Ok(history) => Some(Arc::new(history)),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            };
+            let state_store = match SessionStateStore::open(&database) {
+                Ok(store) => Some(Arc::new(store)),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            };
+            // 编辑历史无法持久化时只保存在内存中
+            let edit_history = match EditHistory::open(&database) {
+                Ok(edit_history) => edit_history,
+                Err(e) => {
+                    error!("{}", e);
+                    EditHistory::new()
+                }
+            };
+            app.manage(commands::ToolState::new(history, state_store, Arc::new(edit_history)));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::execute_computer_command,
             commands::get_computer_options,
             commands::take_screenshot,
             commands::execute_bash_command,
+            commands::execute_repl_command,
+            commands::approve_bash_command,
             commands::execute_edit_command,
+            commands::get_edit_history,
+            commands::set_workspace_root,
+            commands::set_sandbox,
+            commands::set_execution_target,
+            commands::get_env_profiles,
+            commands::save_env_profile,
+            commands::delete_env_profile,
+            commands::get_command_policy,
+            commands::save_command_policy,
+            commands::get_resource_limits,
+            commands::save_resource_limits,
+            commands::get_file_change_tracking,
+            commands::save_file_change_tracking,
+            commands::get_shell,
+            commands::save_shell,
+            commands::get_repl_tools,
+            commands::save_repl_tools,
+            commands::get_edit_backup,
+            commands::save_edit_backup,
+            commands::search_bash_history,
+            commands::rerun_bash_history,
             commands::greet,
             get_screen_size,
             get_log_file_path,
@@ -59,8 +117,16 @@ fn main() {
     
     info!("Tauri 应用程序初始化完成");
     
-    match app.run(tauri::generate_context!()) {
-        Ok(_) => info!("Maestro 应用程序正常退出"),
+    match app.build(tauri::generate_context!()) {
+        Ok(app) => {
+            app.run(|_app, event| {
+                if let RunEvent::Exit = event {
+                    // 退出前终止所有由工具启动的进程
+                    terminate_all_processes();
+                    info!("Maestro 应用程序正常退出");
+                }
+            });
+        }
         Err(e) => error!("Maestro 应用程序运行失败: {}", e),
     }
 }
@@ -0,0 +1,161 @@
+// 这个模块负责应用设置的读取和保存
+
+use crate::tools::{BackupMode, CommandPolicy, EnvProfile, Interpreter, ResourceLimits};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// 设置文件名
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// 应用设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// 命名的环境变量配置，可在启动Bash会话时应用
+    ///
+    /// 标记为敏感的值同样以明文保存在设置文件中，设置文件因此只允许当前用户读写。
+    #[serde(default)]
+    pub env_profiles: Vec<EnvProfile>,
+    /// Bash命令策略
+    #[serde(default)]
+    pub command_policy: CommandPolicy,
+    /// Bash会话的资源限制
+    #[serde(default)]
+    pub resource_limits: ResourceLimits,
+    /// 是否报告Bash命令在工作区中造成的文件变更
+    #[serde(default)]
+    pub track_file_changes: bool,
+    /// Bash工具使用的 shell（bash、sh 或 zsh）
+    #[serde(default)]
+    pub shell: Interpreter,
+    /// 是否启用 Python 和 Node.js REPL 工具
+    ///
+    /// 命令策略无法检查 REPL 中的代码，其中的 `os.system` 等调用不受策略约束，默认关闭。
+    #[serde(default)]
+    pub repl_tools: bool,
+    /// 编辑工具修改文件前备份原文件的方式
+    #[serde(default)]
+    pub edit_backup: BackupMode,
+}
+
+/// 设置存储，保存在应用配置目录下的 JSON 文件中
+///
+/// 在 Unix 上设置文件的权限为 0600。
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// 从文件加载设置，文件不存在或无法解析时使用默认设置
+    pub fn load(path: PathBuf) -> Self {
+        let settings = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("解析设置文件 {} 失败，使用默认设置: {}", path.display(), e);
+                Settings::default()
+            }),
+            Err(_) => {
+                info!("设置文件 {} 不存在，使用默认设置", path.display());
+                Settings::default()
+            }
+        };
+        // 旧版本创建的设置文件可能允许其他用户读取
+        if path.exists() {
+            if let Err(e) = restrict_permissions(&path) {
+                warn!("无法限制设置文件 {} 的权限: {}", path.display(), e);
+            }
+        }
+
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    /// 获取当前设置
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// 修改设置并写回文件
+    pub fn update(&self, f: impl FnOnce(&mut Settings)) -> crate::Result<Settings> {
+        let mut settings = self.settings.lock().unwrap();
+        let mut updated = settings.clone();
+        f(&mut updated);
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(&self.path, &serde_json::to_string_pretty(&updated)?)?;
+
+        *settings = updated.clone();
+        Ok(updated)
+    }
+
+    /// 按名称查找环境变量配置
+    pub fn env_profile(&self, name: &str) -> Option<EnvProfile> {
+        self.settings
+            .lock()
+            .unwrap()
+            .env_profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .cloned()
+    }
+}
+
+/// 写入只允许当前用户读写的文件
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // 创建时的权限只对新文件有效
+    restrict_permissions(path)?;
+    file.write_all(content.as_bytes())
+}
+
+/// 把文件权限设置为只允许当前用户读写，其他平台上什么也不做
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn settings_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("maestro-settings-{}", uuid::Uuid::new_v4()));
+        let path = dir.join(SETTINGS_FILE);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let store = SettingsStore::load(path.clone());
+        let loaded_mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        fs::remove_file(&path).unwrap();
+        store.update(|settings| settings.repl_tools = true).unwrap();
+        let created_mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(loaded_mode, 0o600);
+        assert_eq!(created_mode, 0o600);
+    }
+}
@@ -1,8 +1,80 @@
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+/// 策略规则的动作，也是策略检查的结论
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// 允许执行
+    #[default]
+    Allow,
+    /// 需要用户批准
+    Ask,
+    /// 拒绝执行
+    Deny,
+}
+
+/// 策略检查的结果
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PolicyVerdict {
+    pub decision: PolicyAction,
+    /// 结论的原因，允许执行且没有规则命中时为空
+    pub reason: Option<String>,
+}
+
+impl PolicyVerdict {
+    pub(crate) fn new(decision: PolicyAction, reason: impl Into<String>) -> Self {
+        Self {
+            decision,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// 触发的资源限制种类
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKind {
+    CpuTime,
+    AddressSpace,
+    FileSize,
+    OpenFiles,
+    Processes,
+    Memory,
+    CpuQuota,
+}
+
+/// 一次资源限制触发记录
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LimitHit {
+    pub kind: LimitKind,
+    /// 配置的限制值，单位与 `ResourceLimits` 中对应字段相同
+    pub limit: u64,
+    pub message: String,
+}
+
+/// 文件变更类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// 命令执行期间工作区中的一个文件变更
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileChange {
+    pub path: String,
+    pub kind: ChangeKind,
+}
 
 /// 表示工具执行的结果
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ToolResult {
     /// 工具执行的输出文本
     pub output: Option<String>,
@@ -12,6 +84,27 @@ pub struct ToolResult {
     pub base64_image: Option<String>,
     /// 系统消息
     pub system: Option<String>,
+    /// 命令执行后shell所在的工作目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// 命令未通过策略检查时的结论
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyVerdict>,
+    /// 命令的退出码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// 命令执行期间触发的资源限制
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub limit_hits: Vec<LimitHit>,
+    /// 命令在工作区中新建、修改和删除的文件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_changes: Vec<FileChange>,
+    /// 文件修改的统一差异
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// 命令等待用户批准时的批准令牌，只交给界面，不放进模型看到的工具结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_token: Option<String>,
 }
 
 impl ToolResult {
@@ -22,6 +115,13 @@ impl ToolResult {
             error: None,
             base64_image: None,
             system: None,
+            cwd: None,
+            policy: None,
+            exit_code: None,
+            limit_hits: Vec::new(),
+            file_changes: Vec::new(),
+            diff: None,
+            approval_token: None,
         }
     }
 
@@ -32,6 +132,13 @@ impl ToolResult {
             error: Some(error.into()),
             base64_image: None,
             system: None,
+            cwd: None,
+            policy: None,
+            exit_code: None,
+            limit_hits: Vec::new(),
+            file_changes: Vec::new(),
+            diff: None,
+            approval_token: None,
         }
     }
 
@@ -46,6 +153,21 @@ impl ToolResult {
         self.system = Some(system.into());
         self
     }
+
+    /// 添加统一差异，差异为空时不添加
+    pub fn with_diff(mut self, diff: impl Into<String>) -> Self {
+        let diff = diff.into();
+        if !diff.is_empty() {
+            self.diff = Some(diff);
+        }
+        self
+    }
+
+    /// 添加工作目录
+    pub fn with_cwd(mut self, cwd: impl Into<String>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
 }
 
 /// 工具执行错误
@@ -75,4 +197,36 @@ impl From<ToolError> for ToolResult {
     fn from(error: ToolError) -> Self {
         Self::error(error.message)
     }
-} 
\ No newline at end of file
+} 
+/// 把会话ID等外部传入的标识转换为可以放进路径的文件名
+///
+/// 只保留 `[A-Za-z0-9_-]`，含有其他字符时替换为 `_` 并附加原标识的哈希，不同的标识不会得到同一个文件名。
+pub(crate) fn file_name_component(id: &str) -> String {
+    let safe: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    if !id.is_empty() && safe == id {
+        return safe;
+    }
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    format!("{}~{:016x}", safe, hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_component_stays_a_single_component() {
+        assert_eq!(file_name_component("chat-1_a"), "chat-1_a");
+        for id in ["", ".", "..", "../../etc/x", "/abs", "a/b", "a\\b", "会话"] {
+            let name = file_name_component(id);
+            assert!(!name.contains(['/', '\\']) && !name.starts_with('.') && !name.is_empty(), "{}", name);
+            assert!(name.contains('~'), "{}", name);
+        }
+        assert_ne!(file_name_component("a/b"), file_name_component("a_b"));
+        assert_ne!(file_name_component("a/b"), file_name_component("a\\b"));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::tools::{
+    base::{ChangeKind, FileChange},
+    target::ExecutionTarget,
+    walk::{walk, Visit, WalkOptions},
+};
+use log::warn;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// 不跟踪的目录，通常是版本库元数据、依赖和构建产物
+const IGNORED_DIRS: &[&str] = &[".git", ".hg", ".svn", "node_modules", "target", "__pycache__", ".venv"];
+/// 最多跟踪的文件数，超出时本条命令不跟踪变更
+const MAX_TRACKED_FILES: usize = 50_000;
+/// 超过这个大小的文件只记录元数据，变更后无法撤销
+const MAX_CACHED_FILE_SIZE: u64 = 1024 * 1024;
+/// 缓存的文件内容总量上限
+const MAX_CACHE_SIZE: usize = 64 * 1024 * 1024;
+/// 系统消息中每种变更最多列出的文件数
+const MAX_DESCRIBED_FILES: usize = 20;
+
+/// 检测到的变更以及变更前的文件内容
+pub(super) struct TrackedChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// 变更前的内容，新建的文件和未缓存内容的文件为 None
+    pub previous: Option<Vec<u8>>,
+    /// 变更后的内容，删除的文件和未缓存内容的文件为 None
+    pub current: Option<Vec<u8>>,
+}
+
+/// 快照中的一个文件
+struct FileState {
+    modified: SystemTime,
+    len: u64,
+    content: Option<Vec<u8>>,
+}
+
+/// 通过前后快照检测工作区中的文件变更
+///
+/// 快照记录每个文件的修改时间和大小，较小的文件同时缓存内容，以便把变更前的内容
+/// 写入编辑历史。每次扫描只读取修改时间或大小发生变化的文件。
+pub(super) struct ChangeTracker {
+    root: PathBuf,
+    files: HashMap<PathBuf, FileState>,
+    /// 已缓存的内容总字节数
+    cached: usize,
+    /// 快照不完整的原因；不完整时下一次扫描只重建快照，不报告变更
+    incomplete: Option<String>,
+}
+
+impl ChangeTracker {
+    /// 创建空快照，第一次扫描只建立快照
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            files: HashMap::new(),
+            cached: 0,
+            incomplete: Some("尚未建立快照".to_string()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 扫描工作区，返回自上次扫描以来的变更，并更新快照
+    ///
+    /// 这次或上次扫描无法列出全部文件时返回原因。只列出部分文件时两次快照中的文件
+    /// 不一致，比较结果没有意义，因此这段时间内的变更不被跟踪。
+    pub fn scan(&mut self) -> Result<Vec<TrackedChange>, String> {
+        let current = match walk_files(&self.root) {
+            Ok(current) => current,
+            Err(reason) => {
+                self.files.clear();
+                self.cached = 0;
+                self.incomplete = Some(reason.clone());
+                return Err(reason);
+            }
+        };
+        let mut changes = Vec::new();
+
+        let deleted: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in deleted {
+            let state = self.files.remove(&path).expect("文件必须在快照中");
+            self.release(&state);
+            changes.push(TrackedChange {
+                path,
+                kind: ChangeKind::Deleted,
+                previous: state.content,
+                current: None,
+            });
+        }
+
+        for (path, (modified, len)) in current {
+            let kind = match self.files.get(&path) {
+                None => ChangeKind::Created,
+                Some(state) if state.modified == modified && state.len == len => continue,
+                Some(_) => ChangeKind::Modified,
+            };
+            let content = self.read_content(&path, len);
+            let previous = self.files.remove(&path).and_then(|state| {
+                self.release(&state);
+                state.content
+            });
+            // 只有修改时间变化而内容相同时不算变更
+            let unchanged = kind == ChangeKind::Modified && previous.is_some() && previous == content;
+
+            self.cached += content.as_ref().map_or(0, Vec::len);
+            if !unchanged {
+                changes.push(TrackedChange {
+                    path: path.clone(),
+                    kind,
+                    previous,
+                    current: content.clone(),
+                });
+            }
+            self.files.insert(path, FileState { modified, len, content });
+        }
+
+        if let Some(reason) = self.incomplete.take() {
+            return Err(reason);
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    /// 在大小和总量限制内读取文件内容
+    fn read_content(&self, path: &Path, len: u64) -> Option<Vec<u8>> {
+        if len > MAX_CACHED_FILE_SIZE || self.cached + len as usize > MAX_CACHE_SIZE {
+            return None;
+        }
+        fs::read(path).ok()
+    }
+
+    fn release(&mut self, state: &FileState) {
+        self.cached -= state.content.as_ref().map_or(0, Vec::len);
+    }
+}
+
+/// 列出 `root` 下所有普通文件的修改时间和大小
+///
+/// 与其他工具一样遵循 .gitignore，不跟随符号链接。文件超过 `MAX_TRACKED_FILES` 个
+/// 或无法读取根目录时返回原因。
+fn walk_files(root: &Path) -> Result<HashMap<PathBuf, (SystemTime, u64)>, String> {
+    let mut files = HashMap::new();
+    let mut overflow = false;
+    let options = WalkOptions {
+        hidden: true,
+        gitignore: true,
+        max_depth: None,
+    };
+    walk(&ExecutionTarget::Local, root, options, |entry| {
+        if entry.is_symlink || !entry.is_file {
+            let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+            if entry.is_dir && IGNORED_DIRS.contains(&name.as_ref()) {
+                return Visit::Skip;
+            }
+            return Visit::Continue;
+        }
+        if files.len() >= MAX_TRACKED_FILES {
+            overflow = true;
+            return Visit::Stop;
+        }
+        if let Ok(metadata) = fs::metadata(&entry.path) {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.insert(entry.path.clone(), (modified, metadata.len()));
+        }
+        Visit::Continue
+    })
+    .map_err(|e| format!("无法读取工作区: {}", e))?;
+
+    if overflow {
+        warn!("工作区 {} 中的文件超过 {} 个，不跟踪变更", root.display(), MAX_TRACKED_FILES);
+        return Err(format!("工作区中的文件超过 {} 个", MAX_TRACKED_FILES));
+    }
+    Ok(files)
+}
+
+/// 把变更列表合并为一条系统消息
+pub(super) fn describe_changes(changes: &[FileChange]) -> String {
+    let parts: Vec<String> = [
+        ("新建", ChangeKind::Created),
+        ("修改", ChangeKind::Modified),
+        ("删除", ChangeKind::Deleted),
+    ]
+    .into_iter()
+    .filter_map(|(label, kind)| {
+        let paths: Vec<&str> = changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .map(|change| change.path.as_str())
+            .collect();
+        match paths.len() {
+            0 => None,
+            n if n > MAX_DESCRIBED_FILES => Some(format!(
+                "{}: {} 等 {} 个文件",
+                label,
+                paths[..MAX_DESCRIBED_FILES].join(", "),
+                n
+            )),
+            _ => Some(format!("{}: {}", label, paths.join(", "))),
+        }
+    })
+    .collect();
+    format!("命令改动了工作区中的文件（{}）", parts.join("；"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_honours_gitignore_and_starts_with_baseline() {
+        let root = std::env::temp_dir().join(format!("maestro-changes-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n*.log\n").unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let mut tracker = ChangeTracker::new(root.clone());
+        assert!(tracker.scan().is_err());
+
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(root.join("run.log"), "log").unwrap();
+        fs::write(root.join("build/out"), "out").unwrap();
+        fs::remove_file(root.join("a.txt")).unwrap();
+        let changes = tracker.scan().unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        let changes: Vec<(PathBuf, ChangeKind)> = changes.into_iter().map(|change| (change.path, change.kind)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (root.join("a.txt"), ChangeKind::Deleted),
+                (root.join("b.txt"), ChangeKind::Created),
+            ]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::tools::base::ToolError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    process::Command,
+};
+
+pub(super) const SECRET_MASK: &str = "******";
+const MIN_MASKED_SECRET_LEN: usize = 4; // 过短的值替换后会误伤普通输出
+
+/// 一个环境变量
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    /// 是否为敏感值，敏感值不会出现在日志和工具输出中
+    ///
+    /// 保存在环境变量配置中时仍以明文写入设置文件。
+    #[serde(default)]
+    pub secret: bool,
+}
+
+/// 命名的环境变量配置，例如 "node18"、"proxy-off"
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EnvProfile {
+    pub name: String,
+    /// 需要设置的变量
+    #[serde(default)]
+    pub vars: Vec<EnvVar>,
+    /// 需要移除的变量
+    #[serde(default)]
+    pub unset: Vec<String>,
+}
+
+/// 由工具管理的会话环境变量
+///
+/// 这些变量在启动bash进程时写入进程环境，因此会话重启后依然生效，
+/// 后台任务也会继承它们。
+#[derive(Debug, Clone, Default)]
+pub struct SessionEnv {
+    vars: BTreeMap<String, EnvVar>,
+    unset: BTreeSet<String>,
+}
+
+impl SessionEnv {
+    /// 设置变量
+    pub fn set(&mut self, var: EnvVar) {
+        self.unset.remove(&var.name);
+        self.vars.insert(var.name.clone(), var);
+    }
+
+    /// 移除变量
+    pub fn unset(&mut self, name: &str) {
+        self.vars.remove(name);
+        self.unset.insert(name.to_string());
+    }
+
+    /// 应用环境变量配置
+    pub fn apply_profile(&mut self, profile: &EnvProfile) {
+        for name in &profile.unset {
+            self.unset(name);
+        }
+        for var in &profile.vars {
+            self.set(var.clone());
+        }
+    }
+
+    /// 把变量写入即将启动的进程环境
+    pub fn apply_to_command(&self, command: &mut Command) {
+        for name in &self.unset {
+            command.env_remove(name);
+        }
+        for var in self.vars.values() {
+            command.env(&var.name, &var.value);
+        }
+    }
+
+    /// 在shell中应用这些变量的命令，用于无法直接设置进程环境的远程执行目标
+    pub fn script(&self) -> String {
+        self.unset
+            .iter()
+            .map(|name| unset_command(name))
+            .chain(self.vars.values().map(export_command))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 变量是否由工具设置为敏感值
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.vars.get(name).is_some_and(|var| var.secret)
+    }
+
+    /// 把文本中出现的敏感值替换为掩码
+    pub fn mask_secrets(&self, text: &str) -> String {
+        self.vars
+            .values()
+            .filter(|var| var.secret && var.value.len() >= MIN_MASKED_SECRET_LEN)
+            .fold(text.to_string(), |text, var| text.replace(&var.value, SECRET_MASK))
+    }
+
+    /// 列出由工具管理的变量，敏感值显示为掩码
+    pub fn describe(&self) -> String {
+        if self.vars.is_empty() && self.unset.is_empty() {
+            return "没有由工具设置的环境变量".to_string();
+        }
+
+        let mut lines = Vec::new();
+        for var in self.vars.values() {
+            let value = if var.secret { SECRET_MASK } else { var.value.as_str() };
+            lines.push(format!("{}={}", var.name, value));
+        }
+        for name in &self.unset {
+            lines.push(format!("{}（已移除）", name));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 检查环境变量名是否合法
+pub fn validate_name(name: &str) -> Result<(), ToolError> {
+    let mut chars = name.chars();
+    let valid = matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric());
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ToolError::new(format!("无效的环境变量名: {}", name)))
+    }
+}
+
+/// 生成在运行中的shell里设置变量的命令
+pub fn export_command(var: &EnvVar) -> String {
+    format!(
+        "export {}={}",
+        var.name,
+        shell_escape::unix::escape(var.value.as_str().into())
+    )
+}
+
+/// 生成在运行中的shell里移除变量的命令
+pub fn unset_command(name: &str) -> String {
+    format!("unset {}", name)
+}
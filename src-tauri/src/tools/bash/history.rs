@@ -0,0 +1,255 @@
+use super::env::SECRET_MASK;
+use crate::tools::base::ToolError;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path, sync::Mutex, time::Duration};
+
+/// 应用数据目录下的 SQLite 数据库文件名
+pub const DATABASE_FILE: &str = "maestro.db";
+/// 每条记录保存的输出上限，超出时只保留末尾部分
+const MAX_RECORDED_OUTPUT: usize = 16 * 1024;
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+/// 内存中最多保留多少条隐藏了敏感值的命令原文
+const MAX_UNMASKED: usize = 1000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS bash_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    command TEXT NOT NULL,
+    cwd TEXT,
+    exit_code INTEGER,
+    duration_ms INTEGER NOT NULL,
+    output TEXT,
+    background INTEGER NOT NULL DEFAULT 0,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_bash_history_session ON bash_history (session_id, started_at);
+CREATE INDEX IF NOT EXISTS idx_bash_history_started_at ON bash_history (started_at);
+";
+
+/// 一条Bash命令历史记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub session_id: String,
+    pub command: String,
+    /// 命令开始执行时shell所在的目录
+    pub cwd: Option<String>,
+    /// 退出码，超时、后台执行或bash异常退出时为空
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// 截断后的输出，敏感环境变量已隐藏
+    pub output: Option<String>,
+    /// 是否作为后台任务执行
+    pub background: bool,
+    /// 开始和结束时间（UTC，RFC 3339）
+    pub started_at: String,
+    pub finished_at: String,
+}
+
+/// 历史记录查询条件，所有条件都是可选的
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// 在命令和输出中搜索的文本
+    pub text: Option<String>,
+    pub session_id: Option<String>,
+    /// 只返回此时间之后开始的命令（RFC 3339）
+    pub since: Option<String>,
+    /// 只返回此时间之前开始的命令（RFC 3339）
+    pub until: Option<String>,
+    /// 只返回退出码非 0 或没有退出码的命令
+    #[serde(default)]
+    pub failed_only: bool,
+    /// 最多返回的条数，默认 50
+    pub limit: Option<u32>,
+}
+
+/// 要写入历史的一次命令执行
+pub(super) struct NewEntry<'a> {
+    pub session_id: &'a str,
+    /// 隐藏了敏感值的命令，写入数据库
+    pub command: &'a str,
+    /// 命令原文，与 `command` 不同时只保存在内存中，用于重新执行
+    pub unmasked_command: &'a str,
+    pub cwd: Option<&'a str>,
+    pub exit_code: Option<i32>,
+    pub output: &'a str,
+    pub background: bool,
+    pub started_at: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+/// 保存在 SQLite 中的Bash命令历史
+pub struct CommandHistory {
+    conn: Mutex<Connection>,
+    /// 隐藏了敏感值的记录的命令原文，不写入磁盘，应用退出后丢弃
+    unmasked: Mutex<BTreeMap<i64, String>>,
+}
+
+impl CommandHistory {
+    /// 打开数据库文件，必要时创建表
+    pub fn open(path: &Path) -> Result<Self, ToolError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ToolError::new(format!("创建数据库目录失败: {}", e)))?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| ToolError::new(format!("打开数据库 {} 失败: {}", path.display(), e)))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| ToolError::new(format!("初始化命令历史表失败: {}", e)))?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            unmasked: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// 记录一次命令执行
+    pub(super) fn record(&self, entry: NewEntry) -> Result<(), ToolError> {
+        let finished_at = entry.started_at
+            + chrono::Duration::from_std(entry.duration).unwrap_or_else(|_| chrono::Duration::zero());
+        let output = truncate_output(entry.output);
+
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO bash_history
+                (session_id, command, cwd, exit_code, duration_ms, output, background, started_at, finished_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                entry.session_id,
+                entry.command,
+                entry.cwd,
+                entry.exit_code,
+                entry.duration.as_millis() as i64,
+                if output.is_empty() { None } else { Some(output) },
+                entry.background,
+                format_time(entry.started_at),
+                format_time(finished_at),
+            ],
+        )
+        .map_err(|e| ToolError::new(format!("写入命令历史失败: {}", e)))?;
+
+        if entry.unmasked_command != entry.command {
+            let mut unmasked = self.unmasked.lock().unwrap();
+            unmasked.insert(conn.last_insert_rowid(), entry.unmasked_command.to_string());
+            if unmasked.len() > MAX_UNMASKED {
+                unmasked.pop_first();
+            }
+        }
+        Ok(())
+    }
+
+    /// 按条件搜索历史，最新的记录在前
+    pub fn search(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, ToolError> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(text) = query.text.as_deref().filter(|text| !text.is_empty()) {
+            let pattern = format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+            conditions.push("(command LIKE ? ESCAPE '\\' OR output LIKE ? ESCAPE '\\')");
+            values.push(Value::Text(pattern.clone()));
+            values.push(Value::Text(pattern));
+        }
+        if let Some(session_id) = &query.session_id {
+            conditions.push("session_id = ?");
+            values.push(Value::Text(session_id.clone()));
+        }
+        if let Some(since) = &query.since {
+            conditions.push("started_at >= ?");
+            values.push(Value::Text(parse_time(since)?));
+        }
+        if let Some(until) = &query.until {
+            conditions.push("started_at <= ?");
+            values.push(Value::Text(parse_time(until)?));
+        }
+        if query.failed_only {
+            conditions.push("(exit_code IS NULL OR exit_code != 0)");
+        }
+
+        let mut sql = "SELECT * FROM bash_history".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY started_at DESC, id DESC LIMIT ?");
+        values.push(Value::Integer(i64::from(query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))));
+
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare(&sql)
+            .map_err(|e| ToolError::new(format!("查询命令历史失败: {}", e)))?;
+        let entries = statement
+            .query_map(params_from_iter(values), entry_from_row)
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| ToolError::new(format!("查询命令历史失败: {}", e)))?;
+        Ok(entries)
+    }
+
+    /// 按ID获取一条记录
+    pub fn get(&self, id: i64) -> Result<HistoryEntry, ToolError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT * FROM bash_history WHERE id = ?1", [id], entry_from_row)
+            .optional()
+            .map_err(|e| ToolError::new(format!("查询命令历史失败: {}", e)))?
+            .ok_or_else(|| ToolError::new(format!("未找到命令历史记录: {}", id)))
+    }
+
+    /// 重新执行历史记录时使用的命令
+    ///
+    /// 敏感值被隐藏的命令只能在本次运行期间重新执行，之后原文已经丢弃。
+    pub fn runnable_command(&self, entry: &HistoryEntry) -> Result<String, ToolError> {
+        if let Some(command) = self.unmasked.lock().unwrap().get(&entry.id) {
+            return Ok(command.clone());
+        }
+        if entry.command.contains(SECRET_MASK) {
+            return Err(ToolError::new(format!(
+                "历史命令 {} 中的敏感值已被隐藏，无法重新执行",
+                entry.id
+            )));
+        }
+        Ok(entry.command.clone())
+    }
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.get("id")?,
+        session_id: row.get("session_id")?,
+        command: row.get("command")?,
+        cwd: row.get("cwd")?,
+        exit_code: row.get("exit_code")?,
+        duration_ms: row.get::<_, i64>("duration_ms")? as u64,
+        output: row.get("output")?,
+        background: row.get("background")?,
+        started_at: row.get("started_at")?,
+        finished_at: row.get("finished_at")?,
+    })
+}
+
+/// 统一使用带毫秒的 UTC 时间，保证按字符串比较即按时间比较
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn parse_time(value: &str) -> Result<String, ToolError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| format_time(time.with_timezone(&Utc)))
+        .map_err(|e| ToolError::new(format!("无效的时间 {}: {}", value, e)))
+}
+
+/// 只保留输出末尾的 `MAX_RECORDED_OUTPUT` 字节，错误信息通常在最后
+fn truncate_output(output: &str) -> String {
+    if output.len() <= MAX_RECORDED_OUTPUT {
+        return output.to_string();
+    }
+    let mut start = output.len() - MAX_RECORDED_OUTPUT;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("...（已截断）\n{}", &output[start..])
+}
@@ -0,0 +1,217 @@
+use super::env::{self, EnvVar};
+use crate::tools::base::ToolError;
+use serde::{Deserialize, Serialize};
+
+/// Python 解释器的驱动脚本
+///
+/// 每行输入是 `<base64 代码>\t<哨兵前半>\t<哨兵后半>`。代码在同一个命名空间中执行，
+/// 最后一个表达式的值像交互式解释器一样输出；异常的回溯输出到标准输出，退出码为 1。
+const PYTHON_DRIVER: &str = r#"import ast, base64, os, sys, traceback
+os.dup2(1, 2)
+namespace = {'__name__': '__main__', '__builtins__': __builtins__}
+def run(source):
+    tree = ast.parse(source, '<maestro>')
+    last = None
+    if tree.body and isinstance(tree.body[-1], ast.Expr):
+        last = ast.Expression(tree.body.pop().value)
+    exec(compile(tree, '<maestro>', 'exec'), namespace)
+    if last is not None:
+        value = eval(compile(last, '<maestro>', 'eval'), namespace)
+        if value is not None:
+            namespace['_'] = value
+            print(repr(value))
+while True:
+    line = sys.stdin.readline()
+    if not line:
+        break
+    parts = line.rstrip('\n').split('\t')
+    if len(parts) != 3:
+        continue
+    status = 0
+    try:
+        run(base64.b64decode(parts[0]).decode())
+    except SystemExit as e:
+        status = e.code if isinstance(e.code, int) else (0 if e.code is None else 1)
+    except BaseException:
+        status = 1
+        kind, value, tb = sys.exc_info()
+        while tb is not None and tb.tb_frame.f_code.co_filename == '<string>':
+            tb = tb.tb_next
+        traceback.print_exception(kind, value, tb)
+    sys.stdout.flush()
+    sys.stdout.write('\n%s%s:%d:%s\n' % (parts[1], parts[2], status, os.getcwd()))
+    sys.stdout.flush()
+"#;
+
+/// Node.js 的驱动脚本，输入格式与 Python 相同
+///
+/// 代码在全局上下文中执行，结果是 Promise 时等待它完成。
+const NODE_DRIVER: &str = r#"const vm = require('vm'), util = require('util'), readline = require('readline');
+globalThis.require = require;
+process.stderr.write = process.stdout.write.bind(process.stdout);
+(async () => {
+  const lines = readline.createInterface({ input: process.stdin, terminal: false });
+  for await (const line of lines) {
+    const parts = line.split('\t');
+    if (parts.length !== 3) continue;
+    let status = 0;
+    try {
+      let value = vm.runInThisContext(Buffer.from(parts[0], 'base64').toString('utf8'), { filename: '<maestro>' });
+      if (value && typeof value.then === 'function') value = await value;
+      if (value !== undefined) console.log(util.inspect(value));
+    } catch (e) {
+      status = 1;
+      const stack = e && e.stack ? e.stack.split('\n') : [util.inspect(e)];
+      const end = stack.findIndex(line => line.includes('runInThisContext'));
+      console.log((end < 0 ? stack : stack.slice(0, end)).join('\n'));
+    }
+    process.stdout.write('\n' + parts[1] + parts[2] + ':' + status + ':' + process.cwd() + '\n');
+  }
+})();
+"#;
+
+/// 会话中运行的解释器
+///
+/// shell 通过 `eval` 执行命令；Python 和 Node.js 由驱动脚本逐条执行代码，
+/// 加载的模块和变量在多次调用之间保留。它们共用会话的哨兵、超时和输出限制。
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpreter {
+    #[default]
+    Bash,
+    Sh,
+    Zsh,
+    Python,
+    Node,
+}
+
+impl Interpreter {
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpreter::Bash => "bash",
+            Interpreter::Sh => "sh",
+            Interpreter::Zsh => "zsh",
+            Interpreter::Python => "python",
+            Interpreter::Node => "node",
+        }
+    }
+
+    /// 是否为 shell，只有 shell 支持命令策略、后台任务和会话状态恢复
+    pub fn is_shell(self) -> bool {
+        matches!(self, Interpreter::Bash | Interpreter::Sh | Interpreter::Zsh)
+    }
+
+    /// 启动解释器的命令行
+    pub(super) fn program(self) -> Vec<&'static str> {
+        match self {
+            Interpreter::Bash => vec!["bash"],
+            Interpreter::Sh => vec!["sh"],
+            Interpreter::Zsh => vec!["zsh", "-f"],
+            Interpreter::Python => vec!["python3", "-u", "-c", PYTHON_DRIVER],
+            Interpreter::Node => vec!["node", "-e", NODE_DRIVER],
+        }
+    }
+
+    /// 进程名是否属于这个解释器，用于在进程树中找到执行命令的进程
+    pub(super) fn is_process(self, name: &str) -> bool {
+        let name = name.rsplit('/').next().unwrap_or(name);
+        match self {
+            Interpreter::Bash => name.ends_with("bash"),
+            Interpreter::Sh => name == "sh" || name == "dash" || name == "ash",
+            Interpreter::Zsh => name == "zsh",
+            Interpreter::Python => name.starts_with("python"),
+            Interpreter::Node => name.starts_with("node"),
+        }
+    }
+
+    /// 把 base64 编码的代码包装成一行输入，执行后输出 `\n<哨兵>:<退出码>:<当前目录>`
+    pub(super) fn wrap(self, encoded: &str, head: &str, tail: &str) -> String {
+        if self.is_shell() {
+            format!(
+                "{{ eval \"$(printf '%s' '{}' | base64 --decode)\"; }} 2>&1; printf '\\n%s%s:%s:%s\\n' '{}' '{}' \"$?\" \"$PWD\"",
+                encoded, head, tail
+            )
+        } else {
+            format!("{}\t{}\t{}", encoded, head, tail)
+        }
+    }
+
+    /// 输出解释器进程pid的代码
+    pub(super) fn pid_command(self) -> &'static str {
+        match self {
+            Interpreter::Python => "print(__import__('os').getpid())",
+            Interpreter::Node => "console.log(process.pid)",
+            _ => "printf '%s' \"$$\"",
+        }
+    }
+
+    /// 输出会话状态的命令，不支持恢复状态的解释器返回 None
+    ///
+    /// 只有 bash 和 zsh 的别名可以原样恢复，sh 只记录环境变量。
+    pub(super) fn capture_command(self) -> Option<&'static str> {
+        match self {
+            Interpreter::Bash => Some(
+                "env -0; printf '\\n%s\\n' '__MAESTRO_STATE__'; alias -p; shopt -p expand_aliases",
+            ),
+            Interpreter::Zsh => Some("env -0; printf '\\n%s\\n' '__MAESTRO_STATE__'; alias -L"),
+            Interpreter::Sh => Some("env -0; printf '\\n%s\\n' '__MAESTRO_STATE__'"),
+            Interpreter::Python | Interpreter::Node => None,
+        }
+    }
+
+    /// 在运行中的解释器里设置环境变量的代码
+    pub(super) fn export_command(self, var: &EnvVar) -> String {
+        match self {
+            Interpreter::Python => {
+                format!("__import__('os').environ[{}] = {}", quote(&var.name), quote(&var.value))
+            }
+            Interpreter::Node => format!("process.env[{}] = {}; undefined", quote(&var.name), quote(&var.value)),
+            _ => env::export_command(var),
+        }
+    }
+
+    /// 在运行中的解释器里移除环境变量的代码
+    pub(super) fn unset_command(self, name: &str) -> String {
+        match self {
+            Interpreter::Python => format!("__import__('os').environ.pop({}, None); None", quote(name)),
+            Interpreter::Node => format!("delete process.env[{}]; undefined", quote(name)),
+            _ => env::unset_command(name),
+        }
+    }
+
+    /// 按名称排序输出所有环境变量的代码
+    pub(super) fn env_list_command(self) -> &'static str {
+        match self {
+            Interpreter::Python => {
+                "print('\\n'.join('%s=%s' % item for item in sorted(__import__('os').environ.items())))"
+            }
+            Interpreter::Node => {
+                "console.log(Object.keys(process.env).sort().map(name => name + '=' + process.env[name]).join('\\n'))"
+            }
+            _ => "env | sort",
+        }
+    }
+
+    /// 切换当前目录的代码
+    pub(super) fn cd_command(self, path: &str) -> String {
+        match self {
+            Interpreter::Python => format!("__import__('os').chdir({})", quote(path)),
+            Interpreter::Node => format!("process.chdir({})", quote(path)),
+            _ => format!("cd {}", shell_escape::unix::escape(path.into())),
+        }
+    }
+
+    /// 检查解释器能否作为会话的 shell
+    pub fn validate_shell(self) -> Result<(), ToolError> {
+        if self.is_shell() {
+            Ok(())
+        } else {
+            Err(ToolError::new(format!("{} 不是 shell，只能作为 REPL 工具使用", self.name())))
+        }
+    }
+}
+
+/// 字符串字面量，JSON 字符串同时是合法的 Python 和 JavaScript 字符串
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
@@ -0,0 +1,417 @@
+use super::process;
+use crate::tools::base::{file_name_component, ToolError, ToolResult};
+use log::{info, warn};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+const JOB_LOG_DIR: &str = "maestro-jobs";
+const RING_BUFFER_SIZE: usize = 256 * 1024; // 每个任务在内存中保留最近 256KB 输出
+const MAX_CHUNK_SIZE: usize = 64 * 1024; // 单次 job_output 最多返回 64KB
+
+/// 后台任务的输出缓冲区
+///
+/// 内存中只保留最近的 `RING_BUFFER_SIZE` 字节，完整输出同时写入日志文件。
+/// 偏移量是相对于任务全部输出的绝对字节位置。
+struct JobOutput {
+    /// 最近的输出
+    buffer: VecDeque<u8>,
+    /// 缓冲区第一个字节对应的绝对偏移量
+    start_offset: u64,
+    /// 日志文件
+    log_file: Option<File>,
+    /// 日志写入失败时已写入日志的输出长度，之后的输出只保留在内存中
+    log_end: Option<u64>,
+}
+
+impl JobOutput {
+    fn append(&mut self, data: &[u8]) {
+        if let Some(file) = self.log_file.as_mut() {
+            if let Err(e) = file.write_all(data) {
+                warn!("写入后台任务日志失败: {}", e);
+                self.log_file = None;
+                // 写入失败的这部分可能只写入了一半，从它的起点开始视为丢失
+                self.log_end = Some(self.end_offset());
+            }
+        }
+
+        self.buffer.extend(data);
+        let overflow = self.buffer.len().saturating_sub(RING_BUFFER_SIZE);
+        if overflow > 0 {
+            self.buffer.drain(..overflow);
+            self.start_offset += overflow as u64;
+        }
+    }
+
+    fn end_offset(&self) -> u64 {
+        self.start_offset + self.buffer.len() as u64
+    }
+
+    /// 已从内存中挤出、又没有写入日志而丢失的输出范围
+    fn lost_range(&self) -> Option<(u64, u64)> {
+        self.log_end
+            .filter(|log_end| *log_end < self.start_offset)
+            .map(|log_end| (log_end, self.start_offset))
+    }
+}
+
+/// 后台任务的运行状态
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    /// 正在运行
+    Running,
+    /// 已退出，包含退出码（被信号终止时为 None）
+    Exited(Option<i32>),
+    /// 已被 job_kill 终止
+    Killed,
+}
+
+/// 一个后台任务
+struct Job {
+    command: String,
+    /// 任务进程，被终止后为 None
+    child: Option<Child>,
+    output: Arc<Mutex<JobOutput>>,
+    log_path: PathBuf,
+    started_at: Instant,
+    state: JobState,
+}
+
+impl Job {
+    /// 刷新并返回任务状态
+    fn poll(&mut self) -> JobState {
+        if let (JobState::Running, Some(child)) = (&self.state, self.child.as_mut()) {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    process::release(child.id());
+                    self.state = JobState::Exited(status.code());
+                }
+                Ok(None) => {}
+                Err(e) => warn!("检查后台任务状态失败: {}", e),
+            }
+        }
+        self.state.clone()
+    }
+
+    /// 终止任务所在的整个进程组
+    fn terminate(&mut self) {
+        if let Some(child) = self.child.take() {
+            process::terminate(child);
+        }
+        self.state = JobState::Killed;
+    }
+
+    fn describe_state(&mut self) -> String {
+        match self.poll() {
+            JobState::Running => format!("运行中（已运行 {} 秒）", self.started_at.elapsed().as_secs()),
+            JobState::Exited(Some(code)) => format!("已退出，退出码为 {}", code),
+            JobState::Exited(None) => "已被信号终止".to_string(),
+            JobState::Killed => "已被终止".to_string(),
+        }
+    }
+}
+
+/// 管理一个Bash会话下的所有后台任务
+pub struct JobManager {
+    /// 会话标识，用于区分日志文件
+    session_id: String,
+    /// 下一个任务编号
+    next_id: u32,
+    jobs: HashMap<String, Job>,
+}
+
+impl JobManager {
+    pub fn new(session_id: impl Into<String>) -> Self {
+        Self {
+            session_id: session_id.into(),
+            next_id: 1,
+            jobs: HashMap::new(),
+        }
+    }
+
+    /// 用准备好的 `process` 在后台执行 `command`，返回任务ID
+    ///
+    /// 提供 `input` 时把它作为一行写入任务的标准输入后关闭，否则标准输入为空。
+    pub fn spawn(&mut self, command: &str, mut process: Command, input: Option<String>) -> Result<ToolResult, ToolError> {
+        let job_id = format!("job_{}", self.next_id);
+        self.next_id += 1;
+
+        let log_dir = std::env::temp_dir().join(JOB_LOG_DIR);
+        fs::create_dir_all(&log_dir)
+            .map_err(|e| ToolError::new(format!("创建任务日志目录失败: {}", e)))?;
+        let log_path = log_dir.join(format!("{}-{}.log", file_name_component(&self.session_id), job_id));
+        let log_file = File::create(&log_path)
+            .map_err(|e| ToolError::new(format!("创建任务日志文件 {} 失败: {}", log_path.display(), e)))?;
+
+        // 任务运行在独立的进程组中，终止时连同它启动的进程一起终止
+        process::new_process_group(&mut process);
+        let mut child = process
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| ToolError::new(format!("启动后台任务失败: {}", e)))?;
+        process::register(&child);
+
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            // 写完后关闭标准输入；写入失败时任务会因读不到设置脚本而在输出中报告错误
+            thread::spawn(move || {
+                if let Err(e) = writeln!(stdin, "{}", input) {
+                    warn!("写入后台任务的标准输入失败: {}", e);
+                }
+            });
+        }
+
+        let output = Arc::new(Mutex::new(JobOutput {
+            buffer: VecDeque::new(),
+            start_offset: 0,
+            log_file: Some(log_file),
+            log_end: None,
+        }));
+
+        // stdout 和 stderr 写入同一个缓冲区，与 2>&1 的效果一致
+        if let Some(stdout) = child.stdout.take() {
+            spawn_reader(stdout, Arc::clone(&output));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            spawn_reader(stderr, Arc::clone(&output));
+        }
+
+        let pid = child.id();
+        info!("后台任务 {} 已启动 (pid {})", job_id, pid);
+
+        self.jobs.insert(
+            job_id.clone(),
+            Job {
+                command: command.to_string(),
+                child: Some(child),
+                output,
+                log_path: log_path.clone(),
+                started_at: Instant::now(),
+                state: JobState::Running,
+            },
+        );
+
+        Ok(ToolResult::success(format!(
+            "后台任务已启动: {}（pid {}）\n日志文件: {}\n使用 job_status、job_output 和 job_kill 管理该任务",
+            job_id,
+            pid,
+            log_path.display()
+        )))
+    }
+
+    /// 查询任务状态；未指定任务ID时列出所有任务
+    pub fn status(&mut self, job_id: Option<&str>) -> Result<ToolResult, ToolError> {
+        if let Some(job_id) = job_id {
+            let job = self.get_mut(job_id)?;
+            let (end_offset, lost) = {
+                let output = job.output.lock().unwrap();
+                (output.end_offset(), output.lost_range())
+            };
+            let mut text = format!(
+                "{}: {}\n命令: {}\n输出长度: {} 字节\n日志文件: {}",
+                job_id,
+                job.describe_state(),
+                job.command,
+                end_offset,
+                job.log_path.display()
+            );
+            if let Some((start, end)) = lost {
+                text.push_str(&format!("\n日志写入失败，偏移 {} 到 {} 的输出已丢失", start, end));
+            }
+            return Ok(ToolResult::success(text));
+        }
+
+        if self.jobs.is_empty() {
+            return Ok(ToolResult::success("当前会话没有后台任务"));
+        }
+
+        let mut ids: Vec<String> = self.jobs.keys().cloned().collect();
+        ids.sort_by_key(|id| id.trim_start_matches("job_").parse::<u32>().unwrap_or(0));
+
+        let lines: Vec<String> = ids
+            .iter()
+            .map(|id| {
+                let job = self.jobs.get_mut(id).unwrap();
+                format!("{}: {} - {}", id, job.describe_state(), job.command)
+            })
+            .collect();
+
+        Ok(ToolResult::success(lines.join("\n")))
+    }
+
+    /// 读取任务从 `since_offset` 开始的输出
+    pub fn output(&mut self, job_id: &str, since_offset: u64) -> Result<ToolResult, ToolError> {
+        let job = self.get_mut(job_id)?;
+        let state = job.describe_state();
+        let running = job.state == JobState::Running;
+
+        let mut notice = None;
+        let (mut data, since_offset, end_offset) = {
+            let output = job.output.lock().unwrap();
+            let end_offset = output.end_offset();
+            let mut since_offset = since_offset.min(end_offset);
+
+            // 日志写入失败后被挤出缓冲区的输出无法找回，跳过这一段并说明
+            if let Some((lost_start, lost_end)) = output.lost_range() {
+                if (lost_start..lost_end).contains(&since_offset) {
+                    notice = Some(format!("日志写入失败，偏移 {} 到 {} 的输出已丢失", since_offset, lost_end));
+                    since_offset = lost_end;
+                }
+            }
+
+            if since_offset >= output.start_offset {
+                // 请求的数据仍在内存缓冲区中
+                let skip = (since_offset - output.start_offset) as usize;
+                let data: Vec<u8> = output.buffer.iter().skip(skip).take(MAX_CHUNK_SIZE).copied().collect();
+                (data, since_offset, end_offset)
+            } else {
+                // 已被挤出缓冲区，从日志文件中读取，不超过日志实际写入的范围
+                let max_len = match output.log_end {
+                    Some(log_end) => (log_end.saturating_sub(since_offset) as usize).min(MAX_CHUNK_SIZE),
+                    None => MAX_CHUNK_SIZE,
+                };
+                drop(output);
+                let data = read_log_range(&job.log_path, since_offset, max_len)?;
+                (data, since_offset, end_offset)
+            }
+        };
+
+        // 不在多字节字符中间截断，剩余的字节留到下一次读取；任务已结束时最后的不完整字符按原样返回
+        let reaches_end = since_offset + data.len() as u64 >= end_offset;
+        if running || !reaches_end {
+            data.truncate(char_boundary(&data));
+        }
+        let next_offset = since_offset + data.len() as u64;
+
+        let text = String::from_utf8_lossy(&data);
+        let mut system = format!("{}: {}，next_offset: {}", job_id, state, next_offset);
+        if let Some(notice) = notice {
+            system = format!("{}\n{}", notice, system);
+        }
+        Ok(ToolResult {
+            output: if text.is_empty() { None } else { Some(text.into_owned()) },
+            error: None,
+            base64_image: None,
+            system: Some(system),
+            ..Default::default()
+        })
+    }
+
+    /// 终止任务
+    pub fn kill(&mut self, job_id: &str) -> Result<ToolResult, ToolError> {
+        let job = self.get_mut(job_id)?;
+        if job.poll() != JobState::Running {
+            return Ok(ToolResult::success(format!("{}: {}", job_id, job.describe_state())));
+        }
+
+        job.terminate();
+        info!("后台任务 {} 已终止", job_id);
+
+        Ok(ToolResult::success(format!("后台任务 {} 已终止", job_id)))
+    }
+
+    /// 终止所有仍在运行的任务并清空列表
+    pub fn kill_all(&mut self) {
+        for (job_id, mut job) in self.jobs.drain() {
+            if job.poll() == JobState::Running {
+                job.terminate();
+                info!("会话重启，后台任务 {} 已终止", job_id);
+            }
+        }
+    }
+
+    fn get_mut(&mut self, job_id: &str) -> Result<&mut Job, ToolError> {
+        self.jobs
+            .get_mut(job_id)
+            .ok_or_else(|| ToolError::new(format!("未找到后台任务: {}", job_id)))
+    }
+}
+
+impl Drop for JobManager {
+    fn drop(&mut self) {
+        self.kill_all();
+    }
+}
+
+/// 启动线程持续读取子进程输出
+fn spawn_reader(mut source: impl Read + Send + 'static, output: Arc<Mutex<JobOutput>>) {
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match source.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => output.lock().unwrap().append(&buf[..n]),
+            }
+        }
+    });
+}
+
+/// 数据末尾不完整的 UTF-8 字符的起始位置，末尾完整时返回数据长度
+fn char_boundary(data: &[u8]) -> usize {
+    for back in 1..=data.len().min(4) {
+        let index = data.len() - back;
+        let width = match data[index] {
+            // 后续字节，继续向前查找首字节
+            0x80..=0xBF => continue,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if back < width { index } else { data.len() };
+    }
+    data.len()
+}
+
+/// 从日志文件中读取指定范围的输出
+fn read_log_range(path: &Path, offset: u64, max_len: usize) -> Result<Vec<u8>, ToolError> {
+    let mut file = File::open(path)
+        .map_err(|e| ToolError::new(format!("打开任务日志文件 {} 失败: {}", path.display(), e)))?;
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| ToolError::new(format!("读取任务日志文件失败: {}", e)))?;
+
+    let mut data = Vec::with_capacity(max_len);
+    file.take(max_len as u64)
+        .read_to_end(&mut data)
+        .map_err(|e| ToolError::new(format!("读取任务日志文件失败: {}", e)))?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn char_boundary_keeps_incomplete_character() {
+        let text = "日志".as_bytes();
+        assert_eq!(char_boundary(text), text.len());
+        assert_eq!(char_boundary(&text[..4]), 3);
+        assert_eq!(char_boundary(&text[..5]), 3);
+        assert_eq!(char_boundary(&text[..2]), 0);
+        assert_eq!(char_boundary(b"abc"), 3);
+        // 无效字节不属于不完整的字符
+        assert_eq!(char_boundary(b"ab\x80\x80\x80\x80"), 6);
+    }
+
+    #[test]
+    fn lost_range_after_log_failure() {
+        let mut output = JobOutput {
+            buffer: VecDeque::new(),
+            start_offset: 0,
+            log_file: None,
+            log_end: Some(10),
+        };
+        output.append(&[b'x'; 20]);
+        assert_eq!(output.lost_range(), None);
+        output.append(&vec![b'y'; RING_BUFFER_SIZE]);
+        assert_eq!(output.lost_range(), Some((10, 20)));
+    }
+}
@@ -0,0 +1,446 @@
+use crate::tools::base::{LimitHit, LimitKind, ToolError};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// 等待 systemd 创建 scope 单元的时间
+const SCOPE_START_TIMEOUT: Duration = Duration::from_secs(3);
+/// 命令期间累计被节流超过这个时间（微秒）才报告触发了CPU配额
+const THROTTLE_REPORT_USEC: u64 = 1_000_000;
+/// 进程因信号退出时，bash报告的退出码为 128 + 信号值（Linux 和 macOS 相同）
+const EXIT_SIGXCPU: i32 = 128 + 24;
+const EXIT_SIGXFSZ: i32 = 128 + 25;
+const MB: u64 = 1024 * 1024;
+
+/// bash进程及其子进程的资源限制
+///
+/// 前五项通过 rlimit 在启动进程时设置，对每个进程单独生效；
+/// `memory_mb` 和 `cpu_percent` 通过 cgroup v2 对整个会话生效，
+/// 需要 systemd 用户实例为会话创建临时的 scope 单元。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ResourceLimits {
+    /// 每个进程可使用的CPU时间（秒）
+    pub cpu_seconds: Option<u64>,
+    /// 每个进程的虚拟地址空间上限（MB）
+    pub address_space_mb: Option<u64>,
+    /// 可写入的单个文件大小上限（MB）
+    pub file_size_mb: Option<u64>,
+    /// 每个进程可同时打开的文件数
+    pub open_files: Option<u64>,
+    /// 进程数上限，按当前用户的所有进程计算
+    pub processes: Option<u64>,
+    /// 整个会话的内存上限（MB）
+    pub memory_mb: Option<u64>,
+    /// 整个会话的CPU配额，100 表示一个核心
+    pub cpu_percent: Option<u32>,
+}
+
+impl ResourceLimits {
+    /// 是否需要为会话创建cgroup
+    pub fn needs_cgroup(&self) -> bool {
+        self.memory_mb.is_some() || self.cpu_percent.is_some()
+    }
+
+    /// 在子进程执行前设置 rlimit，并在提供了 cgroup 时把子进程移入其中
+    #[cfg(unix)]
+    pub(super) fn apply_to_command(&self, command: &mut Command, cgroup: Option<&SessionCgroup>) {
+        use std::{io, os::unix::io::AsRawFd, os::unix::process::CommandExt};
+
+        let rlimits: Vec<_> = [
+            (libc::RLIMIT_CPU, self.cpu_seconds),
+            (libc::RLIMIT_AS, self.address_space_mb.map(|mb| mb * MB)),
+            (libc::RLIMIT_FSIZE, self.file_size_mb.map(|mb| mb * MB)),
+            (libc::RLIMIT_NOFILE, self.open_files),
+            (libc::RLIMIT_NPROC, self.processes),
+        ]
+        .into_iter()
+        .filter_map(|(resource, value)| value.map(|value| (resource, value as libc::rlim_t)))
+        .collect();
+        let procs_fd = cgroup.map(|cgroup| cgroup.procs.as_raw_fd());
+
+        if rlimits.is_empty() && procs_fd.is_none() {
+            return;
+        }
+
+        // pre_exec 在 fork 之后、exec 之前运行，这里只调用异步信号安全的系统调用
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(fd) = procs_fd {
+                    // 向 cgroup.procs 写入 0 表示移动写入者自身
+                    if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for &(resource, value) in &rlimits {
+                    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+                    if libc::getrlimit(resource, &mut limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    // 硬限制只能降低，同时设为接近的值防止命令自行调高；
+                    // CPU时间的硬限制多留一秒，让进程先收到 SIGXCPU 而不是直接被 SIGKILL
+                    let value = value.min(limit.rlim_max);
+                    limit.rlim_cur = value;
+                    if resource == libc::RLIMIT_CPU {
+                        limit.rlim_max = limit.rlim_max.min(value.saturating_add(1));
+                    } else {
+                        limit.rlim_max = value;
+                    }
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(super) fn apply_to_command(&self, _command: &mut Command, _cgroup: Option<&SessionCgroup>) {}
+
+    /// 在shell中设置 rlimit 的命令，用于远程执行目标
+    ///
+    /// 先设置软限制再降低硬限制；目标上原有的限制更严格时保持不变。
+    /// bash 的 `ulimit -v` 和 `-f` 以 KB 为单位。
+    pub(super) fn ulimit_script(&self) -> String {
+        [
+            ('t', self.cpu_seconds),
+            ('v', self.address_space_mb.map(|mb| mb * 1024)),
+            ('f', self.file_size_mb.map(|mb| mb * 1024)),
+            ('n', self.open_files),
+            ('u', self.processes),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| {
+            let value = value?;
+            // 与本地一致，CPU时间的硬限制多留一秒
+            let hard = if flag == 't' { value + 1 } else { value };
+            Some(format!(
+                "ulimit -S -{} {} 2>/dev/null; ulimit -H -{} {} 2>/dev/null",
+                flag, value, flag, hard
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    /// 根据退出码判断命令是否被 rlimit 的信号终止
+    ///
+    /// 只有CPU时间和文件大小限制由内核以信号报告，其余 rlimit 表现为系统调用失败，
+    /// 无法与命令自身的错误可靠区分，因此不报告。
+    pub(super) fn detect_hits(&self, exit_code: i32) -> Vec<LimitHit> {
+        let checks = [
+            (
+                LimitKind::CpuTime,
+                self.cpu_seconds,
+                exit_code == EXIT_SIGXCPU,
+                "超出CPU时间限制（{}秒），进程被终止",
+            ),
+            (
+                LimitKind::FileSize,
+                self.file_size_mb,
+                exit_code == EXIT_SIGXFSZ,
+                "超出文件大小限制（{}MB），写入被终止",
+            ),
+        ];
+
+        checks
+            .into_iter()
+            .filter_map(|(kind, limit, hit, template)| match (limit, hit) {
+                (Some(limit), true) => Some(LimitHit {
+                    kind,
+                    limit,
+                    message: template.replace("{}", &limit.to_string()),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// cgroup 中与限制相关的计数
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct CgroupCounters {
+    oom_kill: u64,
+    throttled_usec: u64,
+}
+
+/// 会话专用的 cgroup，由 systemd 用户实例中的临时 scope 单元提供
+///
+/// 不修改应用自身所在的 cgroup；析构时停止 scope，终止其中剩余的进程。
+pub(super) struct SessionCgroup {
+    /// scope 单元名称
+    unit: String,
+    path: PathBuf,
+    /// 预先打开的 cgroup.procs，子进程在 exec 之前通过它把自己移入cgroup
+    procs: fs::File,
+    /// 让 scope 在会话没有进程时也保持存在的占位进程
+    keeper: Child,
+}
+
+impl SessionCgroup {
+    /// 通过 `systemd-run --user --scope` 为会话创建 scope 单元
+    pub fn create(session_id: &str, limits: &ResourceLimits) -> Result<Self, ToolError> {
+        if !cfg!(target_os = "linux") {
+            return Err(ToolError::new("cgroup 仅支持 Linux"));
+        }
+        if !Path::new(CGROUP_ROOT).join("cgroup.controllers").is_file() {
+            return Err(ToolError::new("系统未使用 cgroup v2"));
+        }
+        let name: String = session_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let unit = format!("maestro-{}-{}.scope", name, &Uuid::new_v4().simple().to_string()[..8]);
+
+        let mut keeper = Command::new("systemd-run")
+            .args(["--user", "--scope", "--quiet", "--collect", "--unit", &unit])
+            .args(scope_properties(limits).iter().flat_map(|property| ["-p", property.as_str()]))
+            .args(["--", "sleep", "infinity"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| ToolError::new(format!("启动 systemd-run 失败: {}", e)))?;
+
+        let path = match wait_for_scope(&mut keeper, &unit) {
+            Ok(path) => path,
+            Err(e) => {
+                let _ = keeper.kill();
+                let _ = keeper.wait();
+                return Err(e);
+            }
+        };
+        let procs = fs::OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"))
+            .map_err(|e| ToolError::new(format!("打开 cgroup.procs 失败: {}", e)));
+
+        let cgroup = match procs {
+            Ok(procs) => Self { unit, path, procs, keeper },
+            Err(e) => {
+                // 还没有构造出 SessionCgroup，需要单独停止 scope
+                stop_scope(&unit, &mut keeper);
+                return Err(e);
+            }
+        };
+        cgroup.check_migration()?;
+        info!("已创建会话 cgroup: {} ({})", cgroup.unit, cgroup.path.display());
+        Ok(cgroup)
+    }
+
+    /// 确认子进程可以把自己移入 scope，否则shell会无法启动
+    #[cfg(unix)]
+    fn check_migration(&self) -> Result<(), ToolError> {
+        let mut probe = Command::new("true");
+        ResourceLimits::default().apply_to_command(&mut probe, Some(self));
+        match probe.status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(ToolError::new(format!("无法把进程移入 {}: {}", self.unit, status))),
+            Err(e) => Err(ToolError::new(format!("无法把进程移入 {}: {}", self.unit, e))),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn check_migration(&self) -> Result<(), ToolError> {
+        Ok(())
+    }
+
+    /// 更新 scope 的内存和CPU限制，未配置的项设为不限制
+    pub fn update(&self, limits: &ResourceLimits) -> Result<(), ToolError> {
+        let output = Command::new("systemctl")
+            .args(["--user", "set-property", "--runtime", &self.unit])
+            .args(scope_properties(limits))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| ToolError::new(format!("执行 systemctl 失败: {}", e)))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(ToolError::new(format!(
+                "更新 {} 的资源限制失败: {}",
+                self.unit,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+
+    /// 读取当前的 OOM 终止次数和 CPU 节流时间
+    pub fn counters(&self) -> CgroupCounters {
+        CgroupCounters {
+            oom_kill: read_key(&self.path.join("memory.events"), "oom_kill"),
+            throttled_usec: read_key(&self.path.join("cpu.stat"), "throttled_usec"),
+        }
+    }
+
+    /// 比较前后计数，返回期间触发的限制
+    pub fn detect_hits(&self, before: CgroupCounters, limits: &ResourceLimits) -> Vec<LimitHit> {
+        let after = self.counters();
+        let mut hits = Vec::new();
+
+        if let Some(limit) = limits.memory_mb {
+            if after.oom_kill > before.oom_kill {
+                hits.push(LimitHit {
+                    kind: LimitKind::Memory,
+                    limit,
+                    message: format!(
+                        "超出会话内存上限（{}MB），{} 个进程被终止",
+                        limit,
+                        after.oom_kill - before.oom_kill
+                    ),
+                });
+            }
+        }
+        if let Some(limit) = limits.cpu_percent {
+            let throttled = after.throttled_usec.saturating_sub(before.throttled_usec);
+            if throttled >= THROTTLE_REPORT_USEC {
+                hits.push(LimitHit {
+                    kind: LimitKind::CpuQuota,
+                    limit: u64::from(limit),
+                    message: format!(
+                        "命令受到会话CPU配额（{}%）限制，累计被节流 {:.1} 秒",
+                        limit,
+                        throttled as f64 / 1_000_000.0
+                    ),
+                });
+            }
+        }
+        hits
+    }
+}
+
+impl Drop for SessionCgroup {
+    fn drop(&mut self) {
+        stop_scope(&self.unit, &mut self.keeper);
+    }
+}
+
+/// 与资源限制对应的 scope 单元属性，空值表示恢复为不限制
+fn scope_properties(limits: &ResourceLimits) -> Vec<String> {
+    let memory = limits.memory_mb.map_or_else(|| "infinity".to_string(), |mb| (mb * MB).to_string());
+    // 不允许使用交换空间，否则内存上限只会让命令变慢而不会被报告
+    let swap = if limits.memory_mb.is_some() { "0" } else { "infinity" };
+    let cpu = limits.cpu_percent.map_or_else(String::new, |percent| format!("{}%", percent.max(1)));
+    vec![
+        format!("MemoryMax={}", memory),
+        format!("MemorySwapMax={}", swap),
+        format!("CPUQuota={}", cpu),
+    ]
+}
+
+/// 等待占位进程进入 scope，返回 scope 的 cgroup 路径
+///
+/// `systemd-run --scope` 在 scope 创建后才执行命令，因此进程所在的 cgroup 变为 scope 时即已就绪。
+fn wait_for_scope(keeper: &mut Child, unit: &str) -> Result<PathBuf, ToolError> {
+    let started = Instant::now();
+    let suffix = format!("/{}", unit);
+    loop {
+        if let Some(status) = keeper
+            .try_wait()
+            .map_err(|e| ToolError::new(format!("等待 systemd-run 失败: {}", e)))?
+        {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = keeper.stderr.take() {
+                let _ = std::io::Read::read_to_string(&mut pipe, &mut stderr);
+            }
+            return Err(ToolError::new(format!(
+                "systemd-run 创建 scope 失败（{}）: {}",
+                status,
+                stderr.trim()
+            )));
+        }
+        let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", keeper.id())).unwrap_or_default();
+        if let Some(path) = cgroup
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .map(str::trim)
+            .filter(|path| path.ends_with(&suffix))
+        {
+            return Ok(Path::new(CGROUP_ROOT).join(path.trim_start_matches('/')));
+        }
+        if started.elapsed() > SCOPE_START_TIMEOUT {
+            return Err(ToolError::new(format!("等待 scope {} 创建超时", unit)));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// 停止 scope 单元，systemd 会终止其中剩余的所有进程
+fn stop_scope(unit: &str, keeper: &mut Child) {
+    let stopped = Command::new("systemctl")
+        .args(["--user", "stop", "--no-block", unit])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if !matches!(stopped, Ok(status) if status.success()) {
+        warn!("停止 scope {} 失败", unit);
+    }
+    let _ = keeper.kill();
+    let _ = keeper.wait();
+}
+
+/// 读取 `key value` 格式文件中的计数
+fn read_key(path: &Path, key: &str) -> u64 {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(' ')?;
+            if name == key {
+                value.trim().parse().ok()
+            } else {
+                None
+            }
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_hits_uses_signal_status_only() {
+        let limits = ResourceLimits {
+            cpu_seconds: Some(2),
+            file_size_mb: Some(1),
+            open_files: Some(64),
+            ..Default::default()
+        };
+        let kinds = |exit_code| limits.detect_hits(exit_code).into_iter().map(|hit| hit.kind).collect::<Vec<_>>();
+        assert_eq!(kinds(EXIT_SIGXCPU), vec![LimitKind::CpuTime]);
+        assert_eq!(kinds(EXIT_SIGXFSZ), vec![LimitKind::FileSize]);
+        assert!(kinds(1).is_empty());
+        assert!(ResourceLimits::default().detect_hits(EXIT_SIGXCPU).is_empty());
+    }
+
+    #[test]
+    fn scope_properties_reset_unset_limits() {
+        let limits = ResourceLimits {
+            memory_mb: Some(256),
+            ..Default::default()
+        };
+        assert_eq!(scope_properties(&limits), vec![
+            format!("MemoryMax={}", 256 * MB),
+            "MemorySwapMax=0".to_string(),
+            "CPUQuota=".to_string(),
+        ]);
+        let limits = ResourceLimits {
+            cpu_percent: Some(150),
+            ..Default::default()
+        };
+        assert_eq!(scope_properties(&limits), vec![
+            "MemoryMax=infinity".to_string(),
+            "MemorySwapMax=infinity".to_string(),
+            "CPUQuota=150%".to_string(),
+        ]);
+    }
+}
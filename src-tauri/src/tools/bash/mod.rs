@@ -0,0 +1,1263 @@
+mod changes;
+mod env;
+mod history;
+mod interpreter;
+mod jobs;
+mod limits;
+mod policy;
+mod process;
+mod sandbox;
+mod session;
+mod state;
+mod terminal;
+
+pub use crate::tools::base::{ChangeKind, FileChange, LimitHit, LimitKind, PolicyAction, PolicyVerdict};
+pub use env::{EnvProfile, EnvVar};
+pub use history::{CommandHistory, HistoryEntry, HistoryQuery, DATABASE_FILE};
+pub use interpreter::Interpreter;
+pub use limits::ResourceLimits;
+pub use policy::{CommandPolicy, PolicyRule};
+pub use process::{terminate_all_processes, CommandSignal};
+pub use sandbox::{SandboxConfig, SandboxMode};
+pub use state::{SessionState, SessionStateStore};
+
+use crate::tools::{
+    base::{ToolError, ToolResult},
+    edit_history::{EditHistory, NewEdit},
+    target::ExecutionTarget,
+};
+use base64::{engine::general_purpose, Engine as _};
+use changes::{ChangeTracker, TrackedChange};
+use chrono::{DateTime, Utc};
+use env::SessionEnv;
+use history::NewEntry;
+use jobs::JobManager;
+use limits::{CgroupCounters, SessionCgroup};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use session::{BashSession, PartialOutput, RunStatus};
+use std::{
+    path::PathBuf,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+const TIMEOUT_SECONDS: u64 = 30; // 减少超时时间
+/// 命令没有输出并在等待输入多少秒后提前返回
+const IDLE_SECONDS: u64 = 5;
+/// 发送信号后等待命令结束的时间
+const SIGNAL_WAIT: Duration = Duration::from_secs(2);
+/// 从命令的期限中留给记录会话状态的时间
+const STATE_CAPTURE_TIME: Duration = Duration::from_secs(5);
+/// 远程目标上先从标准输入读取一行 base64 编码的设置脚本并执行，再执行参数中的程序，
+/// 使环境变量的值不出现在远程进程的命令行参数中
+const REMOTE_SETUP_WRAPPER: &str =
+    r#"IFS= read -r setup; eval "$(printf '%s' "$setup" | base64 --decode)"; exec "$@""#;
+/// 最多保留多少条等待用户批准的命令，超出时丢弃最早的
+const MAX_PENDING_APPROVALS: usize = 16;
+
+/// Bash工具支持的操作
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BashAction {
+    /// 在会话中执行命令（run_in_background 为 true 时在后台执行）
+    #[default]
+    Run,
+    /// 查询后台任务状态
+    JobStatus,
+    /// 读取后台任务输出
+    JobOutput,
+    /// 终止后台任务
+    JobKill,
+    /// 设置环境变量
+    EnvSet,
+    /// 移除环境变量
+    EnvUnset,
+    /// 列出环境变量
+    EnvList,
+    /// 向正在执行的命令的标准输入写入内容
+    Stdin,
+    /// 向正在执行的命令发送信号
+    Signal,
+}
+
+/// 需要批准的操作
+#[derive(Debug, Clone, Copy)]
+enum ApprovalKind {
+    /// 在会话中执行命令
+    Run { idle_timeout: Option<u64> },
+    /// 在后台执行命令
+    Background,
+    /// 写入正在执行的 shell 的标准输入
+    Stdin { idle_timeout: Option<u64> },
+}
+
+/// 等待用户批准的命令
+#[derive(Debug, Clone)]
+struct PendingApproval {
+    /// 批准令牌，只交给界面
+    token: String,
+    command: String,
+    kind: ApprovalKind,
+}
+
+/// Bash工具，用于执行系统命令
+///
+/// 同一套会话机制也用于 Python 和 Node.js REPL，见 `BashTool::repl`。
+pub struct BashTool {
+    /// 会话状态，用于保持命令执行的上下文
+    session: Arc<Mutex<Option<BashSession>>>,
+    /// 会话中运行的解释器，shell 可以在 bash、sh 和 zsh 之间切换
+    interpreter: Arc<Mutex<Interpreter>>,
+    /// 会话下的后台任务
+    jobs: Arc<Mutex<JobManager>>,
+    /// 由工具管理的环境变量
+    env: Arc<Mutex<SessionEnv>>,
+    /// 工作区根目录，新的shell从这里启动
+    workspace_root: Arc<Mutex<Option<PathBuf>>>,
+    /// 最近一条命令执行后shell所在的目录
+    cwd: Arc<Mutex<Option<PathBuf>>>,
+    /// 沙箱配置
+    sandbox: Arc<Mutex<SandboxConfig>>,
+    /// 命令策略
+    policy: Arc<Mutex<CommandPolicy>>,
+    /// 资源限制
+    limits: Arc<Mutex<ResourceLimits>>,
+    /// 会话专用的cgroup，仅在配置了会话级限制且系统支持时创建
+    cgroup: Arc<Mutex<Option<SessionCgroup>>>,
+    /// 命令执行目标
+    target: Arc<Mutex<ExecutionTarget>>,
+    /// 是否跟踪命令在工作区中造成的文件变更
+    track_changes: Arc<Mutex<bool>>,
+    /// 工作区快照，跟踪文件变更时创建
+    tracker: Arc<Mutex<Option<ChangeTracker>>>,
+    /// 编辑历史，与同一会话的编辑工具共用
+    edit_history: Arc<EditHistory>,
+    /// 当前的工具调用，记录在编辑历史中
+    tool_use_id: Arc<Mutex<Option<String>>>,
+    /// 会话标识
+    session_id: String,
+    /// 命令历史，未设置时不记录
+    history: Option<Arc<CommandHistory>>,
+    /// 会话状态存储，未设置时不保存和恢复shell状态
+    state_store: Option<Arc<SessionStateStore>>,
+    /// 是否还需要恢复上次保存的会话状态，只在第一次启动shell时恢复
+    restore_pending: Arc<Mutex<bool>>,
+    /// 等待用户批准的命令，按提出的先后排列
+    approvals: Arc<Mutex<Vec<PendingApproval>>>,
+}
+
+impl BashTool {
+    /// 创建一个新的Bash工具实例
+    pub fn new() -> Self {
+        Self::with_session("default")
+    }
+
+    /// 创建一个绑定到指定会话标识的Bash工具实例
+    pub fn with_session(session_id: impl Into<String>) -> Self {
+        let session_id = session_id.into();
+        Self {
+            session: Arc::new(Mutex::new(None)),
+            interpreter: Arc::new(Mutex::new(Interpreter::default())),
+            jobs: Arc::new(Mutex::new(JobManager::new(session_id.clone()))),
+            env: Arc::new(Mutex::new(SessionEnv::default())),
+            workspace_root: Arc::new(Mutex::new(None)),
+            cwd: Arc::new(Mutex::new(None)),
+            sandbox: Arc::new(Mutex::new(SandboxConfig::default())),
+            policy: Arc::new(Mutex::new(CommandPolicy::default())),
+            limits: Arc::new(Mutex::new(ResourceLimits::default())),
+            cgroup: Arc::new(Mutex::new(None)),
+            target: Arc::new(Mutex::new(ExecutionTarget::default())),
+            track_changes: Arc::new(Mutex::new(false)),
+            tracker: Arc::new(Mutex::new(None)),
+            edit_history: Arc::new(EditHistory::new()),
+            tool_use_id: Arc::new(Mutex::new(None)),
+            session_id,
+            history: None,
+            state_store: None,
+            restore_pending: Arc::new(Mutex::new(true)),
+            approvals: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// 创建同一会话的 REPL 工具
+    ///
+    /// REPL 与当前工具共用环境变量、工作区根目录、沙箱、资源限制、执行目标和编辑历史，
+    /// 但有自己的解释器进程，不记录命令历史，也不保存会话状态。
+    pub fn repl(&self, interpreter: Interpreter) -> Result<Self, ToolError> {
+        if interpreter.is_shell() {
+            return Err(ToolError::new(format!("{} 是 shell，请使用Bash工具", interpreter.name())));
+        }
+        Ok(Self {
+            session: Arc::new(Mutex::new(None)),
+            interpreter: Arc::new(Mutex::new(interpreter)),
+            jobs: Arc::new(Mutex::new(JobManager::new(self.session_id.clone()))),
+            env: Arc::clone(&self.env),
+            workspace_root: Arc::clone(&self.workspace_root),
+            cwd: Arc::new(Mutex::new(None)),
+            sandbox: Arc::clone(&self.sandbox),
+            policy: Arc::clone(&self.policy),
+            limits: Arc::clone(&self.limits),
+            cgroup: Arc::clone(&self.cgroup),
+            target: Arc::clone(&self.target),
+            track_changes: Arc::clone(&self.track_changes),
+            tracker: Arc::new(Mutex::new(None)),
+            edit_history: Arc::clone(&self.edit_history),
+            tool_use_id: Arc::new(Mutex::new(None)),
+            session_id: self.session_id.clone(),
+            history: None,
+            state_store: None,
+            restore_pending: Arc::new(Mutex::new(false)),
+            approvals: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// 把执行的命令记录到命令历史中
+    pub fn with_history(mut self, history: Arc<CommandHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// 在每条命令执行后保存shell状态，并在应用重启后第一次启动shell时恢复
+    pub fn with_state_store(mut self, store: Arc<SessionStateStore>) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// 把命令造成的文件变更记录到共享的编辑历史中
+    pub fn with_edit_history(mut self, history: Arc<EditHistory>) -> Self {
+        self.edit_history = history;
+        self
+    }
+
+    /// 会话的编辑历史，文件变更被记录在其中，可以用编辑工具撤销
+    pub fn edit_history(&self) -> Arc<EditHistory> {
+        Arc::clone(&self.edit_history)
+    }
+
+    /// 会话标识
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// 设置当前的工具调用，之后检测到的文件变更会关联到它
+    pub async fn set_tool_use_id(&self, tool_use_id: Option<String>) {
+        *self.tool_use_id.lock().await = tool_use_id;
+    }
+
+    /// 设置是否跟踪命令造成的文件变更
+    ///
+    /// 只在本地执行目标上生效，跟踪工作区根目录，未设置时跟踪shell启动的目录。
+    pub async fn set_change_tracking(&self, enabled: bool) {
+        *self.track_changes.lock().await = enabled;
+        if !enabled {
+            *self.tracker.lock().await = None;
+        }
+    }
+
+    /// 切换会话使用的 shell，当前shell会被终止，下一条命令在新的 shell 中启动
+    pub async fn set_shell(&self, shell: Interpreter) -> Result<(), ToolError> {
+        shell.validate_shell()?;
+        let mut session = self.session.lock().await;
+        let mut interpreter = self.interpreter.lock().await;
+        if *interpreter == shell {
+            return Ok(());
+        }
+        interpreter.validate_shell()?;
+
+        if let Some(mut old_session) = session.take() {
+            info!("切换到 {}，重新启动shell", shell.name());
+            old_session.kill();
+        }
+        *interpreter = shell;
+        Ok(())
+    }
+
+    /// 设置命令策略
+    pub async fn set_policy(&self, policy: CommandPolicy) {
+        *self.policy.lock().await = policy;
+    }
+
+    /// 设置资源限制
+    ///
+    /// 会话级限制会立即更新到cgroup；rlimit 只能在启动进程时设置，
+    /// 因此配置变化时当前shell会被终止，下一条命令在新的限制下启动。
+    pub async fn set_resource_limits(&self, limits: ResourceLimits) {
+        let mut session = self.session.lock().await;
+        let mut current = self.limits.lock().await;
+        if *current == limits {
+            return;
+        }
+
+        if let Some(cgroup) = self.cgroup.lock().await.as_ref() {
+            if let Err(e) = cgroup.update(&limits) {
+                warn!("更新会话 cgroup 限制失败: {}", e);
+            }
+        }
+        if let Some(mut old_session) = session.take() {
+            info!("资源限制已变更，重新启动shell");
+            old_session.kill();
+        }
+        *current = limits;
+    }
+
+    /// 按命令策略检查命令，允许执行时返回 None，否则返回说明原因的结果
+    ///
+    /// `approved` 表示用户已经批准了这条命令，此时只有拒绝规则仍然生效。
+    /// 需要批准的命令会被保存下来，结果中附带只交给界面的批准令牌，见 `BashTool::approve`。
+    /// 策略规则针对 shell 命令，REPL 中的代码不做检查。
+    async fn check_policy(&self, command: &str, kind: ApprovalKind, approved: bool) -> Option<ToolResult> {
+        // 写入标准输入时已确认读取它的是 shell；REPL 中的代码无法按策略检查，
+        // REPL 工具需要在设置中单独启用
+        if !self.interpreter.lock().await.is_shell() && !matches!(kind, ApprovalKind::Stdin { .. }) {
+            return None;
+        }
+        let mut verdict = self.policy.lock().await.evaluate(command);
+        // 原因中引用了命令原文
+        if let Some(reason) = verdict.reason.take() {
+            verdict.reason = Some(self.env.lock().await.mask_secrets(&reason));
+        }
+        let reason = verdict.reason.clone().unwrap_or_default();
+
+        let (error, system) = match verdict.decision {
+            PolicyAction::Allow => return None,
+            PolicyAction::Ask if approved => return None,
+            PolicyAction::Ask => (
+                format!("命令需要用户批准后才能执行: {}", reason),
+                "命令尚未执行，正在等待用户批准",
+            ),
+            PolicyAction::Deny => (
+                format!("命令被策略拒绝: {}", reason),
+                "命令未执行，请换一种方式完成任务",
+            ),
+        };
+        warn!("命令未通过策略检查: {}", reason);
+
+        let approval_token = if verdict.decision == PolicyAction::Ask {
+            let token = uuid::Uuid::new_v4().to_string();
+            let mut approvals = self.approvals.lock().await;
+            if approvals.len() >= MAX_PENDING_APPROVALS {
+                approvals.remove(0);
+            }
+            approvals.push(PendingApproval {
+                token: token.clone(),
+                command: command.to_string(),
+                kind,
+            });
+            Some(token)
+        } else {
+            None
+        };
+
+        Some(ToolResult {
+            error: Some(error),
+            system: Some(system.to_string()),
+            policy: Some(verdict),
+            approval_token,
+            ..Default::default()
+        })
+    }
+
+    /// 处理用户对等待批准的命令的答复
+    ///
+    /// 令牌只出现在交给界面的结果中，模型无法通过工具参数批准命令。
+    /// 批准后命令按原来的方式执行，拒绝规则仍然生效；每个令牌只能使用一次。
+    pub async fn approve(&self, token: &str, approved: bool) -> Result<ToolResult, ToolError> {
+        let pending = {
+            let mut approvals = self.approvals.lock().await;
+            let index = approvals
+                .iter()
+                .position(|pending| pending.token == token)
+                .ok_or_else(|| ToolError::new("没有找到等待批准的命令，令牌无效或已使用"))?;
+            approvals.remove(index)
+        };
+
+        if !approved {
+            info!("用户拒绝执行命令");
+            return Ok(ToolResult {
+                error: Some(format!(
+                    "用户拒绝执行命令: {}",
+                    self.mask_secrets(&pending.command).await
+                )),
+                system: Some("命令未执行，请换一种方式完成任务".to_string()),
+                ..Default::default()
+            });
+        }
+        info!("用户已批准命令");
+        match pending.kind {
+            ApprovalKind::Run { idle_timeout } => self.run_checked(pending.command, idle_timeout, true).await,
+            ApprovalKind::Background => self.spawn_background(pending.command, true).await,
+            ApprovalKind::Stdin { idle_timeout } => self.send_input(pending.command, idle_timeout, true).await,
+        }
+    }
+
+    /// 设置沙箱配置，当前shell会被终止，下一条命令在新配置下启动
+    pub async fn set_sandbox(&self, config: SandboxConfig) -> Result<ToolResult, ToolError> {
+        config.validate()?;
+        if config.is_enabled() && !self.target.lock().await.is_local() {
+            return Err(ToolError::new("沙箱只能用于本地执行目标"));
+        }
+
+        let mut session = self.session.lock().await;
+        if let Some(mut old_session) = session.take() {
+            old_session.kill();
+        }
+        let message = config.describe();
+        *self.sandbox.lock().await = config;
+
+        Ok(ToolResult::success(message))
+    }
+
+    /// 设置命令执行目标
+    ///
+    /// 当前shell会被终止，下一条命令在新的目标上启动；工作区根目录属于原来的目标，
+    /// 需要重新设置。已在运行的后台任务不受影响。
+    pub async fn set_target(&self, target: ExecutionTarget) -> Result<ToolResult, ToolError> {
+        target.validate()?;
+        if !target.is_local() && self.sandbox.lock().await.is_enabled() {
+            return Err(ToolError::new("沙箱只能用于本地执行目标，请先关闭沙箱"));
+        }
+
+        let mut session = self.session.lock().await;
+        if let Some(mut old_session) = session.take() {
+            old_session.kill();
+        }
+        let message = target.describe();
+        *self.target.lock().await = target;
+        *self.workspace_root.lock().await = None;
+        *self.cwd.lock().await = None;
+
+        Ok(ToolResult::success(message))
+    }
+
+    /// 当前的命令执行目标
+    pub async fn target(&self) -> ExecutionTarget {
+        self.target.lock().await.clone()
+    }
+
+    /// 设置工作区根目录，会话已启动时shell会立即切换到该目录
+    ///
+    /// 目录在当前的执行目标上检查。
+    pub async fn set_workspace_root(&self, root: impl Into<PathBuf>) -> Result<ToolResult, ToolError> {
+        let root = root.into();
+        let is_dir = root.is_absolute()
+            && self
+                .target
+                .lock()
+                .await
+                .metadata(&root)?
+                .is_some_and(|metadata| metadata.is_dir);
+        if !is_dir {
+            return Err(ToolError::new(format!(
+                "工作区根目录 {} 不是有效的绝对路径目录",
+                root.display()
+            )));
+        }
+
+        if self.sandbox.lock().await.is_enabled() {
+            // 沙箱的可写挂载在启动时确定，需要重新启动shell
+            if let Some(mut old_session) = self.session.lock().await.take() {
+                old_session.kill();
+            }
+        } else {
+            let cd_command = self.interpreter.lock().await.cd_command(&root.to_string_lossy());
+            self.run_in_shell(&cd_command).await?;
+        }
+        *self.workspace_root.lock().await = Some(root.clone());
+        *self.cwd.lock().await = Some(root.clone());
+
+        Ok(ToolResult::success(format!("工作区根目录已设置为 {}", root.display()))
+            .with_cwd(root.to_string_lossy()))
+    }
+
+    /// 启动新的解释器进程，从工作区根目录开始，在 `deadline` 之前完成
+    async fn start_shell(&self, deadline: Instant) -> Result<BashSession, ToolError> {
+        let root = self.workspace_root.lock().await.clone();
+        let target = self.target.lock().await.clone();
+        let interpreter = *self.interpreter.lock().await;
+        let mut program = interpreter.program();
+        if target.is_local() {
+            let mut session = BashSession::start(self.prepare_command(&program, root.clone()).await?, interpreter)?;
+            *self.cwd.lock().await = root;
+            self.prepare_state(&mut session, deadline).await?;
+            return Ok(session);
+        }
+
+        // 远程目标上无法设置进程环境和 rlimit，由 sh 从标准输入读取设置脚本执行后再启动解释器，
+        // 启动后取得解释器的pid
+        program.splice(0..0, ["sh", "-c", REMOTE_SETUP_WRAPPER, "sh"]);
+        let mut session = BashSession::start(self.prepare_command(&program, root).await?, interpreter)?;
+        session.write_line(&self.remote_setup_line().await)?;
+        let output = session
+            .run(interpreter.pid_command(), remaining(deadline))
+            .await
+            .map_err(|e| ToolError::new(format!("在执行目标上启动{}失败: {}", interpreter.name(), e)))?
+            .ok_or_else(|| ToolError::new("连接执行目标超时"))?;
+        let pid = output.output.trim().parse().map_err(|_| {
+            ToolError::new(format!("在执行目标上启动{}失败: {}", interpreter.name(), output.stderr.trim()))
+        })?;
+        session.set_remote(target, pid);
+        *self.cwd.lock().await = output.cwd.map(PathBuf::from);
+        self.prepare_state(&mut session, deadline).await?;
+        Ok(session)
+    }
+
+    /// 配置了会话状态存储时记录新shell的基准环境，并恢复上次保存的状态
+    async fn prepare_state(&self, session: &mut BashSession, deadline: Instant) -> Result<(), ToolError> {
+        if self.state_store.is_some() {
+            session.record_baseline(remaining(deadline)).await?;
+            self.restore_state(session, deadline).await?;
+        }
+        Ok(())
+    }
+
+    /// 应用重启后第一次启动shell时，恢复上次保存的会话状态
+    ///
+    /// 恢复超时后shell不能再使用，返回错误；只尝试一次，下次启动的shell不再恢复。
+    async fn restore_state(&self, session: &mut BashSession, deadline: Instant) -> Result<(), ToolError> {
+        let Some(store) = &self.state_store else {
+            return Ok(());
+        };
+        if !std::mem::take(&mut *self.restore_pending.lock().await) {
+            return Ok(());
+        }
+        let state = match store.load(&self.session_id) {
+            Ok(Some(state)) => state,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!("{}", e);
+                return Ok(());
+            }
+        };
+
+        info!("恢复会话 {} 的shell状态", self.session_id);
+        let output = session
+            .run(&state.replay_script(), remaining(deadline))
+            .await
+            .map_err(|e| ToolError::new(format!("恢复会话 {} 的shell状态失败: {}", self.session_id, e)))?
+            .ok_or_else(|| ToolError::new(format!("恢复会话 {} 的shell状态超时", self.session_id)))?;
+        *self.cwd.lock().await = output.cwd.map(PathBuf::from);
+        Ok(())
+    }
+
+    /// 保存命令执行后的会话状态，敏感的环境变量不会被保存
+    ///
+    /// 读取状态失败时shell可能仍在执行读取命令，返回错误，由调用者放弃这个会话。
+    async fn save_state(&self, session: &mut BashSession, deadline: Instant) -> Result<(), ToolError> {
+        let Some(store) = &self.state_store else {
+            return Ok(());
+        };
+        let Some(mut state) = session.capture_state(remaining(deadline)).await? else {
+            return Ok(());
+        };
+
+        let env = self.env.lock().await;
+        state.env.retain(|name, _| !env.is_secret(name));
+        if let Err(e) = store.save(&self.session_id, &state) {
+            warn!("{}", e);
+        }
+        Ok(())
+    }
+
+    /// 交给 `REMOTE_SETUP_WRAPPER` 的一行输入
+    async fn remote_setup_line(&self) -> String {
+        general_purpose::STANDARD.encode(self.remote_setup_script().await)
+    }
+
+    /// 在远程目标的shell中应用会话环境变量和 rlimit 的命令
+    async fn remote_setup_script(&self) -> String {
+        let limits = self.limits.lock().await;
+        if limits.needs_cgroup() {
+            warn!("远程执行目标不支持会话级的内存和CPU限制");
+        }
+        [self.env.lock().await.script(), limits.ulimit_script()]
+            .into_iter()
+            .filter(|script| !script.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 构造从 `cwd` 运行 `program` 的命令，应用沙箱配置和会话环境变量
+    ///
+    /// 远程目标上的环境变量和资源限制需要由调用者在shell中设置。
+    async fn prepare_command(&self, program: &[&str], cwd: Option<PathBuf>) -> Result<Command, ToolError> {
+        let target = self.target.lock().await.clone();
+        if !target.is_local() {
+            let cwd = match cwd {
+                Some(cwd) => Some(cwd),
+                None => self.workspace_root.lock().await.clone(),
+            };
+            return Ok(target.command(program, cwd.as_deref()));
+        }
+
+        let workspace = self.start_dir().await?;
+        let cwd = cwd.unwrap_or_else(|| workspace.clone());
+
+        let mut command = self.sandbox.lock().await.command(&workspace, &cwd, program)?;
+        self.env.lock().await.apply_to_command(&mut command);
+
+        let limits = self.limits.lock().await;
+        let mut cgroup = self.cgroup.lock().await;
+        if limits.needs_cgroup() && cgroup.is_none() {
+            match SessionCgroup::create(&self.session_id, &limits) {
+                Ok(created) => *cgroup = Some(created),
+                Err(e) => warn!("无法创建会话 cgroup，仅应用 rlimit: {}", e),
+            }
+        }
+        limits.apply_to_command(&mut command, cgroup.as_ref());
+        Ok(command)
+    }
+
+    /// 执行Bash命令
+    ///
+    /// 命令超过 `idle_timeout` 秒（默认 5 秒，0 表示不检测）没有输出并且在等待输入时，
+    /// 提前返回已有的输出，之后可以用 stdin 或 signal 操作继续。
+    pub async fn execute(
+        &self,
+        command: Option<String>,
+        restart: bool,
+        idle_timeout: Option<u64>,
+    ) -> Result<ToolResult, ToolError> {
+        // 如果需要重启会话
+        if restart {
+            let mut session = self.session.lock().await;
+            // 如果有正在运行的进程，先终止它
+            if let Some(mut old_session) = session.take() {
+                old_session.kill();
+            }
+            // 后台任务与会话绑定，重启时一并清理
+            self.jobs.lock().await.kill_all();
+            // 重启意味着放弃之前的shell状态
+            *self.restore_pending.lock().await = false;
+            self.approvals.lock().await.clear();
+            if let Some(store) = &self.state_store {
+                if let Err(e) = store.delete(&self.session_id) {
+                    warn!("{}", e);
+                }
+            }
+            let deadline = Instant::now() + Duration::from_secs(TIMEOUT_SECONDS);
+            *session = Some(self.start_shell(deadline).await?);
+
+            return Ok(ToolResult {
+                output: None,
+                error: None,
+                base64_image: None,
+                system: Some("工具已重启".to_string()),
+                cwd: self.current_dir().await,
+                ..Default::default()
+            });
+        }
+
+        let command = command.ok_or_else(|| ToolError::new("未提供命令"))?;
+        self.run_checked(command, idle_timeout, false).await
+    }
+
+    /// 检查策略后在会话中执行命令
+    async fn run_checked(
+        &self,
+        command: String,
+        idle_timeout: Option<u64>,
+        approved: bool,
+    ) -> Result<ToolResult, ToolError> {
+        if let Some(result) = self.check_policy(&command, ApprovalKind::Run { idle_timeout }, approved).await {
+            return Ok(result);
+        }
+
+        let cwd = self.current_dir().await;
+        let started_at = Utc::now();
+        let start = Instant::now();
+        let idle = match idle_timeout.unwrap_or(IDLE_SECONDS) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        };
+        let result = self.run_foreground(&command, idle).await;
+        self.record_history(&command, cwd.as_deref(), &result, false, started_at, start.elapsed())
+            .await;
+        result
+    }
+
+    /// 把一次命令执行写入命令历史，写入失败只记录日志
+    async fn record_history(
+        &self,
+        command: &str,
+        cwd: Option<&str>,
+        result: &Result<ToolResult, ToolError>,
+        background: bool,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+    ) {
+        let Some(history) = &self.history else {
+            return;
+        };
+
+        let (output, exit_code) = match result {
+            Ok(result) => {
+                let parts: Vec<&str> = [result.output.as_deref(), result.error.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                (parts.join("\n"), result.exit_code)
+            }
+            Err(e) => (e.message.clone(), None),
+        };
+        let (masked_command, output) = {
+            let env = self.env.lock().await;
+            (env.mask_secrets(command), env.mask_secrets(&output))
+        };
+
+        let entry = NewEntry {
+            session_id: &self.session_id,
+            command: &masked_command,
+            unmasked_command: command,
+            cwd,
+            exit_code,
+            output: &output,
+            background,
+            started_at,
+            duration,
+        };
+        if let Err(e) = history.record(entry) {
+            warn!("{}", e);
+        }
+    }
+
+    /// 在会话中执行命令并等待其完成或开始等待输入
+    ///
+    /// 启动会话、执行命令和记录会话状态共用一个期限。
+    async fn run_foreground(&self, command: &str, idle: Option<Duration>) -> Result<ToolResult, ToolError> {
+        let deadline = Instant::now() + Duration::from_secs(TIMEOUT_SECONDS);
+        let mut session_guard = self.session.lock().await;
+        // 如果会话未启动，先启动它
+        if session_guard.is_none() {
+            *session_guard = Some(self.start_shell(deadline).await?);
+        }
+        let session = session_guard.as_mut().unwrap();
+
+        // 确保进程仍在运行
+        if let Some(code) = session.exit_code()? {
+            *session_guard = None;
+            let name = self.interpreter.lock().await.name();
+            return Ok(ToolResult {
+                output: None,
+                error: Some(format!("{}已退出，退出码为 {}", name, code)),
+                base64_image: None,
+                system: Some(format!("命令未执行，下一条命令会启动新的 {}", name)),
+                ..Default::default()
+            });
+        }
+        if session.is_busy() {
+            return Ok(ToolResult::error("上一条命令仍在运行").with_system(
+                "使用 action: \"stdin\" 和 input 向它输入内容，使用 action: \"signal\" 中断它，或使用 restart: true 放弃它并开始新的会话",
+            ));
+        }
+
+        self.refresh_changes().await;
+        session.send(command)?;
+        self.wait_command(&mut session_guard, command, deadline, idle, None).await
+    }
+
+    /// 等待会话中正在执行的命令，并把结果转换为工具结果
+    ///
+    /// 命令最多运行到 `deadline` 前 `STATE_CAPTURE_TIME`，剩下的时间用于记录会话状态。
+    /// 命令在等待输入时返回已有的部分输出。超时后如果提供了 `still_running`，
+    /// 返回部分输出并以它作为系统消息，否则终止会话。
+    async fn wait_command(
+        &self,
+        session_guard: &mut MutexGuard<'_, Option<BashSession>>,
+        command: &str,
+        deadline: Instant,
+        idle: Option<Duration>,
+        still_running: Option<String>,
+    ) -> Result<ToolResult, ToolError> {
+        let session = session_guard
+            .as_mut()
+            .ok_or_else(|| ToolError::new("当前没有正在运行的命令"))?;
+        let timeout = remaining(deadline).saturating_sub(STATE_CAPTURE_TIME);
+        let limits = self.limits.lock().await.clone();
+        let counters = self.cgroup.lock().await.as_ref().map(|cgroup| cgroup.counters());
+
+        let result = match session.wait(timeout, idle).await {
+            Ok(RunStatus::Finished(result)) => result,
+            Ok(RunStatus::WaitingForInput(partial)) => {
+                let seconds = idle.unwrap_or_default().as_secs();
+                let result = self.partial_result(partial).await.with_system(format!(
+                    "命令在等待输入（{}秒内没有新的输出），仍在运行。使用 action: \"stdin\" 和 input 输入内容，或使用 action: \"signal\" 中断它",
+                    seconds
+                ));
+                return Ok(self.with_changes(result).await);
+            }
+            Ok(RunStatus::Running(partial)) => {
+                if let Some(message) = still_running {
+                    let result = self.partial_result(partial).await.with_system(message);
+                    return Ok(self.with_changes(result).await);
+                }
+                // 超时后终止当前进程，下一条命令会自动启动新的会话
+                if let Some(mut old_session) = session_guard.take() {
+                    old_session.kill();
+                }
+                return Err(ToolError::new(format!(
+                    "命令执行超时（{}秒）: {}",
+                    timeout.as_secs(),
+                    command
+                )));
+            }
+            Err(e) => {
+                **session_guard = None;
+                // bash自身可能因超出会话内存上限被终止
+                let hits = self.cgroup_hits(counters, &limits).await;
+                if hits.is_empty() {
+                    return Err(e);
+                }
+                return Ok(ToolResult {
+                    error: Some(e.message),
+                    system: Some(describe_hits(&hits)),
+                    limit_hits: hits,
+                    ..Default::default()
+                });
+            }
+        };
+
+        let mut hits = limits.detect_hits(result.exit_code);
+        hits.extend(self.cgroup_hits(counters, &limits).await);
+
+        if let Some(cwd) = &result.cwd {
+            *self.cwd.lock().await = Some(PathBuf::from(cwd));
+        }
+        if let Some(session) = session_guard.as_mut() {
+            if let Err(e) = self.save_state(session, deadline).await {
+                // 下一条命令会启动新的会话
+                warn!("记录会话 {} 的shell状态失败: {}", self.session_id, e);
+                **session_guard = None;
+            }
+        }
+
+        // 应用回车、光标移动等控制序列，只把最终显示的文本交给模型
+        let output = terminal::render(&result.output);
+        let stderr = terminal::render(&result.stderr);
+        let env = self.env.lock().await;
+        // 退出码、资源限制和命令结束后的当前目录通过系统消息告诉模型
+        let mut notes = Vec::new();
+        if result.exit_code != 0 {
+            notes.push(format!("命令退出码: {}", result.exit_code));
+        }
+        if !hits.is_empty() {
+            notes.push(describe_hits(&hits));
+        }
+        if let Some(cwd) = &result.cwd {
+            notes.push(format!("当前目录: {}", cwd));
+        }
+        let result = ToolResult {
+            output: if output.is_empty() {
+                None
+            } else {
+                Some(env.mask_secrets(&output))
+            },
+            error: if stderr.is_empty() {
+                None
+            } else {
+                Some(env.mask_secrets(&stderr))
+            },
+            base64_image: None,
+            system: (!notes.is_empty()).then(|| notes.join("，")),
+            cwd: result.cwd,
+            exit_code: Some(result.exit_code),
+            limit_hits: hits,
+            ..Default::default()
+        };
+        Ok(self.with_changes(result).await)
+    }
+
+    /// 命令开始前更新工作区快照，之后检测到的变更都由这条命令造成
+    async fn refresh_changes(&self) {
+        let root = self.tracked_root().await;
+        let mut tracker = self.tracker.lock().await;
+        let Some(root) = root else {
+            *tracker = None;
+            return;
+        };
+        match tracker.as_mut() {
+            Some(tracker) if tracker.root() == root => {
+                let _ = tracker.scan();
+            }
+            _ => {
+                let mut created = ChangeTracker::new(root);
+                let _ = created.scan();
+                *tracker = Some(created);
+            }
+        }
+    }
+
+    /// 需要跟踪文件变更的目录，与本地shell启动的目录相同；未启用跟踪或目标不在本地时为 None
+    async fn tracked_root(&self) -> Option<PathBuf> {
+        if !*self.track_changes.lock().await || !self.target.lock().await.is_local() {
+            return None;
+        }
+        self.start_dir().await.ok()
+    }
+
+    /// 本地shell启动的目录：工作区根目录，未设置时为应用的当前目录
+    async fn start_dir(&self) -> Result<PathBuf, ToolError> {
+        match self.workspace_root.lock().await.clone() {
+            Some(root) => Ok(root),
+            None => std::env::current_dir().map_err(|e| ToolError::new(format!("获取当前目录失败: {}", e))),
+        }
+    }
+
+    /// 在结果中附加自快照以来的文件变更，并把变更前的内容写入编辑历史
+    async fn with_changes(&self, mut result: ToolResult) -> ToolResult {
+        let mut tracker = self.tracker.lock().await;
+        let Some(tracker) = tracker.as_mut() else {
+            return result;
+        };
+
+        let changes = match tracker.scan() {
+            Ok(changes) => changes,
+            Err(reason) => {
+                let note = format!("未跟踪本条命令对工作区文件的改动，无法撤销（{}）", reason);
+                result.system = Some(match result.system.take() {
+                    Some(system) => format!("{}\n{}", system, note),
+                    None => note,
+                });
+                return result;
+            }
+        };
+
+        let tool_use_id = self.tool_use_id.lock().await.clone();
+        let mut notes = Vec::new();
+        for change in changes {
+            if let Some(e) = self.record_edit(&change, tool_use_id.as_deref()) {
+                notes.push(e.to_string());
+            }
+            result.file_changes.push(FileChange {
+                path: change.path.to_string_lossy().into_owned(),
+                kind: change.kind,
+            });
+        }
+
+        if !result.file_changes.is_empty() {
+            notes.insert(0, changes::describe_changes(&result.file_changes));
+            let description = notes.join("\n");
+            result.system = Some(match result.system.take() {
+                Some(system) => format!("{}\n{}", system, description),
+                None => description,
+            });
+        }
+        result
+    }
+
+    /// 把一个文件变更写入编辑历史，没有完整记录时返回原因
+    ///
+    /// 二进制文件和未缓存内容的大文件无法撤销，不会被记录。
+    fn record_edit(&self, change: &TrackedChange, tool_use_id: Option<&str>) -> Option<ToolError> {
+        // 外层 None 表示内容未知或不是文本，内层 None 表示文件不存在
+        fn text(content: &Option<Vec<u8>>) -> Option<Option<&str>> {
+            std::str::from_utf8(content.as_ref()?).ok().map(Some)
+        }
+        let (before, after) = match change.kind {
+            ChangeKind::Created => (Some(None), text(&change.current)),
+            ChangeKind::Modified => (text(&change.previous), text(&change.current)),
+            ChangeKind::Deleted => (text(&change.previous), Some(None)),
+        };
+        let (Some(before), Some(after)) = (before, after) else {
+            return None;
+        };
+
+        let edit = NewEdit {
+            path: &change.path,
+            operation: "bash",
+            tool_use_id,
+            before,
+            after,
+        };
+        self.edit_history.record(&self.session_id, edit).err()
+    }
+
+    /// 命令仍在运行时的部分结果
+    async fn partial_result(&self, partial: PartialOutput) -> ToolResult {
+        let output = terminal::render(&partial.output);
+        let stderr = terminal::render(&partial.stderr);
+        let env = self.env.lock().await;
+        ToolResult {
+            output: if output.is_empty() {
+                None
+            } else {
+                Some(env.mask_secrets(&output))
+            },
+            error: if stderr.is_empty() {
+                None
+            } else {
+                Some(env.mask_secrets(&stderr))
+            },
+            ..Default::default()
+        }
+    }
+
+    /// 向正在执行的命令的标准输入写入内容，然后继续等待
+    ///
+    /// 没有以换行结尾的输入会自动加上换行；输入为空时只继续等待新的输出。
+    pub async fn write_stdin(&self, input: Option<String>, idle_timeout: Option<u64>) -> Result<ToolResult, ToolError> {
+        self.send_input(input.unwrap_or_default(), idle_timeout, false).await
+    }
+
+    /// 写入标准输入，正在执行的命令中有 shell 时先按命令策略检查输入内容
+    async fn send_input(&self, mut input: String, idle_timeout: Option<u64>, approved: bool) -> Result<ToolResult, ToolError> {
+        let mut session_guard = self.session.lock().await;
+        let session = session_guard
+            .as_mut()
+            .filter(|session| session.is_busy())
+            .ok_or_else(|| ToolError::new("当前没有正在运行的命令"))?;
+
+        if session.runs_shell() {
+            if let Some(result) = self.check_policy(&input, ApprovalKind::Stdin { idle_timeout }, approved).await {
+                return Ok(result);
+            }
+        }
+        if !input.is_empty() && !input.ends_with('\n') {
+            input.push('\n');
+        }
+        session.write_input(&input)?;
+
+        let idle = match idle_timeout.unwrap_or(IDLE_SECONDS) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        };
+        let deadline = Instant::now() + Duration::from_secs(TIMEOUT_SECONDS);
+        self.wait_command(&mut session_guard, "stdin", deadline, idle, None).await
+    }
+
+    /// 向正在执行的命令发送信号，并等待片刻查看命令是否结束
+    pub async fn send_signal(&self, signal: Option<CommandSignal>) -> Result<ToolResult, ToolError> {
+        let signal = signal.ok_or_else(|| ToolError::new("未提供信号"))?;
+        let mut session_guard = self.session.lock().await;
+        let session = session_guard
+            .as_mut()
+            .filter(|session| session.is_busy())
+            .ok_or_else(|| ToolError::new("当前没有正在运行的命令"))?;
+
+        let count = session.signal(signal)?;
+        if count == 0 {
+            return Ok(ToolResult::error(format!("没有找到可以接收 {} 的进程", signal.name())).with_system(
+                "命令可能在shell内部执行（如 read），请使用 action: \"stdin\" 和 input 输入内容，或使用 restart: true 放弃它并开始新的会话",
+            ));
+        }
+        info!("已向 {} 个进程发送 {}", count, signal.name());
+
+        let still_running = match signal {
+            CommandSignal::Sigtstp => "命令已暂停，发送 SIGCONT 可以继续运行".to_string(),
+            _ => format!("已向 {} 个进程发送 {}，命令仍在运行", count, signal.name()),
+        };
+        let deadline = Instant::now() + SIGNAL_WAIT + STATE_CAPTURE_TIME;
+        self.wait_command(&mut session_guard, signal.name(), deadline, None, Some(still_running))
+            .await
+    }
+
+    /// 比较cgroup计数，返回命令执行期间触发的会话级限制
+    async fn cgroup_hits(&self, before: Option<CgroupCounters>, limits: &ResourceLimits) -> Vec<LimitHit> {
+        match (self.cgroup.lock().await.as_ref(), before) {
+            (Some(cgroup), Some(before)) => cgroup.detect_hits(before, limits),
+            _ => Vec::new(),
+        }
+    }
+
+    /// shell当前所在的目录，未知时返回 None
+    pub async fn current_dir(&self) -> Option<String> {
+        self.cwd
+            .lock()
+            .await
+            .as_deref()
+            .map(|cwd| cwd.to_string_lossy().into_owned())
+    }
+
+    /// 在后台执行命令，立即返回任务ID
+    pub async fn run_in_background(&self, command: Option<String>) -> Result<ToolResult, ToolError> {
+        let command = command.ok_or_else(|| ToolError::new("未提供命令"))?;
+        self.spawn_background(command, false).await
+    }
+
+    /// 检查策略后在后台执行命令
+    async fn spawn_background(&self, command: String, approved: bool) -> Result<ToolResult, ToolError> {
+        let interpreter = *self.interpreter.lock().await;
+        if !interpreter.is_shell() {
+            return Err(ToolError::new(format!("{} REPL 不支持后台任务", interpreter.name())));
+        }
+        if let Some(result) = self.check_policy(&command, ApprovalKind::Background, approved).await {
+            return Ok(result);
+        }
+
+        let cwd = self.cwd.lock().await.clone();
+        let started_at = Utc::now();
+        let (process, setup) = if self.target.lock().await.is_local() {
+            let process = self.prepare_command(&[interpreter.name(), "-c", &command], cwd.clone()).await?;
+            (process, None)
+        } else {
+            let program = ["sh", "-c", REMOTE_SETUP_WRAPPER, "sh", interpreter.name(), "-c", &command];
+            let process = self.prepare_command(&program, cwd.clone()).await?;
+            (process, Some(self.remote_setup_line().await))
+        };
+        let result = self.jobs.lock().await.spawn(&command, process, setup);
+
+        let cwd = cwd.map(|cwd| cwd.to_string_lossy().into_owned());
+        self.record_history(&command, cwd.as_deref(), &result, true, started_at, Duration::ZERO)
+            .await;
+        result
+    }
+
+    /// 查询后台任务状态，未指定任务ID时列出所有任务
+    pub async fn job_status(&self, job_id: Option<String>) -> Result<ToolResult, ToolError> {
+        let mut result = self.jobs.lock().await.status(job_id.as_deref())?;
+        // 任务列表中包含原始命令
+        let env = self.env.lock().await;
+        result.output = result.output.map(|output| env.mask_secrets(&output));
+        Ok(result)
+    }
+
+    /// 读取后台任务从 `since_offset` 开始的输出
+    pub async fn job_output(&self, job_id: Option<String>, since_offset: Option<u64>) -> Result<ToolResult, ToolError> {
+        let job_id = job_id.ok_or_else(|| ToolError::new("未提供任务ID"))?;
+        let mut result = self.jobs.lock().await.output(&job_id, since_offset.unwrap_or(0))?;
+        let env = self.env.lock().await;
+        // 日志文件中保留原始输出，返回给模型的是渲染后的文本
+        result.output = result
+            .output
+            .map(|output| env.mask_secrets(&terminal::render(&output)));
+        Ok(result)
+    }
+
+    /// 终止后台任务
+    pub async fn job_kill(&self, job_id: Option<String>) -> Result<ToolResult, ToolError> {
+        let job_id = job_id.ok_or_else(|| ToolError::new("未提供任务ID"))?;
+        self.jobs.lock().await.kill(&job_id)
+    }
+
+    /// 设置环境变量，会话已启动时立即在shell中生效
+    pub async fn env_set(&self, name: Option<String>, value: Option<String>, secret: bool) -> Result<ToolResult, ToolError> {
+        let name = name.ok_or_else(|| ToolError::new("未提供环境变量名"))?;
+        let value = value.ok_or_else(|| ToolError::new("未提供环境变量值"))?;
+        env::validate_name(&name)?;
+
+        let var = EnvVar { name, value, secret };
+        let export = self.interpreter.lock().await.export_command(&var);
+        self.run_in_shell(&export).await?;
+        let message = format!("环境变量 {} 已设置", var.name);
+        self.env.lock().await.set(var);
+
+        Ok(ToolResult::success(message))
+    }
+
+    /// 移除环境变量，会话已启动时立即在shell中生效
+    pub async fn env_unset(&self, name: Option<String>) -> Result<ToolResult, ToolError> {
+        let name = name.ok_or_else(|| ToolError::new("未提供环境变量名"))?;
+        env::validate_name(&name)?;
+
+        let unset = self.interpreter.lock().await.unset_command(&name);
+        self.run_in_shell(&unset).await?;
+        self.env.lock().await.unset(&name);
+
+        Ok(ToolResult::success(format!("环境变量 {} 已移除", name)))
+    }
+
+    /// 把文本中出现的敏感环境变量值替换为掩码，用于日志和错误信息
+    pub async fn mask_secrets(&self, text: &str) -> String {
+        self.env.lock().await.mask_secrets(text)
+    }
+
+    /// 列出由工具管理的环境变量，以及shell当前的完整环境
+    pub async fn env_list(&self) -> Result<ToolResult, ToolError> {
+        let managed = self.env.lock().await.describe();
+        let command = self.interpreter.lock().await.env_list_command();
+        let current = self.run_foreground(command, None).await?;
+
+        Ok(ToolResult::success(format!(
+            "由工具设置的环境变量:\n{}\n\n当前shell环境:\n{}",
+            managed,
+            current.output.unwrap_or_default()
+        )))
+    }
+
+    /// 应用环境变量配置，会话已启动时立即在shell中生效
+    pub async fn apply_env_profile(&self, profile: &EnvProfile) -> Result<(), ToolError> {
+        for name in &profile.unset {
+            env::validate_name(name)?;
+        }
+        for var in &profile.vars {
+            env::validate_name(&var.name)?;
+        }
+
+        let interpreter = *self.interpreter.lock().await;
+        let commands: Vec<String> = profile
+            .unset
+            .iter()
+            .map(|name| interpreter.unset_command(name))
+            .chain(profile.vars.iter().map(|var| interpreter.export_command(var)))
+            .collect();
+        if !commands.is_empty() {
+            self.run_in_shell(&commands.join("\n")).await?;
+        }
+        self.env.lock().await.apply_profile(profile);
+
+        Ok(())
+    }
+
+    /// 在已启动的shell中执行内部命令；会话未启动时什么也不做
+    async fn run_in_shell(&self, command: &str) -> Result<(), ToolError> {
+        let mut session_guard = self.session.lock().await;
+        if let Some(session) = session_guard.as_mut() {
+            if session.run(command, Duration::from_secs(TIMEOUT_SECONDS)).await?.is_none() {
+                *session_guard = None;
+                return Err(ToolError::new("更新环境变量超时"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 距离 `deadline` 的剩余时间
+fn remaining(deadline: Instant) -> Duration {
+    deadline.saturating_duration_since(Instant::now())
+}
+
+/// 把触发的资源限制合并为一条系统消息
+fn describe_hits(hits: &[LimitHit]) -> String {
+    let messages: Vec<&str> = hits.iter().map(|hit| hit.message.as_str()).collect();
+    format!("命令触发了资源限制: {}", messages.join("；"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_file_changes_and_records_them_for_undo() {
+        let root = std::env::temp_dir().join(format!("maestro-bash-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "old\n").unwrap();
+
+        let tool = BashTool::new();
+        tool.set_change_tracking(true).await;
+        tool.set_workspace_root(&root).await.unwrap();
+        tool.set_tool_use_id(Some("toolu_1".to_string())).await;
+        let result = tool
+            .execute(Some("echo new > a.txt; echo b > b.txt".to_string()), false, None)
+            .await
+            .unwrap();
+        let history = tool.edit_history().list("default", &root.join("a.txt"), None).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        let changes: Vec<(String, ChangeKind)> =
+            result.file_changes.into_iter().map(|change| (change.path, change.kind)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (root.join("a.txt").to_string_lossy().into_owned(), ChangeKind::Modified),
+                (root.join("b.txt").to_string_lossy().into_owned(), ChangeKind::Created),
+            ]
+        );
+        let system = result.system.unwrap();
+        assert!(system.contains(&format!("当前目录: {}", root.display())), "{}", system);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].operation, "bash");
+        assert_eq!(history[0].tool_use_id.as_deref(), Some("toolu_1"));
+    }
+}
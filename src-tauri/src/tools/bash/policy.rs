@@ -0,0 +1,816 @@
+use crate::tools::base::{PolicyAction, PolicyVerdict};
+use serde::{Deserialize, Serialize};
+
+/// 一条策略规则，所有已设置的条件都满足时命中
+///
+/// 模式使用通配符：`*` 匹配任意字符，`?` 匹配单个字符。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PolicyRule {
+    pub action: PolicyAction,
+    /// 程序名模式，只比较程序的文件名部分，例如 `rm`、`git`
+    #[serde(default)]
+    pub program: Option<String>,
+    /// 参数模式，与以空格连接的全部参数比较，例如 `push --force*`
+    #[serde(default)]
+    pub args: Option<String>,
+    /// 重定向目标模式，任一重定向目标匹配即可，例如 `/etc/*`
+    #[serde(default)]
+    pub redirect: Option<String>,
+    /// 命中时返回给模型的原因
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Bash命令策略
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandPolicy {
+    /// 按顺序匹配的规则
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+    /// 没有规则命中时的动作
+    #[serde(default)]
+    pub default_action: PolicyAction,
+    /// 检测到已知破坏性命令时的动作
+    #[serde(default = "default_dangerous_action")]
+    pub dangerous_action: PolicyAction,
+}
+
+fn default_dangerous_action() -> PolicyAction {
+    PolicyAction::Deny
+}
+
+impl Default for CommandPolicy {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_action: PolicyAction::Allow,
+            dangerous_action: default_dangerous_action(),
+        }
+    }
+}
+
+impl CommandPolicy {
+    /// 检查命令，返回所有简单命令中最严格的结论
+    pub fn evaluate(&self, command: &str) -> PolicyVerdict {
+        let mut verdict = PolicyVerdict {
+            decision: PolicyAction::Allow,
+            reason: None,
+        };
+        let mut consider = |candidate: PolicyVerdict| {
+            if candidate.decision > verdict.decision {
+                verdict = candidate;
+            }
+        };
+
+        if let Some(reason) = detect_fork_bomb(command) {
+            consider(PolicyVerdict::new(self.dangerous_action, reason));
+        }
+
+        let commands = match parse(command) {
+            Ok(commands) => commands,
+            Err(e) => {
+                consider(PolicyVerdict::new(PolicyAction::Ask, format!("无法解析命令（{}），需要人工确认", e)));
+                return verdict;
+            }
+        };
+
+        for simple in &commands {
+            if let Some(reason) = detect_dangerous(simple) {
+                consider(PolicyVerdict::new(self.dangerous_action, reason));
+            }
+
+            match self.rules.iter().find(|rule| rule.matches(simple)) {
+                Some(rule) => {
+                    let reason = rule.reason.clone().unwrap_or_else(|| {
+                        format!("命令 `{}` 命中策略规则", simple.display())
+                    });
+                    consider(PolicyVerdict::new(rule.action, reason));
+                }
+                None if self.default_action != PolicyAction::Allow => {
+                    consider(PolicyVerdict::new(
+                        self.default_action,
+                        format!("命令 `{}` 没有命中任何允许规则", simple.display()),
+                    ));
+                }
+                None => {}
+            }
+        }
+
+        verdict
+    }
+}
+
+impl PolicyRule {
+    fn matches(&self, command: &SimpleCommand) -> bool {
+        if self.program.is_none() && self.args.is_none() && self.redirect.is_none() {
+            return false;
+        }
+        if let Some(pattern) = &self.program {
+            if !glob_match(pattern, command.program_name()) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.args {
+            if !glob_match(pattern, &command.args.join(" ")) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.redirect {
+            if !command.redirects.iter().any(|target| glob_match(pattern, target)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 解析后的简单命令
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// 重定向目标（文件路径）
+    pub redirects: Vec<String>,
+}
+
+impl SimpleCommand {
+    /// 程序的文件名部分，例如 `/bin/rm` 返回 `rm`
+    fn program_name(&self) -> &str {
+        self.program.rsplit('/').next().unwrap_or(&self.program)
+    }
+
+    fn display(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// 词法单元
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(String),
+}
+
+/// 把命令文本解析为简单命令列表
+///
+/// 这是一个保守的 shell 解析器：处理引号、转义、管道、列表、子 shell、
+/// 重定向和 heredoc，命令替换、`bash -c` 和 `eval` 的内容会递归解析，
+/// `sudo`、`env`、`xargs` 等包装命令会展开为真正执行的程序。
+pub fn parse(command: &str) -> Result<Vec<SimpleCommand>, String> {
+    let (tokens, substitutions) = tokenize(command)?;
+    let mut commands = Vec::new();
+
+    let mut words: Vec<String> = Vec::new();
+    let mut redirects: Vec<String> = Vec::new();
+    let mut pending_redirect: Option<String> = None;
+
+    for token in tokens {
+        match token {
+            Token::Word(word) => match pending_redirect.take() {
+                Some(op) => {
+                    // `>&2`、`<&0` 这类复制文件描述符的目标不是文件
+                    let duplicates_fd = op.ends_with('&') && word.chars().all(|c| c.is_ascii_digit() || c == '-');
+                    if !duplicates_fd {
+                        redirects.push(word);
+                    }
+                }
+                None => words.push(word),
+            },
+            Token::Op(op) if is_redirect(&op) => pending_redirect = Some(op),
+            Token::Op(_) => finish_command(&mut words, &mut redirects, &mut commands)?,
+        }
+    }
+    finish_command(&mut words, &mut redirects, &mut commands)?;
+
+    for substitution in substitutions {
+        commands.extend(parse(&substitution)?);
+    }
+
+    Ok(commands)
+}
+
+/// 把已收集的词语和重定向组成简单命令
+fn finish_command(
+    words: &mut Vec<String>,
+    redirects: &mut Vec<String>,
+    commands: &mut Vec<SimpleCommand>,
+) -> Result<(), String> {
+    if let Some(simple) = build_command(std::mem::take(words), std::mem::take(redirects)) {
+        commands.extend(unwrap_command(simple)?);
+    }
+    Ok(())
+}
+
+fn is_redirect(op: &str) -> bool {
+    let op = op.trim_start_matches(|c: char| c.is_ascii_digit());
+    op.starts_with('>') || op.starts_with('<') || op.starts_with("&>")
+}
+
+/// shell 保留字，出现在命令开头时跳过
+const RESERVED_WORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "!", "{", "}", "time", "esac",
+];
+
+/// 由词语构造简单命令，跳过保留字和变量赋值
+fn build_command(words: Vec<String>, redirects: Vec<String>) -> Option<SimpleCommand> {
+    let mut words = words.into_iter().peekable();
+
+    while let Some(word) = words.peek() {
+        if RESERVED_WORDS.contains(&word.as_str()) || is_assignment(word) {
+            words.next();
+        } else {
+            break;
+        }
+    }
+
+    let program = match words.next() {
+        // for/case/select 的词语不是命令，循环体会在后续的分隔符之后单独解析
+        Some(word) if matches!(word.as_str(), "for" | "case" | "select" | "function") => return None,
+        Some(word) => word,
+        None if redirects.is_empty() => return None,
+        None => String::new(),
+    };
+
+    Some(SimpleCommand {
+        program,
+        args: words.collect(),
+        redirects,
+    })
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && name.chars().all(|c| c == '_' || c.is_ascii_alphanumeric())
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// 展开包装命令，返回实际会执行的命令
+fn unwrap_command(command: SimpleCommand) -> Result<Vec<SimpleCommand>, String> {
+    let name = command.program_name().to_string();
+    let mut args = command.args.clone().into_iter().peekable();
+
+    match name.as_str() {
+        "sudo" | "doas" | "nohup" | "nice" | "ionice" | "command" | "builtin" | "exec" | "stdbuf" | "xargs"
+        | "env" | "timeout" | "setsid" | "chroot" => {
+            // 跳过选项和环境变量赋值，找到真正的程序
+            while let Some(arg) = args.peek() {
+                if arg == "--" {
+                    args.next();
+                    break;
+                }
+                if arg.starts_with('-') {
+                    // `sudo -u root rm` 中的 `root` 是选项的值，不是程序
+                    let takes_value = option_takes_value(&name, arg);
+                    args.next();
+                    if takes_value {
+                        args.next();
+                    }
+                } else if name == "env" && is_assignment(arg) {
+                    args.next();
+                } else {
+                    break;
+                }
+            }
+            if name == "timeout" || name == "chroot" {
+                args.next();
+            }
+            let rest: Vec<String> = args.collect();
+            if rest.is_empty() {
+                return Ok(vec![command]);
+            }
+            let inner = SimpleCommand {
+                program: rest[0].clone(),
+                args: rest[1..].to_vec(),
+                redirects: command.redirects.clone(),
+            };
+            let mut commands = vec![command];
+            commands.extend(unwrap_command(inner)?);
+            Ok(commands)
+        }
+        "bash" | "sh" | "zsh" | "dash" | "ksh" => {
+            let script = command
+                .args
+                .iter()
+                .position(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains('c'))
+                .and_then(|idx| command.args.get(idx + 1).cloned());
+            let mut commands = vec![command];
+            if let Some(script) = script {
+                commands.extend(parse(&script)?);
+            }
+            Ok(commands)
+        }
+        "eval" => {
+            let script = command.args.join(" ");
+            let mut commands = vec![command];
+            commands.extend(parse(&script)?);
+            Ok(commands)
+        }
+        _ => Ok(vec![command]),
+    }
+}
+
+/// 包装命令中带有单独值的选项，值写在下一个参数中
+fn option_takes_value(wrapper: &str, option: &str) -> bool {
+    let options: &[&str] = match wrapper {
+        "sudo" => &["-u", "-g", "-h", "-p", "-C", "-D", "-r", "-t", "-U", "-T", "--user", "--group", "--host", "--prompt", "--chdir"],
+        "doas" => &["-u", "-C"],
+        "nice" => &["-n", "--adjustment"],
+        "ionice" => &["-c", "-n", "-p", "-P", "-u", "--class", "--classdata"],
+        "stdbuf" => &["-i", "-o", "-e"],
+        "xargs" => &["-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s", "--arg-file", "--delimiter", "--max-args", "--max-procs"],
+        "env" => &["-u", "-C", "-S", "--unset", "--chdir", "--split-string"],
+        "timeout" => &["-s", "-k", "--signal", "--kill-after"],
+        _ => &[],
+    };
+    options.contains(&option)
+}
+
+/// 词法分析，返回词法单元和需要递归解析的命令替换内容
+fn tokenize(input: &str) -> Result<(Vec<Token>, Vec<String>), String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut substitutions = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut heredocs: Vec<(String, bool)> = Vec::new();
+    let mut i = 0;
+
+    macro_rules! end_word {
+        () => {
+            if in_word {
+                tokens.push(Token::Word(std::mem::take(&mut word)));
+                in_word = false;
+            }
+        };
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => {
+                end_word!();
+                i += 1;
+            }
+            '\n' => {
+                end_word!();
+                tokens.push(Token::Op("\n".to_string()));
+                i += 1;
+                // 跳过 heredoc 正文
+                for (delimiter, strip_tabs) in heredocs.drain(..) {
+                    loop {
+                        if i >= chars.len() {
+                            break;
+                        }
+                        let end = chars[i..].iter().position(|c| *c == '\n').map_or(chars.len(), |p| i + p);
+                        let line: String = chars[i..end].iter().collect();
+                        i = (end + 1).min(chars.len());
+                        let line = if strip_tabs { line.trim_start_matches('\t') } else { line.as_str() };
+                        if line == delimiter {
+                            break;
+                        }
+                    }
+                }
+            }
+            '#' if !in_word => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\\' => {
+                if i + 1 < chars.len() && chars[i + 1] != '\n' {
+                    word.push(chars[i + 1]);
+                    in_word = true;
+                }
+                i += 2;
+            }
+            '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '\'')
+                    .ok_or("单引号未闭合")?;
+                word.extend(&chars[i + 1..i + 1 + end]);
+                in_word = true;
+                i += end + 2;
+            }
+            '"' => {
+                i += 1;
+                in_word = true;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("双引号未闭合".to_string()),
+                        Some('"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\' | '$' | '`')) => {
+                            word.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some('$') if chars.get(i + 1) == Some(&'(') => {
+                            let (inner, next) = read_substitution(&chars, i)?;
+                            word.push_str(&format!("$({})", inner));
+                            push_substitution(&mut substitutions, inner);
+                            i = next;
+                        }
+                        Some('`') => {
+                            let (inner, next) = read_backticks(&chars, i)?;
+                            word.push_str(&format!("`{}`", inner));
+                            substitutions.push(inner);
+                            i = next;
+                        }
+                        Some(&other) => {
+                            word.push(other);
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            '$' if chars.get(i + 1) == Some(&'(') => {
+                let (inner, next) = read_substitution(&chars, i)?;
+                word.push_str(&format!("$({})", inner));
+                push_substitution(&mut substitutions, inner);
+                in_word = true;
+                i = next;
+            }
+            '`' => {
+                let (inner, next) = read_backticks(&chars, i)?;
+                word.push_str(&format!("`{}`", inner));
+                substitutions.push(inner);
+                in_word = true;
+                i = next;
+            }
+            '|' | '&' | ';' | '(' | ')' | '<' | '>' => {
+                // 紧贴在重定向符号前的数字是文件描述符，例如 `2>`
+                let fd_prefix = if (c == '<' || c == '>') && in_word && word.chars().all(|c| c.is_ascii_digit()) {
+                    in_word = false;
+                    std::mem::take(&mut word)
+                } else {
+                    end_word!();
+                    String::new()
+                };
+
+                let op = read_operator(&chars, i);
+                i += op.chars().count();
+
+                if op == "<<" || op == "<<-" {
+                    // 记录 heredoc 结束标记，正文在下一个换行之后
+                    while chars.get(i).is_some_and(|c| *c == ' ' || *c == '\t') {
+                        i += 1;
+                    }
+                    let start = i;
+                    while chars.get(i).is_some_and(|c| !c.is_whitespace() && !"|&;()<>".contains(*c)) {
+                        i += 1;
+                    }
+                    let delimiter: String = chars[start..i].iter().filter(|c| !"'\"\\".contains(**c)).collect();
+                    heredocs.push((delimiter, op == "<<-"));
+                    continue;
+                }
+
+                tokens.push(Token::Op(format!("{}{}", fd_prefix, op)));
+            }
+            _ => {
+                word.push(c);
+                in_word = true;
+                i += 1;
+            }
+        }
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+
+    Ok((tokens, substitutions))
+}
+
+/// 记录需要递归解析的命令替换，`$((...))` 是算术展开，不是命令
+fn push_substitution(substitutions: &mut Vec<String>, inner: String) {
+    if !inner.starts_with('(') {
+        substitutions.push(inner);
+    }
+}
+
+/// 读取从 `start` 开始的运算符，优先匹配最长的
+fn read_operator(chars: &[char], start: usize) -> String {
+    const OPERATORS: &[&str] = &[
+        "&>>", "<<<", "<<-", "&&", "||", "|&", ";;", "&>", ">>", ">&", ">|", "<<", "<&", "<>", "|", "&", ";", "(",
+        ")", "<", ">",
+    ];
+    let rest: String = chars[start..chars.len().min(start + 3)].iter().collect();
+    OPERATORS
+        .iter()
+        .find(|op| rest.starts_with(*op))
+        .map(|op| op.to_string())
+        .unwrap_or_else(|| chars[start].to_string())
+}
+
+/// 读取 `$(...)`，返回括号内的内容和结束位置
+fn read_substitution(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    let mut depth = 0;
+    let mut i = start + 1;
+    let mut quote: Option<char> = None;
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some('"') if c == '\\' => i += 1,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '\\' => i += 1,
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let inner: String = chars[start + 2..i].iter().collect();
+                        return Ok((inner, i + 1));
+                    }
+                }
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    Err("命令替换 $( 未闭合".to_string())
+}
+
+/// 读取反引号命令替换，返回内容和结束位置
+fn read_backticks(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    let mut inner = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '`' => return Ok((inner, i + 1)),
+            '\\' if i + 1 < chars.len() => {
+                inner.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                inner.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err("反引号未闭合".to_string())
+}
+
+/// 系统关键目录，递归删除或修改它们视为破坏性操作
+const PROTECTED_PATHS: &[&str] = &[
+    "/", "/*", "~", "~/", "~/*", "$HOME", "$HOME/", "$HOME/*", "/bin", "/boot", "/dev", "/etc", "/home", "/lib",
+    "/lib64", "/opt", "/proc", "/root", "/sbin", "/sys", "/usr", "/var",
+];
+
+/// 检测已知的破坏性命令，返回原因
+fn detect_dangerous(command: &SimpleCommand) -> Option<String> {
+    let name = command.program_name();
+    let recursive = command.args.iter().any(|arg| {
+        arg == "--recursive" || (arg.starts_with('-') && !arg.starts_with("--") && (arg.contains('r') || arg.contains('R')))
+    });
+    let targets_protected = || {
+        command.args.iter().filter(|arg| !arg.starts_with('-')).find(|arg| {
+            let trimmed = if arg.len() > 1 { arg.trim_end_matches('/') } else { arg.as_str() };
+            PROTECTED_PATHS.contains(&trimmed) || PROTECTED_PATHS.contains(&arg.as_str())
+        })
+    };
+
+    match name {
+        "rm" if command.args.iter().any(|arg| arg == "--no-preserve-root") => {
+            return Some("检测到危险命令: rm --no-preserve-root".to_string());
+        }
+        "rm" if recursive => {
+            if let Some(target) = targets_protected() {
+                return Some(format!("检测到危险命令: 递归删除 {}", target));
+            }
+        }
+        "chmod" | "chown" | "chgrp" if recursive => {
+            if let Some(target) = targets_protected() {
+                return Some(format!("检测到危险命令: 递归修改 {} 的权限或所有者", target));
+            }
+        }
+        _ if name.starts_with("mkfs") || matches!(name, "mke2fs" | "wipefs" | "mkswap") => {
+            return Some(format!("检测到危险命令: {} 会格式化磁盘", name));
+        }
+        "dd" => {
+            if let Some(target) = command.args.iter().find_map(|arg| arg.strip_prefix("of=")) {
+                if is_block_device(target) {
+                    return Some(format!("检测到危险命令: dd 写入设备 {}", target));
+                }
+            }
+        }
+        "shred" if command.args.iter().any(|arg| is_block_device(arg)) => {
+            return Some("检测到危险命令: shred 擦除设备".to_string());
+        }
+        _ => {}
+    }
+
+    command
+        .redirects
+        .iter()
+        .find(|target| is_block_device(target))
+        .map(|target| format!("检测到危险命令: 重定向写入设备 {}", target))
+}
+
+/// 是否为磁盘等块设备路径
+fn is_block_device(path: &str) -> bool {
+    match path.strip_prefix("/dev/") {
+        Some(device) => !matches!(
+            device,
+            "null" | "zero" | "stdout" | "stderr" | "stdin" | "tty" | "random" | "urandom" | "full"
+        ) && !device.starts_with("fd/")
+            && !device.starts_with("pts/")
+            && !device.starts_with("shm/"),
+        None => false,
+    }
+}
+
+/// 检测 `:(){ :|:& };:` 形式的 fork 炸弹
+fn detect_fork_bomb(command: &str) -> Option<String> {
+    let compact: String = command.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut search = compact.as_str();
+    while let Some(pos) = search.find("(){") {
+        let name_start = search[..pos]
+            .rfind([';', '&', '|', '{', '}', '(', ')'])
+            .map_or(0, |p| p + 1);
+        let name = search[name_start..pos].trim_start_matches("function");
+        if !name.is_empty() && (compact.contains(&format!("{}|{}&", name, name)) || compact.contains(&format!("{}&{}&", name, name))) {
+            return Some("检测到危险命令: fork 炸弹".to_string());
+        }
+        search = &search[pos + 3..];
+    }
+    None
+}
+
+/// 通配符匹配，`*` 匹配任意字符，`?` 匹配单个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<Token> {
+        tokenize(input).unwrap().0
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.to_string())
+    }
+
+    fn op(text: &str) -> Token {
+        Token::Op(text.to_string())
+    }
+
+    fn programs(command: &str) -> Vec<String> {
+        parse(command).unwrap().into_iter().map(|simple| simple.program).collect()
+    }
+
+    #[test]
+    fn tokenize_handles_quotes_and_escapes() {
+        assert_eq!(words(r#"echo 'a b' "c \"d\" $x" e\ f"#), vec![
+            word("echo"),
+            word("a b"),
+            word(r#"c "d" $x"#),
+            word("e f"),
+        ]);
+        assert_eq!(words("echo ''"), vec![word("echo"), word("")]);
+        assert!(tokenize("echo 'open").is_err());
+        assert!(tokenize("echo \"open").is_err());
+    }
+
+    #[test]
+    fn tokenize_splits_operators_and_fd_redirects() {
+        assert_eq!(words("a|b&&c;d 2>&1 >>log"), vec![
+            word("a"),
+            op("|"),
+            word("b"),
+            op("&&"),
+            word("c"),
+            op(";"),
+            word("d"),
+            op("2>&"),
+            word("1"),
+            op(">>"),
+            word("log"),
+        ]);
+        // 不紧贴重定向的数字是普通参数
+        assert_eq!(words("echo 2 > f"), vec![word("echo"), word("2"), op(">"), word("f")]);
+    }
+
+    #[test]
+    fn tokenize_skips_comments_and_heredoc_bodies() {
+        assert_eq!(words("echo a # rm -rf /"), vec![word("echo"), word("a")]);
+        assert_eq!(words("echo a#b"), vec![word("echo"), word("a#b")]);
+        let tokens = words("cat <<-'EOF'\n\trm -rf /\n\tEOF\necho done");
+        assert_eq!(tokens, vec![word("cat"), op("\n"), word("echo"), word("done")]);
+    }
+
+    #[test]
+    fn tokenize_collects_substitutions() {
+        let (_, substitutions) = tokenize("echo $(rm x) \"`ls`\" $((1 + 2))").unwrap();
+        assert_eq!(substitutions, vec!["rm x".to_string(), "ls".to_string()]);
+    }
+
+    #[test]
+    fn parse_unwraps_wrappers_and_nested_scripts() {
+        assert_eq!(programs("FOO=1 sudo -u root env A=b rm -rf x"), vec!["sudo", "env", "rm"]);
+        assert_eq!(programs("bash -c 'ls; git push'"), vec!["bash", "ls", "git"]);
+        assert_eq!(programs("eval \"touch a\""), vec!["eval", "touch"]);
+        assert_eq!(programs("timeout 5 curl x"), vec!["timeout", "curl"]);
+        assert_eq!(programs("for f in *; do rm $f; done"), vec!["rm"]);
+        assert_eq!(programs("if true; then (cd x && make); fi"), vec!["true", "cd", "make"]);
+    }
+
+    #[test]
+    fn parse_records_redirect_targets() {
+        let commands = parse("echo hi > /etc/x 2>&1 < in").unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].redirects, vec!["/etc/x".to_string(), "in".to_string()]);
+        let commands = parse("> out").unwrap();
+        assert_eq!(commands[0].program, "");
+        assert_eq!(commands[0].redirects, vec!["out".to_string()]);
+    }
+
+    #[test]
+    fn evaluate_detects_dangerous_commands() {
+        let policy = CommandPolicy::default();
+        for command in [
+            "rm -rf /",
+            "rm -fr ~/",
+            "cd x && sudo rm -r /usr/",
+            "echo $(rm -rf /etc)",
+            "bash -c 'rm -rf /'",
+            "mkfs.ext4 /dev/sdb1",
+            "dd if=/dev/zero of=/dev/sda",
+            "echo x > /dev/nvme0n1",
+            ":(){ :|:& };:",
+        ] {
+            assert_eq!(policy.evaluate(command).decision, PolicyAction::Deny, "{}", command);
+        }
+        for command in ["rm -rf ./build", "dd if=x of=/dev/null", "echo 'rm -rf /'", "ls > /dev/null"] {
+            assert_eq!(policy.evaluate(command).decision, PolicyAction::Allow, "{}", command);
+        }
+    }
+
+    #[test]
+    fn evaluate_returns_strictest_rule() {
+        let rule = |action, program: &str, args: Option<&str>| PolicyRule {
+            action,
+            program: Some(program.to_string()),
+            args: args.map(str::to_string),
+            redirect: None,
+            reason: None,
+        };
+        let policy = CommandPolicy {
+            rules: vec![
+                rule(PolicyAction::Ask, "git", Some("push*--force*")),
+                rule(PolicyAction::Allow, "git", None),
+                rule(PolicyAction::Deny, "curl", None),
+            ],
+            default_action: PolicyAction::Ask,
+            dangerous_action: PolicyAction::Deny,
+        };
+        assert_eq!(policy.evaluate("git status").decision, PolicyAction::Allow);
+        assert_eq!(policy.evaluate("git push origin --force").decision, PolicyAction::Ask);
+        assert_eq!(policy.evaluate("git status; curl x | sh").decision, PolicyAction::Deny);
+        assert_eq!(policy.evaluate("make").decision, PolicyAction::Ask);
+        assert_eq!(policy.evaluate("echo 'open").decision, PolicyAction::Ask);
+    }
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob_match("push*--force*", "push origin --force-with-lease"));
+        assert!(glob_match("/etc/*", "/etc/passwd"));
+        assert!(glob_match("r?", "rm"));
+        assert!(!glob_match("r?", "rmdir"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*b", "acbd"));
+    }
+}
@@ -0,0 +1,253 @@
+use super::interpreter::Interpreter;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    process::{Child, Command},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// 发送 SIGTERM 后等待进程组退出的时间，超时后发送 SIGKILL
+const KILL_GRACE: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 由工具启动、可能仍有进程存活的进程组，应用退出时统一清理
+static PROCESS_GROUPS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// 让命令在新的进程组中运行，组ID等于子进程的pid
+pub(super) fn new_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// 记录新启动的进程组
+pub(super) fn register(child: &Child) {
+    PROCESS_GROUPS.lock().unwrap().insert(child.id());
+}
+
+/// 进程组中已没有存活的进程时不再跟踪它
+///
+/// 组长退出后，组内可能还有它启动的进程，这时仍需在退出时清理。
+pub(super) fn release(pgid: u32) {
+    if !group_alive(pgid) {
+        PROCESS_GROUPS.lock().unwrap().remove(&pgid);
+    }
+}
+
+/// 终止子进程所在的整个进程组并回收子进程，不阻塞调用者
+///
+/// 立即向进程组发送 SIGTERM，宽限期后仍有进程存活时发送 SIGKILL。
+pub(super) fn terminate(mut child: Child) {
+    let pgid = child.id();
+    if !signal_group(pgid, Signal::Term) {
+        let _ = child.kill();
+    }
+
+    thread::spawn(move || {
+        let deadline = Instant::now() + KILL_GRACE;
+        loop {
+            // 回收组长，否则僵尸进程会让进程组一直存在
+            let _ = child.try_wait();
+            if !group_alive(pgid) {
+                break;
+            }
+            if Instant::now() >= deadline {
+                warn!("进程组 {} 未在 {} 秒内退出，发送 SIGKILL", pgid, KILL_GRACE.as_secs());
+                signal_group(pgid, Signal::Kill);
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        let _ = child.wait();
+        PROCESS_GROUPS.lock().unwrap().remove(&pgid);
+    });
+}
+
+/// 终止所有由工具启动的进程组，等待它们退出后返回
+///
+/// 应用退出时调用，保证不会遗留任何子进程。
+pub fn terminate_all_processes() {
+    let groups: Vec<u32> = std::mem::take(&mut *PROCESS_GROUPS.lock().unwrap())
+        .into_iter()
+        .filter(|pgid| group_alive(*pgid))
+        .collect();
+    if groups.is_empty() {
+        return;
+    }
+    info!("正在终止 {} 个进程组", groups.len());
+
+    for pgid in &groups {
+        signal_group(*pgid, Signal::Term);
+    }
+    let deadline = Instant::now() + KILL_GRACE;
+    while Instant::now() < deadline && groups.iter().any(|pgid| group_alive(*pgid)) {
+        thread::sleep(POLL_INTERVAL);
+    }
+    for pgid in groups.iter().filter(|pgid| group_alive(**pgid)) {
+        warn!("进程组 {} 未在 {} 秒内退出，发送 SIGKILL", pgid, KILL_GRACE.as_secs());
+        signal_group(*pgid, Signal::Kill);
+    }
+}
+
+/// 可以发送给正在执行的命令的信号
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CommandSignal {
+    /// 中断，相当于 Ctrl+C
+    Sigint,
+    /// 请求终止
+    Sigterm,
+    /// 暂停，相当于 Ctrl+Z
+    Sigtstp,
+    /// 继续运行被暂停的进程
+    Sigcont,
+}
+
+impl CommandSignal {
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandSignal::Sigint => "SIGINT",
+            CommandSignal::Sigterm => "SIGTERM",
+            CommandSignal::Sigtstp => "SIGTSTP",
+            CommandSignal::Sigcont => "SIGCONT",
+        }
+    }
+}
+
+/// 向单个进程发送信号，成功时返回 true
+#[cfg(unix)]
+pub(super) fn send_signal(pid: u32, signal: CommandSignal) -> bool {
+    let signal = match signal {
+        CommandSignal::Sigint => libc::SIGINT,
+        CommandSignal::Sigterm => libc::SIGTERM,
+        CommandSignal::Sigtstp => libc::SIGTSTP,
+        CommandSignal::Sigcont => libc::SIGCONT,
+    };
+    unsafe { libc::kill(pid as libc::pid_t, signal) == 0 }
+}
+
+#[cfg(not(unix))]
+pub(super) fn send_signal(_pid: u32, _signal: CommandSignal) -> bool {
+    false
+}
+
+/// `root` 及其所有子孙进程的pid
+pub(super) fn process_tree(root: u32) -> Vec<u32> {
+    descendants(&list_processes(), root, true)
+}
+
+/// 会话中正在执行命令的进程
+///
+/// 启用沙箱时会话进程是 bwrap 或 unshare，解释器在其下层，
+/// 因此取从 `root` 开始遇到的第一个解释器进程的所有子孙进程，
+/// `include_interpreter` 为 true 时包括解释器自身。
+pub(super) fn command_processes(root: u32, interpreter: Interpreter, include_interpreter: bool) -> Vec<u32> {
+    find_command_processes(&list_processes(), root, interpreter, include_interpreter)
+}
+
+/// 会话中正在执行命令的进程里是否有 shell（不包括 `excluded` 中的进程），
+/// 例如命令又启动了一个读取标准输入的 bash
+pub(super) fn command_runs_shell(root: u32, interpreter: Interpreter, excluded: &[u32]) -> bool {
+    let processes = list_processes();
+    let pids = find_command_processes(&processes, root, interpreter, false);
+    processes.iter().any(|p| {
+        pids.contains(&p.pid)
+            && !excluded.contains(&p.pid)
+            && ([Interpreter::Bash, Interpreter::Sh, Interpreter::Zsh].iter().any(|shell| shell.is_process(&p.name))
+                || matches!(p.name.rsplit('/').next(), Some("ksh" | "mksh" | "fish")))
+    })
+}
+
+fn find_command_processes(
+    processes: &[ProcessInfo],
+    root: u32,
+    interpreter: Interpreter,
+    include_interpreter: bool,
+) -> Vec<u32> {
+    let mut queue = VecDeque::from([root]);
+    while let Some(pid) = queue.pop_front() {
+        if processes.iter().any(|p| p.pid == pid && interpreter.is_process(&p.name)) {
+            return descendants(processes, pid, include_interpreter);
+        }
+        queue.extend(processes.iter().filter(|p| p.ppid == pid).map(|p| p.pid));
+    }
+    Vec::new()
+}
+
+struct ProcessInfo {
+    pid: u32,
+    ppid: u32,
+    name: String,
+}
+
+/// 通过 ps 列出所有进程，Linux 和 macOS 通用
+fn list_processes() -> Vec<ProcessInfo> {
+    let output = match Command::new("ps").args(["-A", "-o", "pid=,ppid=,comm="]).output() {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("列出进程失败: {}", e);
+            return Vec::new();
+        }
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(ProcessInfo {
+                pid: fields.next()?.parse().ok()?,
+                ppid: fields.next()?.parse().ok()?,
+                name: fields.collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect()
+}
+
+fn descendants(processes: &[ProcessInfo], root: u32, include_root: bool) -> Vec<u32> {
+    let mut result = if include_root { vec![root] } else { Vec::new() };
+    let mut parents = vec![root];
+    while let Some(parent) = parents.pop() {
+        for process in processes.iter().filter(|p| p.ppid == parent) {
+            result.push(process.pid);
+            parents.push(process.pid);
+        }
+    }
+    result
+}
+
+enum Signal {
+    Term,
+    Kill,
+}
+
+/// 向进程组发送信号，进程组不存在时返回 false
+#[cfg(unix)]
+fn signal_group(pgid: u32, signal: Signal) -> bool {
+    let signal = match signal {
+        Signal::Term => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    unsafe { libc::kill(-(pgid as libc::pid_t), signal) == 0 }
+}
+
+#[cfg(not(unix))]
+fn signal_group(_pgid: u32, _signal: Signal) -> bool {
+    false
+}
+
+/// 进程组中是否还有进程（包括未回收的僵尸进程）
+#[cfg(unix)]
+fn group_alive(pgid: u32) -> bool {
+    unsafe { libc::kill(-(pgid as libc::pid_t), 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn group_alive(_pgid: u32) -> bool {
+    false
+}
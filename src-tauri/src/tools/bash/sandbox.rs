@@ -0,0 +1,166 @@
+use crate::tools::base::ToolError;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// 命名空间模式下挂载新根目录的位置，仅在私有挂载命名空间内使用
+const SANDBOX_ROOT: &str = "/tmp/.maestro-sandbox-root";
+
+/// 在用户命名空间内以 root 身份执行的初始化脚本
+///
+/// 把整个文件系统递归绑定到新根目录并重新挂载为只读，在其上挂载私有的
+/// /tmp 和可写的工作区，最后 chroot 进去执行目标程序。重新挂载时保留挂载点原有的选项，
+/// 用户命名空间中被锁定的选项（如 nosuid、nodev）不能去掉。任何一个挂载点无法变为只读时
+/// 中止启动，不会在可写的系统上继续运行。
+/// 参数: $1 新根目录，$2 工作区，$3 初始目录，其余为要执行的程序及参数。
+const NAMESPACE_SETUP_SCRIPT: &str = r#"set -e
+root="$1"; ws="$2"; cwd="$3"; shift 3
+fail() { echo "沙箱启动失败: $1" >&2; exit 1; }
+mkdir -p "$root"
+mount --rbind / "$root"
+findmnt -rno TARGET,VFS-OPTIONS -R "$root" | sort -r | while read -r m options; do
+    m=$(printf '%b' "$m")
+    case "$m" in "$root/dev"*|"$root/proc"*|"$root/sys"*) continue ;; esac
+    mount -o "remount,bind,$options,ro" "$m" || fail "无法把 $m 重新挂载为只读"
+done
+mount -t tmpfs tmpfs "$root/tmp"
+mkdir -p "$root$ws"
+mount --bind "$ws" "$root$ws"
+mount -t proc proc "$root/proc" || fail "无法挂载 /proc"
+exec chroot "$root" /bin/sh -c 'cd "$0" && exec "$@"' "$cwd" "$@"
+"#;
+
+/// 沙箱模式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxMode {
+    /// 不使用沙箱，直接以当前用户权限运行
+    #[default]
+    Off,
+    /// 优先使用 bubblewrap，未安装时使用 Linux 命名空间
+    Auto,
+    /// 使用 bubblewrap (bwrap)
+    Bubblewrap,
+    /// 使用 unshare 创建的 Linux 用户、挂载、PID 和网络命名空间
+    Namespaces,
+}
+
+/// 沙箱配置
+///
+/// 启用后系统目录只读，工作区可写，/tmp 为私有目录。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SandboxConfig {
+    #[serde(default)]
+    pub mode: SandboxMode,
+    /// 是否禁止访问网络
+    #[serde(default)]
+    pub no_network: bool,
+}
+
+impl SandboxConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.mode != SandboxMode::Off
+    }
+
+    /// 检查当前系统是否支持所选的沙箱模式
+    pub fn validate(&self) -> Result<(), ToolError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if !cfg!(target_os = "linux") {
+            return Err(ToolError::new("沙箱模式仅支持 Linux"));
+        }
+        self.backend().map(|_| ())
+    }
+
+    /// 构造在沙箱中从 `cwd` 运行 `program` 的命令；未启用沙箱时直接运行
+    pub fn command(&self, workspace: &Path, cwd: &Path, program: &[&str]) -> Result<Command, ToolError> {
+        let backend = match self.mode {
+            SandboxMode::Off => {
+                let mut command = Command::new(program[0]);
+                command.args(&program[1..]).current_dir(cwd);
+                return Ok(command);
+            }
+            _ => self.backend()?,
+        };
+
+        let workspace = workspace.to_string_lossy();
+        let cwd = cwd.to_string_lossy();
+        let mut command = match backend {
+            Backend::Bubblewrap(bwrap) => {
+                let mut command = Command::new(bwrap);
+                command
+                    .args(["--ro-bind", "/", "/"])
+                    .args(["--dev", "/dev"])
+                    .args(["--proc", "/proc"])
+                    .args(["--tmpfs", "/tmp"])
+                    .args(["--bind", &workspace, &workspace])
+                    .args(["--chdir", &cwd])
+                    .args(["--unshare-user", "--unshare-pid", "--die-with-parent"]);
+                if self.no_network {
+                    command.arg("--unshare-net");
+                }
+                command.arg("--");
+                command
+            }
+            Backend::Namespaces(unshare) => {
+                let mut command = Command::new(unshare);
+                command.args(["--user", "--map-root-user", "--mount", "--pid", "--fork", "--kill-child"]);
+                if self.no_network {
+                    command.arg("--net");
+                }
+                command
+                    .args(["--", "/bin/sh", "-c", NAMESPACE_SETUP_SCRIPT, "maestro-sandbox"])
+                    .args([SANDBOX_ROOT, &workspace, &cwd]);
+                command
+            }
+        };
+
+        command.args(program);
+        Ok(command)
+    }
+
+    /// 描述沙箱配置，用于返回给模型
+    pub fn describe(&self) -> String {
+        let mode = match self.mode {
+            SandboxMode::Off => return "沙箱已关闭".to_string(),
+            SandboxMode::Auto => "自动",
+            SandboxMode::Bubblewrap => "bubblewrap",
+            SandboxMode::Namespaces => "Linux 命名空间",
+        };
+        format!(
+            "沙箱已启用（{}）：系统目录只读，工作区可写，/tmp 为私有目录，网络{}",
+            mode,
+            if self.no_network { "已禁用" } else { "可用" }
+        )
+    }
+
+    fn backend(&self) -> Result<Backend, ToolError> {
+        let bwrap = find_in_path("bwrap");
+        let unshare = find_in_path("unshare");
+
+        match (self.mode, bwrap, unshare) {
+            (SandboxMode::Bubblewrap | SandboxMode::Auto, Some(bwrap), _) => Ok(Backend::Bubblewrap(bwrap)),
+            (SandboxMode::Bubblewrap, None, _) => Err(ToolError::new("未找到 bwrap，请先安装 bubblewrap")),
+            (SandboxMode::Namespaces | SandboxMode::Auto, _, Some(unshare)) => Ok(Backend::Namespaces(unshare)),
+            _ => Err(ToolError::new("未找到 bwrap 或 unshare，无法启用沙箱")),
+        }
+    }
+}
+
+/// 实际使用的沙箱实现
+enum Backend {
+    Bubblewrap(PathBuf),
+    Namespaces(PathBuf),
+}
+
+/// 在 PATH 中查找可执行文件
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
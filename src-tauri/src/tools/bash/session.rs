@@ -0,0 +1,695 @@
+use super::{
+    interpreter::Interpreter,
+    process::{self, CommandSignal},
+    state::{self, SessionState},
+};
+use crate::tools::{base::ToolError, target::ExecutionTarget};
+use base64::{engine::general_purpose, Engine as _};
+use log::warn;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read, Write},
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    thread,
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender},
+    time::{timeout_at, Instant},
+};
+use uuid::Uuid;
+
+const MAX_OUTPUT_SIZE: usize = 1024 * 1024; // 限制输出大小为 1MB
+const TRUNCATED_MESSAGE: &str = "\n... 输出过大，已截断 ...";
+const SPILL_DIR: &str = "maestro-output";
+/// 命令没有输出但尚未等待输入时，再次检查的间隔
+const IDLE_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// 判断提示符时检查的输出末尾长度
+const PROMPT_TAIL_SIZE: usize = 256;
+
+/// 从bash进程读取到的一段输出
+enum OutputChunk {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+}
+
+/// 一条命令的执行结果
+///
+/// 如果之前已经返回过部分结果，这里只包含之后的输出。
+pub(super) struct CommandOutput {
+    /// 命令的标准输出和标准错误（已合并）
+    pub output: String,
+    /// bash自身输出到标准错误的内容
+    pub stderr: String,
+    /// 命令的退出码
+    pub exit_code: i32,
+    /// 命令执行后shell所在的目录
+    pub cwd: Option<String>,
+}
+
+/// 等待命令的结果
+pub(super) enum RunStatus {
+    /// 命令已结束
+    Finished(CommandOutput),
+    /// 命令一段时间没有输出，并且在等待输入
+    WaitingForInput(PartialOutput),
+    /// 等待时间已到，命令仍在运行
+    Running(PartialOutput),
+}
+
+/// 命令仍在运行时，自上次返回以来的新输出
+#[derive(Default)]
+pub(super) struct PartialOutput {
+    pub output: String,
+    pub stderr: String,
+}
+
+/// 正在执行的命令的输出状态
+struct PendingCommand {
+    /// 哨兵行的开头
+    marker: Vec<u8>,
+    /// 可能属于哨兵的尾部输出
+    pending: Vec<u8>,
+    output: OutputBuffer,
+    stderr: Vec<u8>,
+    /// 已通过部分结果返回的输出和标准错误的字节数
+    reported: (usize, usize),
+    /// 命令开始前就已存在的进程（之前命令留下的后台进程），不向它们发送信号
+    existing_processes: Vec<u32>,
+}
+
+/// 一个运行中的bash进程
+///
+/// 命令以 base64 编码后通过 `eval` 在当前 shell 中执行，因此 `$`、反引号、
+/// 反斜杠、heredoc 和多行脚本都无需转义，`cd`、`export` 等状态也会保留。
+/// 每条命令使用随机生成的哨兵标记结束位置，并在哨兵后附带退出码和当前目录。
+/// bash运行在独立的进程组中，终止会话时命令启动的所有进程会一起被终止。
+///
+/// Python 和 Node.js 解释器使用相同的协议，由驱动脚本解码并执行代码，见 `Interpreter`。
+pub(super) struct BashSession {
+    /// bash进程，终止后为 None
+    process: Option<Child>,
+    /// 会话中运行的解释器
+    interpreter: Interpreter,
+    stdin: ChildStdin,
+    output_rx: UnboundedReceiver<OutputChunk>,
+    /// 正在执行的命令
+    command: Option<PendingCommand>,
+    /// 在远程目标上运行时，目标和远程bash的pid
+    remote: Option<(ExecutionTarget, u32)>,
+    /// shell启动时的环境变量，会话状态相对于它计算
+    baseline_env: Option<HashMap<String, String>>,
+}
+
+impl BashSession {
+    /// 用准备好的命令启动一个新的解释器进程
+    pub fn start(mut command: Command, interpreter: Interpreter) -> Result<Self, ToolError> {
+        process::new_process_group(&mut command);
+        let mut process = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| ToolError::new(format!("启动{}进程失败: {}", interpreter.name(), e)))?;
+        process::register(&process);
+
+        let stdin = process
+            .stdin
+            .take()
+            .ok_or_else(|| ToolError::new("无法获取bash进程的stdin"))?;
+        let stdout = process
+            .stdout
+            .take()
+            .ok_or_else(|| ToolError::new("无法获取bash进程的stdout"))?;
+        let stderr = process
+            .stderr
+            .take()
+            .ok_or_else(|| ToolError::new("无法获取bash进程的stderr"))?;
+
+        // 用后台线程持续读取输出，避免阻塞读取导致超时失效
+        let (tx, output_rx) = mpsc::unbounded_channel();
+        spawn_reader(stdout, tx.clone(), OutputChunk::Stdout);
+        spawn_reader(stderr, tx, OutputChunk::Stderr);
+
+        Ok(Self {
+            process: Some(process),
+            interpreter,
+            stdin,
+            output_rx,
+            command: None,
+            remote: None,
+            baseline_env: None,
+        })
+    }
+
+    /// 在执行第一条命令之前向解释器写入一行输入，用于把设置脚本传给远程目标上的包装脚本
+    pub fn write_line(&mut self, line: &str) -> Result<(), ToolError> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| ToolError::new(format!("写入{}进程失败: {}", self.interpreter.name(), e)))
+    }
+
+    /// 记录远程bash的pid
+    ///
+    /// 本地只有 ssh 或容器运行时的客户端进程，发送信号和终止会话都需要在目标上进行。
+    pub fn set_remote(&mut self, target: ExecutionTarget, pid: u32) {
+        self.remote = Some((target, pid));
+    }
+
+    /// 记录shell当前的环境变量作为基准，解释器不支持恢复状态时什么也不做
+    pub async fn record_baseline(&mut self, timeout: Duration) -> Result<(), ToolError> {
+        let Some(capture) = self.interpreter.capture_command() else {
+            return Ok(());
+        };
+        let output = self
+            .run(capture, timeout)
+            .await?
+            .ok_or_else(|| ToolError::new("读取shell环境超时"))?;
+        self.baseline_env = Some(state::parse_capture(&output.output).0);
+        Ok(())
+    }
+
+    /// 记录当前目录、相对于基准的环境变量差异和别名，没有基准时返回 None
+    pub async fn capture_state(&mut self, timeout: Duration) -> Result<Option<SessionState>, ToolError> {
+        let (Some(capture), Some(_)) = (self.interpreter.capture_command(), &self.baseline_env) else {
+            return Ok(None);
+        };
+        let output = self
+            .run(capture, timeout)
+            .await?
+            .ok_or_else(|| ToolError::new("读取shell环境超时"))?;
+        let (env, aliases) = state::parse_capture(&output.output);
+        let baseline = self.baseline_env.as_ref().expect("基准环境已记录");
+        Ok(Some(SessionState::diff(baseline, env, aliases, output.cwd)))
+    }
+
+    /// 检查bash进程是否已退出，返回退出码
+    pub fn exit_code(&mut self) -> Result<Option<i32>, ToolError> {
+        let Some(child) = self.process.as_mut() else {
+            return Ok(Some(-1));
+        };
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                process::release(child.id());
+                Ok(Some(status.code().unwrap_or(-1)))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(ToolError::new(format!("检查bash进程状态失败: {}", e))),
+        }
+    }
+
+    /// 执行命令并等待其完成，超时返回 `Ok(None)`
+    ///
+    /// 超时后无法确定命令何时结束，进程会被终止，会话不能再使用。
+    pub async fn run(&mut self, command: &str, timeout: Duration) -> Result<Option<CommandOutput>, ToolError> {
+        self.send(command)?;
+        match self.wait(timeout, None).await? {
+            RunStatus::Finished(output) => Ok(Some(output)),
+            RunStatus::WaitingForInput(_) | RunStatus::Running(_) => {
+                self.kill();
+                self.command = None;
+                Ok(None)
+            }
+        }
+    }
+
+    /// 是否有命令正在执行
+    pub fn is_busy(&self) -> bool {
+        self.command.is_some()
+    }
+
+    /// 开始执行命令，不等待其完成
+    pub fn send(&mut self, command: &str) -> Result<(), ToolError> {
+        if self.command.is_some() {
+            return Err(ToolError::new("上一条命令仍在运行"));
+        }
+        // 进程启动后立即退出（如沙箱初始化失败）时，报告它在 stderr 中给出的原因
+        if self.exit_code()?.is_some() {
+            let mut stderr = Vec::new();
+            while let Ok(chunk) = self.output_rx.try_recv() {
+                if let OutputChunk::Stderr(data) = chunk {
+                    stderr.extend(data);
+                }
+            }
+            return Err(exited_error(&stderr));
+        }
+
+        let sentinel = format!("__MAESTRO_DONE_{}__", Uuid::new_v4().simple());
+        // 哨兵分两段输出，保证命令行本身不包含完整的哨兵字符串
+        let (head, tail) = sentinel.split_at(sentinel.len() / 2);
+        let encoded = general_purpose::STANDARD.encode(command);
+
+        // 清理上一条命令残留的输出
+        while self.output_rx.try_recv().is_ok() {}
+        let existing_processes = self
+            .process
+            .as_ref()
+            .map(|child| process::command_processes(child.id(), self.interpreter, false))
+            .unwrap_or_default();
+
+        let wrapped_command = self.interpreter.wrap(&encoded, head, tail);
+        writeln!(self.stdin, "{}", wrapped_command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| ToolError::new(format!("写入命令失败: {}", e)))?;
+
+        self.command = Some(PendingCommand {
+            marker: format!("\n{}:", sentinel).into_bytes(),
+            pending: Vec::new(),
+            output: OutputBuffer::default(),
+            stderr: Vec::new(),
+            reported: (0, 0),
+            existing_processes,
+        });
+        Ok(())
+    }
+
+    /// 正在执行的命令中是否有 shell，写入的内容可能被它当作命令执行
+    ///
+    /// 远程目标上无法检查进程，按有 shell 处理。
+    pub fn runs_shell(&self) -> bool {
+        let Some(command) = &self.command else {
+            return false;
+        };
+        if self.remote.is_some() {
+            return true;
+        }
+        self.process.as_ref().is_some_and(|child| {
+            process::command_runs_shell(child.id(), self.interpreter, &command.existing_processes)
+        })
+    }
+
+    /// 向正在执行的命令的标准输入写入内容
+    pub fn write_input(&mut self, input: &str) -> Result<(), ToolError> {
+        if self.command.is_none() {
+            return Err(ToolError::new("当前没有正在运行的命令"));
+        }
+        self.stdin
+            .write_all(input.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| ToolError::new(format!("写入标准输入失败: {}", e)))
+    }
+
+    /// 向正在执行的命令的进程发送信号，返回收到信号的进程数
+    ///
+    /// 信号只发给这条命令启动的进程，不会发给bash自身和之前留下的后台进程。
+    /// 在远程目标上信号会发给远程bash的所有子孙进程，包括之前留下的后台进程。
+    /// Python 和 Node.js 在解释器进程中执行代码，信号同时发给解释器自身。
+    pub fn signal(&mut self, signal: CommandSignal) -> Result<usize, ToolError> {
+        let Some(command) = &self.command else {
+            return Err(ToolError::new("当前没有正在运行的命令"));
+        };
+        let include_interpreter = !self.interpreter.is_shell();
+        if let Some((target, pid)) = &self.remote {
+            return target.signal_tree(*pid, signal.name().trim_start_matches("SIG"), include_interpreter);
+        }
+        let child = self
+            .process
+            .as_ref()
+            .ok_or_else(|| ToolError::new("bash进程已退出，工具需要重启"))?;
+
+        let targets = process::command_processes(child.id(), self.interpreter, include_interpreter);
+        Ok(targets
+            .into_iter()
+            .filter(|pid| !command.existing_processes.contains(pid))
+            .filter(|pid| process::send_signal(*pid, signal))
+            .count())
+    }
+
+    /// 等待正在执行的命令
+    ///
+    /// 最多等待 `timeout`。提供 `idle` 时，如果命令超过这段时间没有输出并且
+    /// 看起来在等待输入，则提前返回已有的输出，命令继续运行。
+    pub async fn wait(&mut self, timeout: Duration, idle: Option<Duration>) -> Result<RunStatus, ToolError> {
+        if self.command.is_none() {
+            return Err(ToolError::new("当前没有正在运行的命令"));
+        }
+        let deadline = Instant::now() + timeout;
+        let mut next_check = idle.map(|idle| Instant::now() + idle);
+
+        loop {
+            let wake = next_check.map_or(deadline, |check| check.min(deadline));
+            let chunk = match timeout_at(wake, self.output_rx.recv()).await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    let stderr = self.command.take().map(|command| command.stderr).unwrap_or_default();
+                    return Err(exited_error(&stderr));
+                }
+                Err(_) if Instant::now() >= deadline => {
+                    return Ok(RunStatus::Running(self.take_partial()));
+                }
+                Err(_) => {
+                    if self.waiting_for_input() {
+                        return Ok(RunStatus::WaitingForInput(self.take_partial()));
+                    }
+                    next_check = Some(Instant::now() + IDLE_RECHECK_INTERVAL);
+                    continue;
+                }
+            };
+            if let Some(idle) = idle {
+                next_check = Some(Instant::now() + idle);
+            }
+
+            let Some(command) = self.command.as_mut() else {
+                return Err(ToolError::new("当前没有正在运行的命令"));
+            };
+            match chunk {
+                OutputChunk::Stderr(data) => command.stderr.extend(data),
+                OutputChunk::Stdout(data) => {
+                    command.pending.extend(data);
+
+                    if let Some(pos) = find(&command.pending, &command.marker) {
+                        let rest = &command.pending[pos + command.marker.len()..];
+                        if let Some(end) = rest.iter().position(|b| *b == b'\n') {
+                            // 哨兵行格式为 `<退出码>:<当前目录>`
+                            let status = String::from_utf8_lossy(&rest[..end]).into_owned();
+                            let (code, cwd) = status.split_once(':').unwrap_or((status.as_str(), ""));
+                            let exit_code = code.trim().parse().unwrap_or(-1);
+                            let cwd = if cwd.is_empty() { None } else { Some(cwd.to_string()) };
+                            let output: Vec<u8> = command.pending.drain(..pos).collect();
+                            command.output.append(&output);
+                            return Ok(RunStatus::Finished(self.finish(exit_code, cwd)));
+                        }
+                        // 哨兵所在行尚未读完整
+                        continue;
+                    }
+
+                    // 只保留可能是哨兵开头的尾部字节，其余移入输出
+                    let keep = marker_prefix_len(&command.pending, &command.marker);
+                    let flush = command.pending.len() - keep;
+                    if flush > 0 {
+                        let flushed: Vec<u8> = command.pending.drain(..flush).collect();
+                        command.output.append(&flushed);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 命令结束后收集剩余输出，返回上次部分结果之后的内容
+    fn finish(&mut self, exit_code: i32, cwd: Option<String>) -> CommandOutput {
+        let mut command = self.command.take().expect("命令必须处于执行状态");
+
+        // 收集命令结束时已到达的标准错误
+        loop {
+            match self.output_rx.try_recv() {
+                Ok(OutputChunk::Stderr(data)) => command.stderr.extend(data),
+                Ok(OutputChunk::Stdout(data)) => warn!("哨兵之后出现意外输出: {} 字节", data.len()),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        let (reported_output, reported_stderr) = command.reported;
+        CommandOutput {
+            output: command.output.finish(reported_output),
+            stderr: String::from_utf8_lossy(&command.stderr[reported_stderr..]).into_owned(),
+            exit_code,
+            cwd,
+        }
+    }
+
+    /// 取出自上次返回以来的新输出，末尾不完整的 UTF-8 字符留到下一次
+    fn take_partial(&mut self) -> PartialOutput {
+        let Some(command) = self.command.as_mut() else {
+            return PartialOutput::default();
+        };
+        let (reported_output, reported_stderr) = &mut command.reported;
+
+        let output = &command.output.data[*reported_output..];
+        let output_len = complete_utf8_len(output);
+        let stderr = &command.stderr[*reported_stderr..];
+        let stderr_len = complete_utf8_len(stderr);
+        let partial = PartialOutput {
+            output: String::from_utf8_lossy(&output[..output_len]).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr[..stderr_len]).into_owned(),
+        };
+        *reported_output += output_len;
+        *reported_stderr += stderr_len;
+        partial
+    }
+
+    /// 判断正在执行的命令是否在等待输入
+    ///
+    /// 以是否有进程阻塞在读取会话的标准输入上为准；无法检查进程时（远程目标、
+    /// 非 Linux 系统）才根据输出是否以提示符结尾（如 `[y/N] `、`Password:`）判断。
+    fn waiting_for_input(&self) -> bool {
+        let Some(command) = &self.command else {
+            return false;
+        };
+        self.blocked_on_stdin()
+            .unwrap_or_else(|| looks_like_prompt(&command.output.data))
+    }
+
+    /// 是否有进程阻塞在读取会话的标准输入上，无法检查时返回 None
+    #[cfg(target_os = "linux")]
+    fn blocked_on_stdin(&self) -> Option<bool> {
+        use std::os::unix::io::AsRawFd;
+
+        // 本地只有 ssh 或容器运行时的客户端进程，它们读取标准输入的方式与命令无关
+        if self.remote.is_some() {
+            return None;
+        }
+        let child = self.process.as_ref()?;
+        // 管道两端的 /proc/<pid>/fd 链接相同，形如 pipe:[12345]
+        let pipe = fs::read_link(format!("/proc/self/fd/{}", self.stdin.as_raw_fd())).ok()?;
+        let mut checked = false;
+        for pid in process::process_tree(child.id()) {
+            if !fs::read_link(format!("/proc/{}/fd/0", pid)).is_ok_and(|stdin| stdin == pipe) {
+                continue;
+            }
+            // 内核不公开等待位置时 wchan 为 0
+            let Ok(wchan) = fs::read_to_string(format!("/proc/{}/wchan", pid)) else {
+                continue;
+            };
+            if wchan.contains("pipe_read") || wchan.contains("pipe_wait") {
+                return Some(true);
+            }
+            checked |= !matches!(wchan.trim(), "" | "0");
+        }
+        checked.then_some(false)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn blocked_on_stdin(&self) -> Option<bool> {
+        None
+    }
+
+    /// 终止bash进程及其进程组中的所有进程
+    pub fn kill(&mut self) {
+        if let Some((target, pid)) = self.remote.take() {
+            // 断开连接不一定会终止远程进程，在目标上直接终止，不等待结果
+            thread::spawn(move || {
+                if let Err(e) = target.signal_tree(pid, "KILL", true) {
+                    warn!("终止远程bash进程 {} 失败: {}", pid, e);
+                }
+            });
+        }
+        if let Some(child) = self.process.take() {
+            process::terminate(child);
+        }
+    }
+}
+
+impl Drop for BashSession {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// 解释器进程已退出的错误，附带它最后输出到 stderr 的内容
+fn exited_error(stderr: &[u8]) -> ToolError {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        ToolError::new("bash进程已退出，工具需要重启")
+    } else {
+        ToolError::new(format!("bash进程已退出，工具需要重启: {}", stderr))
+    }
+}
+
+/// 启动线程持续读取bash进程的输出
+fn spawn_reader(
+    mut source: impl Read + Send + 'static,
+    tx: UnboundedSender<OutputChunk>,
+    wrap: fn(Vec<u8>) -> OutputChunk,
+) {
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match source.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(wrap(buf[..n].to_vec())).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// 一条命令的输出缓冲区
+///
+/// 内存中最多保留 `MAX_OUTPUT_SIZE` 字节；超出后完整的原始输出写入溢出文件，
+/// 截断提示中会给出文件路径。
+#[derive(Default)]
+struct OutputBuffer {
+    data: Vec<u8>,
+    truncated: bool,
+    spill: Option<(PathBuf, File)>,
+}
+
+impl OutputBuffer {
+    fn append(&mut self, bytes: &[u8]) {
+        let room = MAX_OUTPUT_SIZE.saturating_sub(self.data.len());
+        if bytes.len() > room && !self.truncated {
+            self.truncated = true;
+            self.spill = open_spill_file(&self.data);
+        }
+        if let Some((path, file)) = self.spill.as_mut() {
+            if let Err(e) = file.write_all(bytes) {
+                warn!("写入溢出文件 {} 失败: {}", path.display(), e);
+                self.spill = None;
+            }
+        }
+        self.data.extend_from_slice(&bytes[..bytes.len().min(room)]);
+    }
+
+    /// 返回从 `from` 开始的输出，被截断时附加提示
+    fn finish(self, from: usize) -> String {
+        let mut output = String::from_utf8_lossy(&self.data[from.min(self.data.len())..]).into_owned();
+        match (self.truncated, self.spill) {
+            (false, _) => {}
+            (true, Some((path, _))) => output.push_str(&format!(
+                "\n... 输出过大，已截断，完整的原始输出见 {} ...",
+                path.display()
+            )),
+            (true, None) => output.push_str(TRUNCATED_MESSAGE),
+        }
+        output
+    }
+}
+
+/// 创建溢出文件并写入已缓冲的输出
+fn open_spill_file(buffered: &[u8]) -> Option<(PathBuf, File)> {
+    let dir = std::env::temp_dir().join(SPILL_DIR);
+    let path = dir.join(format!("{}.log", Uuid::new_v4().simple()));
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| File::create(&path))
+        .and_then(|mut file| file.write_all(buffered).map(|_| file));
+
+    match result {
+        Ok(file) => Some((path, file)),
+        Err(e) => {
+            warn!("创建溢出文件 {} 失败: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// `data` 末尾与 `marker` 开头相同的最大长度（小于 `marker` 的长度）
+fn marker_prefix_len(data: &[u8], marker: &[u8]) -> usize {
+    (1..marker.len().min(data.len() + 1))
+        .rev()
+        .find(|len| data.ends_with(&marker[..*len]))
+        .unwrap_or(0)
+}
+
+/// 去掉末尾不完整的 UTF-8 字符后的长度
+fn complete_utf8_len(data: &[u8]) -> usize {
+    match std::str::from_utf8(data) {
+        Ok(_) => data.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => data.len(),
+    }
+}
+
+/// 输出的最后一行是否像是在提示用户输入，只在无法检查进程时使用
+///
+/// 以 `:`、`?` 结尾，以 `[y/N]`、`(yes/no)` 这样的选项结尾，或者以后面带空格的
+/// `>`、`$`、`#` 结尾时视为提示符。
+fn looks_like_prompt(data: &[u8]) -> bool {
+    let tail = String::from_utf8_lossy(&data[data.len().saturating_sub(PROMPT_TAIL_SIZE)..]);
+    let last_line = tail.rsplit('\n').next().unwrap_or_default();
+    let trimmed = last_line.trim_end();
+    if trimmed.ends_with([':', '?', '：', '？']) {
+        return true;
+    }
+    let open = match trimmed.chars().last() {
+        Some(']') => trimmed.rfind('['),
+        Some(')') => trimmed.rfind('('),
+        _ => None,
+    };
+    if let Some(open) = open {
+        let choices = &trimmed[open + 1..trimmed.len() - 1];
+        return choices.contains('/') && choices.chars().all(|c| c.is_alphabetic() || c == '/');
+    }
+    trimmed.len() < last_line.len() && trimmed.ends_with(['>', '$', '#'])
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_stderr_when_interpreter_exits_at_startup() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo '沙箱启动失败: 无法把 /usr 重新挂载为只读' >&2; exit 1"]);
+        let mut session = BashSession::start(command, Interpreter::Bash).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let error = match session.run("true", Duration::from_secs(5)).await {
+            Err(error) => error,
+            Ok(_) => panic!("进程已退出，命令不应执行成功"),
+        };
+        assert!(error.message.contains("无法把 /usr 重新挂载为只读"), "{}", error.message);
+    }
+
+    #[test]
+    fn prompt_heuristic_needs_a_prompt_like_last_line() {
+        for prompt in ["Password:", "Continue? ", "Overwrite? [y/N] ", "Proceed (yes/no)", ">>> ", "$ ", "名字："] {
+            assert!(looks_like_prompt(prompt.as_bytes()), "{}", prompt);
+        }
+        for output in ["done\n", "Compiling (3/10)", "[1/4] Resolving", "vec![1, 2]", "a -> b", "cost: 5$", "#"] {
+            assert!(!looks_like_prompt(output.as_bytes()), "{}", output);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn waits_for_input_only_when_reading_stdin() {
+        let mut session = BashSession::start(Command::new("bash"), Interpreter::Bash).unwrap();
+        let idle = Some(Duration::from_millis(300));
+
+        // 输出像提示符，但命令没有读取标准输入
+        session.send("printf 'Progress:'; sleep 1; echo").unwrap();
+        assert!(matches!(session.wait(Duration::from_secs(5), idle).await, Ok(RunStatus::Finished(_))));
+
+        session.send("printf 'loading'; read name; echo \"hi $name\"").unwrap();
+        let partial = match session.wait(Duration::from_secs(5), idle).await {
+            Ok(RunStatus::WaitingForInput(partial)) => partial,
+            _ => panic!("命令在读取标准输入，应该返回等待输入"),
+        };
+        assert_eq!(partial.output, "loading");
+        session.write_input("you\n").unwrap();
+        match session.wait(Duration::from_secs(5), idle).await {
+            Ok(RunStatus::Finished(output)) => assert_eq!(output.output, "hi you\n"),
+            _ => panic!("输入后命令应该结束"),
+        }
+        session.kill();
+    }
+
+    #[tokio::test]
+    async fn run_timeout_leaves_no_pending_command() {
+        let mut session = BashSession::start(Command::new("bash"), Interpreter::Bash).unwrap();
+
+        assert!(session.run("sleep 5", Duration::from_millis(200)).await.unwrap().is_none());
+        assert!(!session.is_busy());
+        assert!(session.exit_code().unwrap().is_some());
+    }
+}
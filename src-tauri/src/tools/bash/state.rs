@@ -0,0 +1,187 @@
+use crate::tools::base::ToolError;
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS bash_session_state (
+    session_id TEXT PRIMARY KEY,
+    cwd TEXT,
+    env TEXT NOT NULL,
+    unset TEXT NOT NULL,
+    aliases TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+";
+
+/// 状态输出中环境变量和别名之间的分隔行，见 `Interpreter::capture_command`
+const CAPTURE_SEPARATOR: &str = "\n__MAESTRO_STATE__\n";
+
+/// 每条命令都会改变、不需要恢复的变量
+const VOLATILE_VARS: &[&str] = &["_", "PWD", "OLDPWD", "SHLVL"];
+
+/// 可以在新shell中恢复的会话状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SessionState {
+    /// shell所在的目录
+    pub cwd: Option<String>,
+    /// 相对于新启动的shell新增或改变的环境变量，例如激活虚拟环境后的 PATH 和 VIRTUAL_ENV
+    pub env: BTreeMap<String, String>,
+    /// 新启动的shell中存在、会话中已被移除的环境变量
+    pub unset: Vec<String>,
+    /// 定义别名的命令（如 `alias -p` 的输出），可以直接执行
+    pub aliases: String,
+}
+
+/// 解析 `Interpreter::capture_command` 的输出，返回环境变量和别名脚本
+pub(super) fn parse_capture(output: &str) -> (HashMap<String, String>, String) {
+    let (env, aliases) = output.split_once(CAPTURE_SEPARATOR).unwrap_or((output, ""));
+    let env = env
+        .split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .filter(|(name, _)| !VOLATILE_VARS.contains(name))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    (env, aliases.trim().to_string())
+}
+
+impl SessionState {
+    /// 根据启动时和当前的环境变量计算会话状态
+    pub(super) fn diff(
+        baseline: &HashMap<String, String>,
+        current: HashMap<String, String>,
+        aliases: String,
+        cwd: Option<String>,
+    ) -> Self {
+        let mut unset: Vec<String> = baseline
+            .keys()
+            .filter(|name| !current.contains_key(*name))
+            .cloned()
+            .collect();
+        unset.sort();
+        let env = current
+            .into_iter()
+            .filter(|(name, value)| baseline.get(name) != Some(value))
+            .collect();
+
+        Self {
+            cwd,
+            env,
+            unset,
+            aliases,
+        }
+    }
+
+    /// 在新shell中恢复状态的脚本
+    pub(super) fn replay_script(&self) -> String {
+        let mut lines: Vec<String> = self.unset.iter().map(|name| super::env::unset_command(name)).collect();
+        lines.extend(self.env.iter().map(|(name, value)| {
+            format!("export {}={}", name, shell_escape::unix::escape(value.as_str().into()))
+        }));
+        if !self.aliases.is_empty() {
+            lines.push(self.aliases.clone());
+        }
+        // 目录可能已被删除，这时留在启动目录
+        if let Some(cwd) = &self.cwd {
+            lines.push(format!("cd {} 2>/dev/null || true", shell_escape::unix::escape(cwd.as_str().into())));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 按会话ID保存在 SQLite 中的Bash会话状态，应用重启后用于恢复shell
+pub struct SessionStateStore {
+    conn: Mutex<Connection>,
+}
+
+impl SessionStateStore {
+    /// 打开数据库文件，必要时创建表
+    pub fn open(path: &Path) -> Result<Self, ToolError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ToolError::new(format!("创建数据库目录失败: {}", e)))?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| ToolError::new(format!("打开数据库 {} 失败: {}", path.display(), e)))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| ToolError::new(format!("初始化会话状态表失败: {}", e)))?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// 保存会话状态，覆盖之前的记录
+    pub(super) fn save(&self, session_id: &str, state: &SessionState) -> Result<(), ToolError> {
+        let env = serde_json::to_string(&state.env)
+            .map_err(|e| ToolError::new(format!("序列化会话状态失败: {}", e)))?;
+        let unset = serde_json::to_string(&state.unset)
+            .map_err(|e| ToolError::new(format!("序列化会话状态失败: {}", e)))?;
+
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO bash_session_state (session_id, cwd, env, unset, aliases, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    session_id,
+                    state.cwd,
+                    env,
+                    unset,
+                    state.aliases,
+                    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                ],
+            )
+            .map_err(|e| ToolError::new(format!("保存会话状态失败: {}", e)))?;
+        Ok(())
+    }
+
+    /// 读取会话状态，没有记录时返回 None
+    pub fn load(&self, session_id: &str) -> Result<Option<SessionState>, ToolError> {
+        let row = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT cwd, env, unset, aliases FROM bash_session_state WHERE session_id = ?1",
+                [session_id],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| ToolError::new(format!("读取会话状态失败: {}", e)))?;
+
+        let Some((cwd, env, unset, aliases)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(SessionState {
+            cwd,
+            env: serde_json::from_str(&env).map_err(|e| ToolError::new(format!("解析会话状态失败: {}", e)))?,
+            unset: serde_json::from_str(&unset)
+                .map_err(|e| ToolError::new(format!("解析会话状态失败: {}", e)))?,
+            aliases,
+        }))
+    }
+
+    /// 删除会话状态
+    pub(super) fn delete(&self, session_id: &str) -> Result<(), ToolError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM bash_session_state WHERE session_id = ?1", [session_id])
+            .map_err(|e| ToolError::new(format!("删除会话状态失败: {}", e)))?;
+        Ok(())
+    }
+}
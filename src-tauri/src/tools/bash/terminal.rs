@@ -0,0 +1,278 @@
+use std::{iter::Peekable, str::Chars};
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+/// 8 位形式的 CSI
+const CSI_8BIT: char = '\u{9b}';
+/// 光标所在列的上限
+const MAX_COLUMNS: usize = 4096;
+/// 光标最多移到已有内容之后多少行
+const MAX_ROWS_PAST_END: usize = 100;
+/// 光标移动产生的空白填充总量上限（字符数），防止控制序列把少量输出放大成大量内存
+const MAX_PADDING: usize = 1024 * 1024;
+
+/// 把带终端控制序列的输出渲染为终端最终显示的文本
+///
+/// 支持回车、退格、光标移动和擦除序列，`cargo`、`npm`、`wget` 等工具的进度刷新
+/// 只保留最后的状态。颜色（SGR）、窗口标题等无法用纯文本表示的序列会被丢弃。
+pub(super) fn render(input: &str) -> String {
+    if !input.chars().any(|c| c.is_control() && c != '\n' && c != '\t') {
+        return input.to_string();
+    }
+
+    let mut screen = Screen::default();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => screen.newline(),
+            '\r' => screen.col = 0,
+            '\x08' => screen.col = screen.col.saturating_sub(1),
+            '\t' => screen.put('\t'),
+            ESC => screen.escape(&mut chars),
+            CSI_8BIT => screen.csi(&mut chars),
+            c if c.is_control() => {}
+            c => screen.put(c),
+        }
+    }
+    screen.into_string()
+}
+
+/// 模拟的终端屏幕，行数不限，光标不能移到第一行之前
+///
+/// 光标位置有上限，移动到内容之外产生的空行和空格计入填充总量，
+/// 总量用完后光标停在内容的末尾。
+#[derive(Default)]
+struct Screen {
+    lines: Vec<Vec<char>>,
+    row: usize,
+    col: usize,
+    saved: (usize, usize),
+    /// 已产生的空白填充，清屏后不重置
+    padding: usize,
+}
+
+impl Screen {
+    fn line(&mut self) -> &mut Vec<char> {
+        if self.lines.len() <= self.row {
+            let skipped = self.row - self.lines.len();
+            self.row = self.lines.len() + self.pad(skipped);
+            self.lines.resize_with(self.row + 1, Vec::new);
+        }
+        &mut self.lines[self.row]
+    }
+
+    /// 申请 `wanted` 个填充，返回允许的数量
+    fn pad(&mut self, wanted: usize) -> usize {
+        let allowed = wanted.min(MAX_PADDING - self.padding);
+        self.padding += allowed;
+        allowed
+    }
+
+    /// 在光标处写入字符，覆盖原有内容
+    fn put(&mut self, c: char) {
+        let len = self.line().len();
+        if self.col > len {
+            self.col = len + self.pad(self.col - len);
+        }
+        let col = self.col;
+        let line = self.line();
+        if col < line.len() {
+            line[col] = c;
+        } else {
+            line.resize(col, ' ');
+            line.push(c);
+        }
+        self.col = (col + 1).min(MAX_COLUMNS - 1);
+    }
+
+    /// 把光标限制在允许的范围内
+    fn clamp_cursor(&mut self) {
+        self.col = self.col.min(MAX_COLUMNS - 1);
+        self.row = self.row.min(self.lines.len() + MAX_ROWS_PAST_END);
+    }
+
+    /// 清空屏幕，已用的填充总量保留
+    fn reset(&mut self) {
+        *self = Screen {
+            padding: self.padding,
+            ..Screen::default()
+        };
+    }
+
+    fn newline(&mut self) {
+        self.line();
+        self.row += 1;
+        self.col = 0;
+        self.line();
+    }
+
+    /// 处理 ESC 开头的序列
+    fn escape(&mut self, chars: &mut Peekable<Chars>) {
+        match chars.next() {
+            Some('[') => self.csi(chars),
+            Some(']') | Some('P') | Some('_') | Some('^') => skip_string(chars),
+            Some('7') => self.saved = (self.row, self.col),
+            Some('8') => (self.row, self.col) = self.saved,
+            Some('M') => self.row = self.row.saturating_sub(1),
+            Some('c') => self.reset(),
+            // 字符集选择等带中间字节的序列，如 ESC ( B
+            Some(c) if ('\x20'..='\x2f').contains(&c) => {
+                while chars.next_if(|c| ('\x20'..='\x2f').contains(c)).is_some() {}
+                chars.next();
+            }
+            _ => {}
+        }
+        self.clamp_cursor();
+    }
+
+    /// 处理 CSI 序列，`chars` 位于参数的开头
+    fn csi(&mut self, chars: &mut Peekable<Chars>) {
+        let mut params = String::new();
+        while let Some(c) = chars.next_if(|c| ('\x30'..='\x3f').contains(c)) {
+            params.push(c);
+        }
+        while chars.next_if(|c| ('\x20'..='\x2f').contains(c)).is_some() {}
+        let Some(action) = chars.next() else {
+            return;
+        };
+        // 私有模式（如 ?25l 隐藏光标）与显示内容无关
+        if params.starts_with(['?', '>', '<', '=']) {
+            return;
+        }
+
+        // 超出范围的数值按上限处理，之后由 clamp_cursor 限制
+        let args: Vec<usize> = params
+            .split(';')
+            .map(|p| match p.parse::<usize>() {
+                Ok(value) => value,
+                Err(_) if !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) => usize::MAX,
+                Err(_) => 0,
+            })
+            .collect();
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        // 光标移动的次数省略或为 0 时按 1 处理
+        let count = arg(0).max(1);
+
+        match action {
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' => self.row = self.row.saturating_add(count),
+            'C' => self.col = self.col.saturating_add(count),
+            'D' => self.col = self.col.saturating_sub(count),
+            'E' => {
+                self.row = self.row.saturating_add(count);
+                self.col = 0;
+            }
+            'F' => {
+                self.row = self.row.saturating_sub(count);
+                self.col = 0;
+            }
+            'G' | '`' => self.col = count - 1,
+            'd' => self.row = count - 1,
+            'H' | 'f' => {
+                self.row = arg(0).max(1) - 1;
+                self.col = arg(1).max(1) - 1;
+            }
+            'K' => self.erase_line(arg(0)),
+            'J' => self.erase_display(arg(0)),
+            's' => self.saved = (self.row, self.col),
+            'u' => (self.row, self.col) = self.saved,
+            // SGR 和其他序列直接丢弃
+            _ => {}
+        }
+        self.clamp_cursor();
+    }
+
+    fn erase_line(&mut self, mode: usize) {
+        let col = self.col;
+        let line = self.line();
+        match mode {
+            0 => line.truncate(col),
+            1 => {
+                let end = (col + 1).min(line.len());
+                line[..end].fill(' ');
+            }
+            _ => line.clear(),
+        }
+    }
+
+    fn erase_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_line(0);
+                self.lines.truncate(self.row + 1);
+            }
+            1 => {
+                for line in self.lines.iter_mut().take(self.row) {
+                    line.clear();
+                }
+                self.erase_line(1);
+            }
+            _ => self.reset(),
+        }
+    }
+
+    fn into_string(self) -> String {
+        let lines: Vec<String> = self
+            .lines
+            .into_iter()
+            .map(|line| line.into_iter().collect())
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// 跳过以 BEL 或 ST（ESC \）结束的字符串序列，如 OSC 窗口标题
+fn skip_string(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.next() {
+        match c {
+            BEL => break,
+            ESC => {
+                chars.next_if_eq(&'\\');
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_progress_updates() {
+        assert_eq!(render("10%\r50%\r100%\n"), "100%\n");
+        assert_eq!(render("ab\x08c"), "ac");
+        assert_eq!(render("\x1b[31mred\x1b[0m \x1b]0;title\x07ok"), "red ok");
+        assert_eq!(render("line1\nline2\x1b[1A\x1b[2K\rnew\n"), "new\nline2");
+        assert_eq!(render("abc\x1b[2G\x1b[K"), "a");
+        assert_eq!(render("a\x1b[3Cb"), "a   b");
+    }
+
+    #[test]
+    fn clamps_hostile_cursor_moves() {
+        for input in [
+            "\x1b[99999999999999999999Cx",
+            "\x1b[18446744073709551615Bx",
+            "\x1b[999999999;999999999Hx",
+            "\x1b[999999999dx",
+            "\x1b[999999999Gx",
+            "\x1b[999999999Ex",
+            "\u{9b}999999999Cx",
+        ] {
+            let output = render(input);
+            assert!(output.ends_with('x'), "{:?}", input);
+            assert!(output.len() <= MAX_COLUMNS + MAX_ROWS_PAST_END + 1, "{:?}: {}", input, output.len());
+        }
+    }
+
+    #[test]
+    fn limits_total_padding() {
+        // 每个序列都会把内容推到更远的位置，重置屏幕也不能恢复填充额度
+        let input = "\x1b[4000Cx\x1b[100Bx\x1bc".repeat(2000);
+        let output = render(&input);
+        assert!(output.chars().count() <= MAX_PADDING + input.len());
+        let input = "\x1b[4000Cx\n".repeat(1000);
+        assert!(render(&input).chars().count() <= MAX_PADDING + input.len());
+    }
+}
@@ -325,6 +325,7 @@ impl ComputerTool {
                         error: if error.is_empty() { None } else { Some(error) },
                         base64_image: screenshot.base64_image,
                         system: None,
+                        ..Default::default()
                     })
                 }
             }
@@ -409,6 +410,7 @@ impl ComputerTool {
                         error: None,
                         base64_image: None,
                         system: None,
+                        ..Default::default()
                     })
                 } else {
                     Err(ToolError::new("获取光标位置失败"))
@@ -582,6 +584,7 @@ impl ComputerTool {
             },
             base64_image: None,
             system: None,
+            ..Default::default()
         };
 
         if take_screenshot {
@@ -700,6 +703,7 @@ impl ComputerTool {
                 error: None,
                 base64_image: Some(base64_image),
                 system: None,
+                ..Default::default()
             })
         } else {
             let err_msg = format!("截图失败: {:?}", result.error);
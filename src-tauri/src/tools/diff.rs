@@ -0,0 +1,35 @@
+use similar::TextDiff;
+use std::path::Path;
+
+/// 统一差异格式的上下文行数
+const CONTEXT_LINES: usize = 3;
+/// 文件不存在时使用的路径
+const DEV_NULL: &str = "/dev/null";
+
+/// 生成文件修改前后的统一差异，内容为 None 表示文件不存在
+///
+/// 路径使用 `a/` 和 `b/` 前缀，与 `git diff` 的输出一致；内容相同时返回空字符串。
+pub fn unified_diff(path: &Path, before: Option<&str>, after: Option<&str>) -> String {
+    let old = before.unwrap_or_default();
+    let new = after.unwrap_or_default();
+    if before.is_some() == after.is_some() && old == new {
+        return String::new();
+    }
+
+    let path = path.to_string_lossy();
+    let path = path.trim_start_matches('/');
+    let old_header = match before {
+        Some(_) => format!("a/{}", path),
+        None => DEV_NULL.to_string(),
+    };
+    let new_header = match after {
+        Some(_) => format!("b/{}", path),
+        None => DEV_NULL.to_string(),
+    };
+
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&old_header, &new_header)
+        .to_string()
+}
@@ -1,15 +1,44 @@
-use crate::tools::base::{ToolError, ToolResult};
+use crate::tools::{
+    base::{file_name_component, ToolError, ToolResult},
+    diff::unified_diff,
+    edit_history::{EditHistory, EditSnapshot, FileStamp, NewEdit},
+    encoding::{self, TextFormat},
+    patch::{apply_hunks, parse_patch, HunkStatus},
+    target::{ExecutionTarget, FileMetadata},
+    walk::{walk, Visit, WalkOptions},
+};
+use chrono::{DateTime, Local};
+use encoding_rs::Encoding;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use log::warn;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
-    path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashSet},
+    hash::{Hash, Hasher},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
-const SNIPPET_LINES: usize = 4;
 const MAX_FILE_SIZE: usize = 10 * 1024 * 1024; // 10MB
 const MAX_DISPLAY_LINES: usize = 1000;
+/// 搜索默认最多返回的匹配数
+const DEFAULT_MAX_RESULTS: usize = 100;
+/// 搜索结果中每行最多显示的字符数
+const MAX_MATCH_LINE_CHARS: usize = 500;
+/// 判断二进制文件时检查的字节数
+const BINARY_CHECK_BYTES: usize = 8192;
+/// 查看目录时默认列出的深度
+const DEFAULT_VIEW_DEPTH: usize = 2;
+/// 查看目录和 glob 默认最多列出的条目数
+const DEFAULT_MAX_ENTRIES: usize = 500;
+/// 记录文件状态时保存内容的最大字节数，更大的文件被外部修改时不显示差异
+const MAX_STAMP_CONTENT: usize = 256 * 1024;
+/// 会话备份目录（位于执行目标的临时目录下）
+const SESSION_BACKUP_DIR: &str = "maestro-backups";
+/// 会话备份保留的时间，与编辑历史保留的天数一致
+const SESSION_BACKUP_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
 const TRUNCATED_MESSAGE: &str = "<文件已截断>\n注意：为了节省上下文，只显示了部分文件内容。请使用view_range参数查看特定行范围。";
 
 /// 编辑命令类型
@@ -24,24 +53,173 @@ pub enum EditCommand {
     StrReplace,
     /// 插入文本
     Insert,
-    /// 撤销编辑
+    /// 在一次调用中原子地执行多处字符串替换
+    MultiEdit,
+    /// 应用跨多个文件的统一差异补丁
+    ApplyPatch,
+    /// 在文件或目录中搜索文本
+    Search,
+    /// 列出目录中匹配 glob 模式的路径
+    Glob,
+    /// 撤销编辑，可以一次撤销多步
     UndoEdit,
+    /// 重做被撤销的编辑
+    RedoEdit,
+    /// 列出文件的编辑历史
+    History,
+}
+
+/// `multi_edit` 中的一处替换
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrEdit {
+    pub old_str: String,
+    #[serde(default)]
+    pub new_str: Option<String>,
+    /// 替换所有匹配，为 false 时原字符串必须唯一
+    #[serde(default)]
+    pub replace_all: bool,
+}
+
+/// `str_replace` 的匹配方式
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceOptions {
+    /// 原字符串是正则表达式，新字符串中可以用 `$1`、`${name}` 引用捕获组
+    pub regex: bool,
+    /// 替换所有匹配，否则原字符串必须唯一
+    pub replace_all: bool,
+    /// 期望的匹配数，实际匹配数不同时不做任何修改
+    pub expected_count: Option<usize>,
+}
+
+/// `search` 的选项
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// 模式是正则表达式，否则按字面匹配
+    pub regex: bool,
+    /// 忽略大小写
+    pub case_insensitive: bool,
+    /// 只搜索匹配这些 glob 的文件，路径相对于搜索目录
+    pub include: Vec<String>,
+    /// 跳过匹配这些 glob 的文件和目录
+    pub exclude: Vec<String>,
+    /// 每个匹配前后显示的行数
+    pub context: usize,
+    /// 最多返回的匹配数，默认 100
+    pub max_results: Option<usize>,
+}
+
+/// 查看目录和 `glob` 的选项
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// 查看目录时列出的深度，默认 2
+    pub depth: Option<usize>,
+    /// 包括以 `.` 开头的隐藏文件和目录
+    pub hidden: bool,
+    /// 最多列出的条目数，默认 500
+    pub max_entries: Option<usize>,
+}
+
+/// 修改文件前备份原文件的方式，每个文件只备份第一次修改前的内容
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupMode {
+    /// 不备份
+    #[default]
+    Off,
+    /// 在原文件旁边保存 `文件名.orig`，已存在时不覆盖
+    Orig,
+    /// 保存到执行目标临时目录中按会话划分的备份目录，保持原文件的路径结构
+    ///
+    /// 新会话第一次备份时删除创建超过 30 天的会话备份目录。
+    Session,
 }
 
 /// 文本编辑工具
 pub struct EditTool {
     /// 文件历史记录，用于撤销操作
-    file_history: Arc<Mutex<HashMap<PathBuf, Vec<String>>>>,
+    file_history: Arc<EditHistory>,
+    /// 编辑历史所属的会话
+    session_id: String,
+    /// 当前的工具调用，记录在编辑历史中
+    tool_use_id: Option<String>,
+    /// 预览模式，只返回差异而不写入文件
+    dry_run: bool,
+    /// `str_replace` 的匹配方式
+    replace_options: ReplaceOptions,
+    /// `search` 的选项
+    search_options: SearchOptions,
+    /// 查看目录和 `glob` 的选项
+    list_options: ListOptions,
+    /// 修改文件前备份原文件的方式
+    backup: BackupMode,
+    /// 文件所在的执行目标
+    target: ExecutionTarget,
 }
 
 impl EditTool {
     /// 创建一个新的文本编辑工具实例
     pub fn new() -> Self {
+        Self::with_target(ExecutionTarget::Local)
+    }
+
+    /// 创建一个操作指定执行目标上文件的文本编辑工具实例
+    pub fn with_target(target: ExecutionTarget) -> Self {
         Self {
-            file_history: Arc::new(Mutex::new(HashMap::new())),
+            file_history: Arc::new(EditHistory::new()),
+            session_id: "default".to_string(),
+            tool_use_id: None,
+            dry_run: false,
+            replace_options: ReplaceOptions::default(),
+            search_options: SearchOptions::default(),
+            list_options: ListOptions::default(),
+            backup: BackupMode::default(),
+            target,
         }
     }
 
+    /// 使用会话共享的编辑历史，Bash命令的文件变更也记录在其中
+    pub fn with_history(mut self, history: Arc<EditHistory>, session_id: impl Into<String>) -> Self {
+        self.file_history = history;
+        self.session_id = session_id.into();
+        self
+    }
+
+    /// 设置本次修改对应的工具调用
+    pub fn with_tool_use_id(mut self, tool_use_id: Option<String>) -> Self {
+        self.tool_use_id = tool_use_id;
+        self
+    }
+
+    /// 设置预览模式，修改文件的命令只返回差异，不写入文件
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// 设置 `str_replace` 的匹配方式
+    pub fn with_replace_options(mut self, options: ReplaceOptions) -> Self {
+        self.replace_options = options;
+        self
+    }
+
+    /// 设置 `search` 的选项
+    pub fn with_search_options(mut self, options: SearchOptions) -> Self {
+        self.search_options = options;
+        self
+    }
+
+    /// 设置查看目录和 `glob` 的选项
+    pub fn with_list_options(mut self, options: ListOptions) -> Self {
+        self.list_options = options;
+        self
+    }
+
+    /// 设置修改文件前备份原文件的方式
+    pub fn with_backup(mut self, backup: BackupMode) -> Self {
+        self.backup = backup;
+        self
+    }
+
     /// 执行编辑操作
     pub async fn execute(
         &self,
@@ -52,12 +230,29 @@ impl EditTool {
         old_str: Option<String>,
         new_str: Option<String>,
         insert_line: Option<i32>,
+        steps: Option<u32>,
+        edits: Option<Vec<StrEdit>>,
+        patch: Option<String>,
+        pattern: Option<String>,
     ) -> Result<ToolResult, ToolError> {
         let path = PathBuf::from(&path);
-        self.validate_path(&command, &path)?;
+        let metadata = self.validate_path(&command, &path)?;
+        let writes = matches!(
+            command,
+            EditCommand::Create
+                | EditCommand::StrReplace
+                | EditCommand::Insert
+                | EditCommand::MultiEdit
+                | EditCommand::ApplyPatch
+        );
+        if self.dry_run && !writes {
+            return Err(ToolError::new(
+                "dry_run 只能用于 create、str_replace、insert、multi_edit 和 apply_patch 命令",
+            ));
+        }
 
         match command {
-            EditCommand::View => self.view(&path, view_range).await,
+            EditCommand::View => self.view(&path, metadata, view_range).await,
             EditCommand::Create => {
                 let file_text = file_text.ok_or_else(|| ToolError::new("创建文件时需要提供文件内容"))?;
                 self.create(&path, &file_text)
@@ -71,12 +266,30 @@ impl EditTool {
                 let new_str = new_str.ok_or_else(|| ToolError::new("插入文本时需要提供新文本"))?;
                 self.insert(&path, insert_line, &new_str)
             }
-            EditCommand::UndoEdit => self.undo_edit(&path),
+            EditCommand::MultiEdit => {
+                let edits = edits.ok_or_else(|| ToolError::new("多处替换时需要提供修改列表"))?;
+                self.multi_edit(&path, &edits)
+            }
+            EditCommand::ApplyPatch => {
+                let patch = patch.ok_or_else(|| ToolError::new("应用补丁时需要提供补丁内容"))?;
+                self.apply_patch(&path, &patch)
+            }
+            EditCommand::Search => {
+                let pattern = pattern.ok_or_else(|| ToolError::new("搜索时需要提供模式"))?;
+                self.search(&path, metadata, &pattern)
+            }
+            EditCommand::Glob => {
+                let pattern = pattern.ok_or_else(|| ToolError::new("glob 命令需要提供模式"))?;
+                self.glob(&path, &pattern)
+            }
+            EditCommand::UndoEdit => self.undo_edit(&path, steps.unwrap_or(1)),
+            EditCommand::RedoEdit => self.redo_edit(&path, steps.unwrap_or(1)),
+            EditCommand::History => self.history(&path, steps),
         }
     }
 
-    /// 验证路径和命令组合是否有效
-    fn validate_path(&self, command: &EditCommand, path: &Path) -> Result<(), ToolError> {
+    /// 验证路径和命令组合是否有效，返回路径的信息（不存在时为 None）
+    fn validate_path(&self, command: &EditCommand, path: &Path) -> Result<Option<FileMetadata>, ToolError> {
         // 检查是否是绝对路径
         if !path.is_absolute() {
             let suggested_path = Path::new("").join(path);
@@ -87,15 +300,22 @@ impl EditTool {
             )));
         }
 
+        let metadata = self.target.metadata(path)?;
+
         // 检查路径是否存在
-        if !path.exists() && !matches!(command, EditCommand::Create) {
+        // 撤销和重做可以恢复被删除的文件
+        let may_be_missing = matches!(
+            command,
+            EditCommand::Create | EditCommand::UndoEdit | EditCommand::RedoEdit | EditCommand::History
+        );
+        if metadata.is_none() && !may_be_missing {
             return Err(ToolError::new(format!(
                 "路径 {} 不存在。请提供有效的路径。",
                 path.display()
             )));
         }
 
-        if path.exists() && matches!(command, EditCommand::Create) {
+        if metadata.is_some() && matches!(command, EditCommand::Create) {
             return Err(ToolError::new(format!(
                 "文件已存在于: {}。不能使用 create 命令覆盖文件。",
                 path.display()
@@ -103,80 +323,52 @@ impl EditTool {
         }
 
         // 检查路径是否指向目录
-        if path.is_dir() && !matches!(command, EditCommand::View) {
+        let is_dir = metadata.is_some_and(|metadata| metadata.is_dir);
+        if !is_dir && matches!(command, EditCommand::ApplyPatch | EditCommand::Glob) {
             return Err(ToolError::new(format!(
-                "路径 {} 是一个目录，只能对目录使用 view 命令",
+                "路径 {} 不是目录。apply_patch 和 glob 的路径应该是相对路径所基于的目录",
+                path.display()
+            )));
+        }
+        let accepts_dir = matches!(
+            command,
+            EditCommand::View | EditCommand::ApplyPatch | EditCommand::Search | EditCommand::Glob
+        );
+        if is_dir && !accepts_dir {
+            return Err(ToolError::new(format!(
+                "路径 {} 是一个目录，只能对目录使用 view、apply_patch、search 和 glob 命令",
                 path.display()
             )));
         }
 
-        Ok(())
+        Ok(metadata)
     }
 
     /// 查看文件内容
-    async fn view(&self, path: &Path, view_range: Option<Vec<i32>>) -> Result<ToolResult, ToolError> {
-        if path.is_dir() {
+    async fn view(
+        &self,
+        path: &Path,
+        metadata: Option<FileMetadata>,
+        view_range: Option<Vec<i32>>,
+    ) -> Result<ToolResult, ToolError> {
+        if metadata.is_some_and(|metadata| metadata.is_dir) {
             if view_range.is_some() {
                 return Err(ToolError::new("查看目录时不允许使用 view_range 参数"));
             }
-
-            // 使用tokio的异步文件系统操作
-            let entries = tokio::fs::read_dir(path)
-                .await
-                .map_err(|e| ToolError::new(format!("读取目录失败: {}", e)))?;
-
-            let mut files = Vec::new();
-            let mut dirs = Vec::new();
-
-            let mut entries_vec = Vec::new();
-            let mut entry = entries;
-            
-            // 收集目录条目
-            loop {
-                match entry.next_entry().await {
-                    Ok(Some(e)) => entries_vec.push(e),
-                    Ok(None) => break,
-                    Err(e) => return Err(ToolError::new(format!("读取目录条目失败: {}", e))),
-                }
-            }
-
-            // 处理每个条目
-            for entry in entries_vec {
-                let path = entry.path();
-                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-                
-                // 跳过隐藏文件
-                if file_name.starts_with('.') {
-                    continue;
-                }
-                
-                let metadata = tokio::fs::metadata(&path)
-                    .await
-                    .map_err(|e| ToolError::new(format!("获取文件元数据失败: {}", e)))?;
-                
-                if metadata.is_dir() {
-                    dirs.push(format!("{}/", file_name));
-                } else {
-                    files.push(file_name);
-                }
-            }
-
-            // 排序并组合结果
-            dirs.sort();
-            files.sort();
-            
-            let output = format!(
-                "目录 {} 的内容:\n\n目录:\n{}\n\n文件:\n{}",
-                path.display(),
-                dirs.join("\n"),
-                files.join("\n")
-            );
-            
-            return Ok(ToolResult::success(output));
+            return self.view_dir(path);
         }
 
         // 读取文件内容
-        let file_content = self.read_file(path)?;
+        let (file_content, format) = self.read_text(path)?;
+        self.remember(path, &file_content);
+        // 不是常见的 UTF-8 和 LF 时提示文件格式，编辑时会保持不变
+        let with_format = |result: ToolResult| {
+            if format.is_default() {
+                result
+            } else {
+                result.with_system(format!("文件编码: {}，编辑后保持不变", format.describe()))
+            }
+        };
         
         // 处理查看范围
         if let Some(range) = view_range {
@@ -215,88 +407,245 @@ impl EditTool {
                 file_lines[(init_line - 1) as usize..final_line as usize].join("\n")
             };
             
-            return Ok(ToolResult::success(self.make_output(&content, &path.to_string_lossy(), init_line as usize)));
+            return Ok(with_format(ToolResult::success(self.make_output(
+                &content,
+                &path.to_string_lossy(),
+                init_line as usize,
+            ))));
         }
         
         // 返回完整文件内容
-        Ok(ToolResult::success(self.make_output(&file_content, &path.to_string_lossy(), 1)))
+        Ok(with_format(ToolResult::success(self.make_output(&file_content, &path.to_string_lossy(), 1))))
+    }
+
+    /// 以树形列出目录的内容和文件大小
+    ///
+    /// 默认列出两层，跳过隐藏文件和 .gitignore 忽略的文件，条目数超过上限时截断。
+    fn view_dir(&self, path: &Path) -> Result<ToolResult, ToolError> {
+        let options = &self.list_options;
+        let depth = options.depth.unwrap_or(DEFAULT_VIEW_DEPTH).max(1);
+        let max_entries = options.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES).max(1);
+        let walk_options = WalkOptions {
+            hidden: options.hidden,
+            gitignore: true,
+            max_depth: Some(depth),
+        };
+
+        let mut lines = Vec::new();
+        let mut truncated = false;
+        walk(&self.target, path, walk_options, |entry| {
+            if lines.len() == max_entries {
+                truncated = true;
+                return Visit::Stop;
+            }
+            let indent = "  ".repeat(entry.depth - 1);
+            let name = entry.relative.rsplit('/').next().unwrap_or(&entry.relative);
+            lines.push(if entry.is_dir {
+                format!("{}{}/", indent, name)
+            } else {
+                format!("{}{} ({})", indent, name, format_size(entry.len))
+            });
+            Visit::Continue
+        })?;
+
+        let mut output = format!(
+            "目录 {} 的内容（深度 {}，不包括{}被 .gitignore 忽略的文件）:\n{}",
+            path.display(),
+            depth,
+            if options.hidden { "" } else { "隐藏文件和" },
+            lines.join("\n")
+        );
+        if truncated {
+            output.push_str(&format!(
+                "\n\n<列表已截断，只显示了前 {} 个条目。请减小 depth 或查看子目录。>",
+                max_entries
+            ));
+        }
+        Ok(ToolResult::success(output))
+    }
+
+    /// 列出目录中相对路径匹配 glob 模式的文件和目录
+    ///
+    /// `*` 不匹配 `/`，递归匹配需要使用 `**`。
+    fn glob(&self, root: &Path, pattern: &str) -> Result<ToolResult, ToolError> {
+        // 绝对路径的模式转换为相对于根目录的模式
+        let relative = match Path::new(pattern).strip_prefix(root) {
+            Ok(relative) => relative.to_string_lossy().into_owned(),
+            Err(_) if Path::new(pattern).is_absolute() => {
+                return Err(ToolError::new(format!(
+                    "模式 {} 不在目录 {} 中，请使用相对于该目录的模式",
+                    pattern,
+                    root.display()
+                )))
+            }
+            Err(_) => pattern.to_string(),
+        };
+        let matcher = GlobBuilder::new(&relative)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| ToolError::new(format!("无效的 glob `{}`: {}", pattern, e)))?
+            .compile_matcher();
+        let options = &self.list_options;
+        let max_entries = options.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES).max(1);
+        let walk_options = WalkOptions {
+            hidden: options.hidden,
+            gitignore: true,
+            max_depth: None,
+        };
+
+        let mut paths = Vec::new();
+        let mut truncated = false;
+        walk(&self.target, root, walk_options, |entry| {
+            if matcher.is_match(&entry.relative) {
+                if paths.len() == max_entries {
+                    truncated = true;
+                    return Visit::Stop;
+                }
+                paths.push(if entry.is_dir {
+                    format!("{}/", entry.path.display())
+                } else {
+                    entry.path.display().to_string()
+                });
+            }
+            Visit::Continue
+        })?;
+
+        if paths.is_empty() {
+            return Ok(ToolResult::success(format!(
+                "在 {} 中没有找到匹配 `{}` 的路径",
+                root.display(),
+                pattern
+            )));
+        }
+        let mut output = format!(
+            "在 {} 中找到 {} 个匹配 `{}` 的路径:\n{}",
+            root.display(),
+            paths.len(),
+            pattern,
+            paths.join("\n")
+        );
+        if truncated {
+            output.push_str(&format!(
+                "\n\n<结果已截断，只显示了前 {} 个路径。请使用更具体的模式。>",
+                max_entries
+            ));
+        }
+        Ok(ToolResult::success(output))
     }
 
     /// 创建新文件
     fn create(&self, path: &Path, file_text: &str) -> Result<ToolResult, ToolError> {
-        self.write_file(path, file_text)?;
-        
-        // 添加到历史记录
-        let mut history = self.file_history.lock().unwrap();
-        history.entry(path.to_path_buf()).or_insert_with(Vec::new).push(file_text.to_string());
-        
-        Ok(ToolResult::success(format!("文件创建成功: {}", path.display())))
+        self.apply(
+            path,
+            "create",
+            None,
+            file_text,
+            &TextFormat::default(),
+            format!("文件创建成功: {}", path.display()),
+        )
     }
 
     /// 字符串替换
+    ///
+    /// 默认要求原字符串唯一；设置 `replace_all` 或 `expected_count` 时替换所有匹配。
     fn str_replace(&self, path: &Path, old_str: &str, new_str: Option<&str>) -> Result<ToolResult, ToolError> {
         // 读取文件内容
-        let file_content = self.read_file(path)?;
-        let old_str = old_str.replace("\t", "    ");
-        let new_str = new_str.unwrap_or("").replace("\t", "    ");
-        
-        // 检查原字符串是否唯一
-        let occurrences = file_content.matches(&old_str).count();
-        if occurrences == 0 {
+        let (file_content, format) = self.read_text(path)?;
+        self.ensure_unchanged(path, &file_content)?;
+        let new_str = new_str.unwrap_or("").to_string();
+        let options = &self.replace_options;
+
+        // 每处匹配的范围和替换后的文本
+        let spans: Vec<(usize, usize, String)> = if options.regex {
+            let regex = Regex::new(old_str)
+                .map_err(|e| ToolError::new(format!("未执行替换，无效的正则表达式 `{}`: {}", old_str, e)))?;
+            regex
+                .captures_iter(&file_content)
+                .map(|captures| {
+                    let found = captures.get(0).unwrap();
+                    let mut replacement = String::new();
+                    captures.expand(&new_str, &mut replacement);
+                    (found.start(), found.end(), replacement)
+                })
+                .collect()
+        } else if old_str.is_empty() {
+            return Err(ToolError::new("未执行替换，原字符串为空"));
+        } else {
+            file_content
+                .match_indices(old_str)
+                .map(|(start, _)| (start, start + old_str.len(), new_str.clone()))
+                .collect()
+        };
+
+        // 有匹配的行，用于错误提示
+        let matched_lines = || {
+            let lines: BTreeSet<usize> = line_numbers(&file_content, spans.iter().map(|(start, _, _)| *start))
+                .into_iter()
+                .collect();
+            lines.into_iter().collect::<Vec<_>>()
+        };
+        if spans.is_empty() {
             return Err(ToolError::new(format!(
                 "未执行替换，原字符串 `{}` 在文件 {} 中未找到",
                 old_str, path.display()
             )));
-        } else if occurrences > 1 {
-            let file_lines: Vec<&str> = file_content.split('\n').collect();
-            let mut lines = Vec::new();
-            
-            for (idx, line) in file_lines.iter().enumerate() {
-                if line.contains(&old_str) {
-                    lines.push(idx + 1);
-                }
+        }
+        if let Some(expected) = options.expected_count {
+            if spans.len() != expected {
+                return Err(ToolError::new(format!(
+                    "未执行替换。期望匹配 {} 处，原字符串 `{}` 实际在行 {:?} 中匹配了 {} 处",
+                    expected,
+                    old_str,
+                    matched_lines(),
+                    spans.len()
+                )));
             }
-            
+        } else if spans.len() > 1 && !options.replace_all {
             return Err(ToolError::new(format!(
-                "未执行替换。原字符串 `{}` 在行 {:?} 中有多处匹配。请确保它是唯一的",
-                old_str, lines
+                "未执行替换。原字符串 `{}` 在行 {:?} 中有多处匹配。请确保它是唯一的，或设置 replace_all",
+                old_str,
+                matched_lines()
             )));
         }
-        
-        // 计算替换行号（在替换前）
-        let replacement_line = file_content.split(&old_str).next().unwrap().matches('\n').count();
-        let start_line = replacement_line.saturating_sub(SNIPPET_LINES);
-        let end_line = replacement_line + SNIPPET_LINES + new_str.matches('\n').count();
-        
-        // 替换字符串
-        let new_file_content = file_content.replace(&old_str, &new_str);
-        
-        // 写入文件
-        self.write_file(path, &new_file_content)?;
-        
-        // 添加到历史记录
-        let mut history = self.file_history.lock().unwrap();
-        history.entry(path.to_path_buf()).or_insert_with(Vec::new).push(file_content.clone());
-        
-        // 创建编辑部分的片段
-        let file_lines: Vec<&str> = new_file_content.split('\n').collect();
-        let snippet = file_lines[start_line..std::cmp::min(end_line + 1, file_lines.len())].join("\n");
-        
-        // 准备成功消息
-        let success_msg = format!(
-            "文件 {} 已编辑。\n\n{}",
-            path.display(),
-            self.make_output(&snippet, &format!("{} 的片段", path.display()), start_line + 1)
-        );
-        
-        Ok(ToolResult::success(success_msg))
+
+        // 替换字符串，同时记录替换后的文本在新文件中所在的行
+        let mut new_file_content = String::with_capacity(file_content.len());
+        let mut changed_lines = BTreeSet::new();
+        let mut last = 0;
+        // 新文件中已写入内容的行号
+        let mut line = 1;
+        for (start, end, replacement) in &spans {
+            let unchanged = &file_content[last..*start];
+            new_file_content.push_str(unchanged);
+            line += unchanged.matches('\n').count();
+            let first_line = line;
+            new_file_content.push_str(replacement);
+            line += replacement.matches('\n').count();
+            changed_lines.extend(first_line..=line);
+            last = *end;
+        }
+        new_file_content.push_str(&file_content[last..]);
+
+        self.apply(
+            path,
+            "str_replace",
+            Some(&file_content),
+            &new_file_content,
+            &format,
+            format!(
+                "文件 {} 已编辑，替换了 {} 处，修改的行: {}。",
+                path.display(),
+                spans.len(),
+                format_lines(&changed_lines)
+            ),
+        )
     }
 
     /// 插入文本
     fn insert(&self, path: &Path, insert_line: i32, new_str: &str) -> Result<ToolResult, ToolError> {
         // 读取文件内容
-        let file_content = self.read_file(path)?;
-        let new_str = new_str.replace("\t", "    ");
+        let (file_content, format) = self.read_text(path)?;
+        self.ensure_unchanged(path, &file_content)?;
         
         let file_lines: Vec<&str> = file_content.split('\n').collect();
         let n_lines_file = file_lines.len() as i32;
@@ -318,85 +667,803 @@ impl EditTool {
         
         let new_file_content = new_file_lines.join("\n");
         
-        // 创建片段
-        let start_snippet_line = (insert_line as usize).saturating_sub(SNIPPET_LINES);
-        let end_snippet_line = std::cmp::min(
-            insert_line as usize + new_str_lines.len() + SNIPPET_LINES,
-            new_file_lines.len()
+        self.apply(
+            path,
+            "insert",
+            Some(&file_content),
+            &new_file_content,
+            &format,
+            format!("文件 {} 已编辑。", path.display()),
+        )
+    }
+
+    /// 多处字符串替换
+    ///
+    /// 所有原字符串都在原始内容中查找，全部通过检查并且互不重叠时才一次性写入，
+    /// 在编辑历史中只记录一次修改。
+    fn multi_edit(&self, path: &Path, edits: &[StrEdit]) -> Result<ToolResult, ToolError> {
+        if edits.is_empty() {
+            return Err(ToolError::new("修改列表为空"));
+        }
+        let (file_content, format) = self.read_text(path)?;
+        self.ensure_unchanged(path, &file_content)?;
+
+        // (开始, 结束, 修改序号, 新字符串)
+        let mut spans: Vec<(usize, usize, usize, String)> = Vec::new();
+        let mut errors = Vec::new();
+        for (index, edit) in edits.iter().enumerate() {
+            let old_str = edit.old_str.as_str();
+            let new_str = edit.new_str.as_deref().unwrap_or("");
+            if old_str.is_empty() {
+                errors.push(format!("第 {} 处修改的原字符串为空", index + 1));
+                continue;
+            }
+
+            let starts: Vec<usize> = file_content.match_indices(old_str).map(|(start, _)| start).collect();
+            match starts.len() {
+                0 => errors.push(format!("第 {} 处修改的原字符串 `{}` 未找到", index + 1, old_str)),
+                n if n > 1 && !edit.replace_all => {
+                    let lines = line_numbers(&file_content, starts.iter().copied());
+                    errors.push(format!(
+                        "第 {} 处修改的原字符串 `{}` 在行 {:?} 中有多处匹配，请确保它是唯一的或设置 replace_all",
+                        index + 1,
+                        old_str,
+                        lines
+                    ));
+                }
+                _ => spans.extend(
+                    starts
+                        .into_iter()
+                        .map(|start| (start, start + old_str.len(), index, new_str.to_string())),
+                ),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ToolError::new(format!(
+                "未执行任何修改:\n{}",
+                errors.join("\n")
+            )));
+        }
+
+        spans.sort_by_key(|span| span.0);
+        for pair in spans.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                return Err(ToolError::new(format!(
+                    "未执行任何修改: 第 {} 处和第 {} 处修改的范围重叠",
+                    pair[0].2.min(pair[1].2) + 1,
+                    pair[0].2.max(pair[1].2) + 1
+                )));
+            }
+        }
+
+        let mut new_file_content = String::with_capacity(file_content.len());
+        let mut last = 0;
+        for (start, end, _, new_str) in &spans {
+            new_file_content.push_str(&file_content[last..*start]);
+            new_file_content.push_str(new_str);
+            last = *end;
+        }
+        new_file_content.push_str(&file_content[last..]);
+
+        self.apply(
+            path,
+            "multi_edit",
+            Some(&file_content),
+            &new_file_content,
+            &format,
+            format!(
+                "文件 {} 已编辑，{} 处修改共替换了 {} 处。",
+                path.display(),
+                edits.len(),
+                spans.len()
+            ),
+        )
+    }
+
+    /// 应用跨多个文件的补丁
+    ///
+    /// 补丁中的相对路径基于 `root` 目录。所有块先在内存中应用，全部成功后才写入文件，
+    /// 写入中途失败时恢复已写入的文件；所有文件的修改在编辑历史中记录为同一批次，一次撤销即可还原。
+    fn apply_patch(&self, root: &Path, patch: &str) -> Result<ToolResult, ToolError> {
+        let patches = parse_patch(patch)?;
+        if patches.is_empty() {
+            return Err(ToolError::new("补丁中没有文件修改"));
+        }
+
+        // 文件的原始内容和应用补丁后的内容，None 表示文件不存在
+        let mut original = BTreeMap::new();
+        let mut current = BTreeMap::new();
+        // 文件的编码和换行符，重命名的文件沿用原文件的格式
+        let mut formats = BTreeMap::new();
+        let mut report = Vec::new();
+        let mut failed = false;
+
+        for file in &patches {
+            let old_path = file.old_path.as_deref().map(|path| patch_path(root, path)).transpose()?;
+            let new_path = file.new_path.as_deref().map(|path| patch_path(root, path)).transpose()?;
+            let (title, kind) = match (&old_path, &new_path) {
+                (None, None) => return Err(ToolError::new("补丁中的文件缺少路径")),
+                (None, Some(new)) => (new.display().to_string(), "新建"),
+                (Some(old), None) => (old.display().to_string(), "删除"),
+                (Some(old), Some(new)) if old != new => (format!("{} -> {}", old.display(), new.display()), "重命名"),
+                (Some(old), Some(_)) => (old.display().to_string(), "修改"),
+            };
+            report.push(format!("{}（{}）", title, kind));
+
+            let source = match &old_path {
+                Some(old) => match self.load(old, &mut original, &mut current, &mut formats)? {
+                    Some(text) => text,
+                    None => {
+                        report.push("  文件不存在".to_string());
+                        failed = true;
+                        continue;
+                    }
+                },
+                None => String::new(),
+            };
+            if let Some(new) = new_path.as_ref().filter(|new| old_path.as_ref() != Some(*new)) {
+                if self.load(new, &mut original, &mut current, &mut formats)?.is_some() {
+                    report.push("  目标文件已存在".to_string());
+                    failed = true;
+                    continue;
+                }
+            }
+
+            let (text, statuses) = apply_hunks(&source, &file.hunks);
+            for (index, status) in statuses.iter().enumerate() {
+                report.push(format!("  第 {} 块{}", index + 1, describe_hunk(status)));
+            }
+            let applied = statuses.iter().all(|status| *status != HunkStatus::Failed);
+            if !applied {
+                failed = true;
+            } else if new_path.is_none() && !text.is_empty() {
+                report.push("  删除文件的补丁与文件内容不一致".to_string());
+                failed = true;
+            }
+
+            if let (Some(old), Some(new)) = (&old_path, &new_path) {
+                let format = formats[old];
+                formats.insert(new.clone(), format);
+            }
+            if let Some(old) = old_path {
+                current.insert(old, None);
+            }
+            if let Some(new) = new_path {
+                current.insert(new, Some(text));
+            }
+        }
+        if failed {
+            return Err(ToolError::new(format!(
+                "补丁未应用，没有文件被修改:\n{}",
+                report.join("\n")
+            )));
+        }
+
+        let changes: Vec<(&PathBuf, Option<&str>, Option<&str>)> = current
+            .iter()
+            .map(|(path, after)| (path, original[path].as_deref(), after.as_deref()))
+            .filter(|(_, before, after)| before != after)
+            .collect();
+        if changes.is_empty() {
+            return Ok(ToolResult::success(format!("补丁没有改变任何文件:\n{}", report.join("\n"))));
+        }
+        let diff: String = changes
+            .iter()
+            .map(|(path, before, after)| unified_diff(path, *before, *after))
+            .collect();
+        if self.dry_run {
+            return Ok(ToolResult::success(format!("预览: 补丁尚未应用\n{}\n\n{}", report.join("\n"), diff))
+                .with_diff(diff)
+                .with_system("这是预览，文件未被修改"));
+        }
+
+        // 全部备份成功后才开始写入
+        for (path, before, _) in &changes {
+            if before.is_none() {
+                continue;
+            }
+            if let Some(backup) = self.backup(path)? {
+                report.push(format!("已备份 {} 到 {}", path.display(), backup.display()));
+            }
+        }
+
+        let mut written: Vec<(&PathBuf, Option<&str>)> = Vec::new();
+        for (path, before, after) in &changes {
+            if let Err(e) = self.write_or_remove(path, *after, &formats[*path]) {
+                for (path, before) in written.iter().rev() {
+                    if let Err(e) = self.write_or_remove(path, *before, &formats[*path]) {
+                        warn!("恢复文件 {} 失败: {}", path.display(), e);
+                    }
+                }
+                return Err(ToolError::new(format!(
+                    "写入文件 {} 失败，已恢复其他文件: {}",
+                    path.display(),
+                    e
+                )));
+            }
+            written.push((path, *before));
+        }
+
+        let edits: Vec<NewEdit> = changes
+            .iter()
+            .map(|(path, before, after)| NewEdit {
+                path,
+                operation: "apply_patch",
+                tool_use_id: self.tool_use_id.as_deref(),
+                before: *before,
+                after: *after,
+            })
+            .collect();
+        let note = self.file_history.record_batch(&self.session_id, &edits).err();
+
+        let result = ToolResult::success(format!(
+            "补丁已应用，修改了 {} 个文件:\n{}\n\n{}",
+            changes.len(),
+            report.join("\n"),
+            diff
+        ))
+        .with_diff(diff);
+        Ok(with_history_note(result, note))
+    }
+
+    /// 读取补丁涉及的文件，已在补丁中修改过的文件返回修改后的内容
+    fn load(
+        &self,
+        path: &Path,
+        original: &mut BTreeMap<PathBuf, Option<String>>,
+        current: &mut BTreeMap<PathBuf, Option<String>>,
+        formats: &mut BTreeMap<PathBuf, TextFormat>,
+    ) -> Result<Option<String>, ToolError> {
+        if let Some(content) = current.get(path) {
+            return Ok(content.clone());
+        }
+        let (content, format) = match self.target.metadata(path)? {
+            Some(metadata) if metadata.is_dir => {
+                return Err(ToolError::new(format!("补丁中的路径 {} 是一个目录", path.display())))
+            }
+            Some(_) => {
+                let (text, format) = self.read_text(path)?;
+                self.ensure_unchanged(path, &text)?;
+                (Some(text), format)
+            }
+            None => (None, TextFormat::default()),
+        };
+        formats.insert(path.to_path_buf(), format);
+        original.insert(path.to_path_buf(), content.clone());
+        current.insert(path.to_path_buf(), content.clone());
+        Ok(content)
+    }
+
+    /// 在文件或目录中搜索，返回 `路径:行:列` 形式的匹配和带行号的内容
+    ///
+    /// 搜索目录时跳过隐藏文件、.gitignore 忽略的文件和二进制文件，匹配数超过上限时截断。
+    fn search(&self, path: &Path, metadata: Option<FileMetadata>, pattern: &str) -> Result<ToolResult, ToolError> {
+        let options = &self.search_options;
+        let regex = RegexBuilder::new(&if options.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        })
+        .case_insensitive(options.case_insensitive)
+        .build()
+        .map_err(|e| ToolError::new(format!("无效的正则表达式 `{}`: {}", pattern, e)))?;
+        let include = build_globs(&options.include)?;
+        let exclude = build_globs(&options.exclude)?;
+        let max_results = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS).max(1);
+
+        let mut files = Vec::new();
+        if metadata.is_some_and(|metadata| metadata.is_dir) {
+            let walk_options = WalkOptions {
+                hidden: false,
+                gitignore: true,
+                max_depth: None,
+            };
+            walk(&self.target, path, walk_options, |entry| {
+                if exclude.as_ref().is_some_and(|exclude| exclude.is_match(&entry.relative)) {
+                    return Visit::Skip;
+                }
+                let included = include.as_ref().map_or(true, |include| include.is_match(&entry.relative));
+                if entry.is_file && included {
+                    files.push(entry.path.clone());
+                }
+                Visit::Continue
+            })?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+
+        let mut blocks = Vec::new();
+        let mut matched_files = 0;
+        let mut truncated = false;
+        'files: for file in &files {
+            let Some(content) = self.read_searchable(file) else {
+                continue;
+            };
+            let lines: Vec<&str> = content.strip_suffix('\n').unwrap_or(&content).split('\n').collect();
+            let mut file_matched = false;
+            for (index, line) in lines.iter().enumerate() {
+                for found in regex.find_iter(line) {
+                    if blocks.len() == max_results {
+                        truncated = true;
+                        break 'files;
+                    }
+                    if !file_matched {
+                        file_matched = true;
+                        matched_files += 1;
+                    }
+                    let column = line[..found.start()].chars().count() + 1;
+                    let first = index.saturating_sub(options.context);
+                    let last = (index + options.context).min(lines.len() - 1);
+                    let excerpt: Vec<String> = lines[first..=last]
+                        .iter()
+                        .map(|line| truncate_line(line))
+                        .collect();
+                    blocks.push(format!(
+                        "{}:{}:{}\n{}",
+                        file.display(),
+                        index + 1,
+                        column,
+                        self.number_lines(&excerpt.join("\n"), first + 1)
+                    ));
+                }
+            }
+        }
+
+        if blocks.is_empty() {
+            return Ok(ToolResult::success(format!("在 {} 中没有找到 `{}` 的匹配", path.display(), pattern)));
+        }
+        let mut output = format!(
+            "在 {} 中找到 {} 个匹配（{} 个文件）:\n\n{}",
+            path.display(),
+            blocks.len(),
+            matched_files,
+            blocks.join("\n\n")
         );
-        
-        let snippet = new_file_lines[start_snippet_line..end_snippet_line].join("\n");
-        
-        // 写入文件
-        self.write_file(path, &new_file_content)?;
-        
+        if truncated {
+            output.push_str(&format!(
+                "\n\n<结果已截断，只显示了前 {} 个匹配。请缩小搜索范围或使用更具体的模式。>",
+                max_results
+            ));
+        }
+        Ok(ToolResult::success(output))
+    }
+
+    /// 读取要搜索的文件，不是普通文件、过大、无法读取和二进制文件返回 None
+    fn read_searchable(&self, path: &Path) -> Option<String> {
+        let metadata = self.target.metadata(path).ok()??;
+        // FIFO 和设备文件可能一直读不完
+        if !metadata.is_file || metadata.len > MAX_FILE_SIZE as u64 {
+            return None;
+        }
+        let bytes = self.target.read(path).ok()?;
+        // 带 BOM 的 UTF-16 文件包含 NUL 字节，不是二进制文件
+        if Encoding::for_bom(&bytes).is_none() && bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0) {
+            return None;
+        }
+        match encoding::decode(&bytes) {
+            Ok((text, _)) => Some(text),
+            Err(_) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+        }
+    }
+
+    /// 写入修改并记录历史，返回附带统一差异的结果；预览模式下只返回差异
+    fn apply(
+        &self,
+        path: &Path,
+        operation: &str,
+        before: Option<&str>,
+        after: &str,
+        format: &TextFormat,
+        message: String,
+    ) -> Result<ToolResult, ToolError> {
+        let diff = unified_diff(path, before, Some(after));
+        if self.dry_run {
+            return Ok(ToolResult::success(format!("预览: 以下修改尚未写入 {}\n\n{}", path.display(), diff))
+                .with_diff(diff)
+                .with_system("这是预览，文件未被修改"));
+        }
+
+        let backup = match before {
+            Some(_) => self.backup(path)?,
+            None => None,
+        };
+        self.write_file(path, after, format)?;
+
         // 添加到历史记录
-        let mut history = self.file_history.lock().unwrap();
-        history.entry(path.to_path_buf()).or_insert_with(Vec::new).push(file_content);
-        
-        // 准备成功消息
-        let success_msg = format!(
-            "文件 {} 已编辑。\n\n{}",
+        let note = self.record(path, operation, before, Some(after));
+
+        let message = match backup {
+            Some(backup) => format!("{}\n原文件已备份到 {}", message, backup.display()),
+            None => message,
+        };
+        let result = ToolResult::success(format!("{}\n\n{}", message, diff)).with_diff(diff);
+        Ok(with_history_note(result, note))
+    }
+
+    /// 撤销最近的 `steps` 次编辑
+    ///
+    /// 没有保存内容的修改无法撤销，撤销停在它之后。
+    fn undo_edit(&self, path: &Path, steps: u32) -> Result<ToolResult, ToolError> {
+        let mut snapshots = self.file_history.undo_candidates(&self.session_id, path, steps.max(1))?;
+        let blocked = snapshots.iter().position(|snapshot| !snapshot.recorded);
+        if let Some(position) = blocked {
+            snapshots.truncate(position);
+        }
+        if snapshots.is_empty() {
+            return Err(ToolError::new(match blocked {
+                Some(_) => format!(
+                    "文件 {} 最近的一次修改内容过大，编辑历史没有保存它的内容，无法撤销",
+                    path.display()
+                ),
+                None => format!("未找到文件 {} 可以撤销的编辑历史", path.display()),
+            }));
+        }
+        let members = self.batch_members(path, &snapshots, true)?;
+        self.ensure_in_sync(path, snapshots[0].after.as_deref())?;
+        for member in &members {
+            self.ensure_in_sync(Path::new(&member.path), member.after.as_deref())?;
+        }
+        let current = self.read_current(path);
+        for snapshot in &snapshots {
+            for member in members.iter().filter(|member| member.batch == snapshot.batch) {
+                self.restore(Path::new(&member.path), member.before.as_deref())?;
+                self.file_history.set_undone(member.id, true)?;
+            }
+            self.restore(path, snapshot.before.as_deref())?;
+            self.file_history.set_undone(snapshot.id, true)?;
+        }
+
+        let mut message = match snapshots.len() {
+            1 => format!("文件 {} 的最后一次编辑已撤销", path.display()),
+            n => format!("文件 {} 的最近 {} 次编辑已撤销", path.display(), n),
+        };
+        if blocked.is_some() {
+            message.push_str("（更早的一次修改内容过大，编辑历史没有保存它的内容，撤销停在这里）");
+        }
+        append_members(&mut message, &members, "撤销");
+        Ok(self.history_result(message, path, current, &snapshots, steps, |snapshot| {
+            snapshot.before.as_deref()
+        }))
+    }
+
+    /// 重做最近撤销的 `steps` 次编辑
+    fn redo_edit(&self, path: &Path, steps: u32) -> Result<ToolResult, ToolError> {
+        let snapshots = self.file_history.redo_candidates(&self.session_id, path, steps.max(1))?;
+        if snapshots.is_empty() {
+            return Err(ToolError::new(format!("文件 {} 没有可以重做的编辑", path.display())));
+        }
+        let members = self.batch_members(path, &snapshots, false)?;
+        self.ensure_in_sync(path, snapshots[0].before.as_deref())?;
+        for member in &members {
+            self.ensure_in_sync(Path::new(&member.path), member.before.as_deref())?;
+        }
+        let current = self.read_current(path);
+        for snapshot in &snapshots {
+            for member in members.iter().filter(|member| member.batch == snapshot.batch) {
+                self.restore(Path::new(&member.path), member.after.as_deref())?;
+                self.file_history.set_undone(member.id, false)?;
+            }
+            self.restore(path, snapshot.after.as_deref())?;
+            self.file_history.set_undone(snapshot.id, false)?;
+        }
+
+        let mut message = match snapshots.len() {
+            1 => format!("文件 {} 的编辑已重做", path.display()),
+            n => format!("文件 {} 的 {} 次编辑已重做", path.display(), n),
+        };
+        append_members(&mut message, &members, "重做");
+        Ok(self.history_result(message, path, current, &snapshots, steps, |snapshot| {
+            snapshot.after.as_deref()
+        }))
+    }
+
+    /// 与这些修改同批次的其他文件的修改
+    ///
+    /// 批次中的其他文件之后又有未撤销的修改（重做时是更早的修改尚未重做）时返回错误，
+    /// 避免覆盖这些修改。
+    fn batch_members(&self, path: &Path, snapshots: &[EditSnapshot], undo: bool) -> Result<Vec<EditSnapshot>, ToolError> {
+        let path = path.to_string_lossy();
+        let mut members = Vec::new();
+        for batch in snapshots.iter().filter_map(|snapshot| snapshot.batch) {
+            members.extend(
+                self.file_history
+                    .batch(batch)?
+                    .into_iter()
+                    .filter(|member| member.path != path),
+            );
+        }
+
+        let ids: HashSet<i64> = snapshots.iter().chain(&members).map(|snapshot| snapshot.id).collect();
+        for member in &members {
+            let blocking = self.file_history.blocking(&self.session_id, member, undo)?;
+            if blocking.iter().any(|id| !ids.contains(id)) {
+                return Err(ToolError::new(if undo {
+                    format!("同一补丁修改的文件 {} 之后又被编辑过，请先撤销该文件较新的编辑", member.path)
+                } else {
+                    format!("同一补丁修改的文件 {} 有更早的编辑尚未重做，请先重做该文件的编辑", member.path)
+                }));
+            }
+        }
+        Ok(members)
+    }
+
+    /// 撤销或重做后的结果，附带文件最终的内容和相对于操作前的差异
+    ///
+    /// `previous` 是操作前的文件内容，无法读取时不生成差异。
+    fn history_result(
+        &self,
+        message: String,
+        path: &Path,
+        previous: Option<Option<String>>,
+        snapshots: &[EditSnapshot],
+        steps: u32,
+        content: impl Fn(&EditSnapshot) -> Option<&str>,
+    ) -> ToolResult {
+        let mut message = message;
+        if (snapshots.len() as u32) < steps {
+            message.push_str(&format!("（只有 {} 次编辑可以操作）", snapshots.len()));
+        }
+        let current = snapshots.last().and_then(content);
+        let diff = previous
+            .map(|previous| unified_diff(path, previous.as_deref(), current))
+            .unwrap_or_default();
+        match current {
+            Some(text) => ToolResult::success(format!(
+                "{}。\n\n{}",
+                message,
+                self.make_output(text, &path.to_string_lossy(), 1)
+            )),
+            None => ToolResult::success(format!("{}，文件已删除", message)),
+        }
+        .with_diff(diff)
+    }
+
+    /// 读取文件当前的内容，文件不存在时为 `Some(None)`，无法作为文本读取时为 None
+    fn read_current(&self, path: &Path) -> Option<Option<String>> {
+        match self.target.metadata(path) {
+            Ok(Some(_)) => self.read_file(path).ok().map(Some),
+            Ok(None) => Some(None),
+            Err(_) => None,
+        }
+    }
+
+    /// 记录文件当前的状态，之后的编辑会检查文件是否被外部修改
+    fn remember(&self, path: &Path, content: &str) {
+        let modified = self.target.metadata(path).ok().flatten().and_then(|metadata| metadata.modified);
+        let stamp = FileStamp {
+            hash: content_hash(content),
+            modified,
+            content: (content.len() <= MAX_STAMP_CONTENT).then(|| content.to_string()),
+        };
+        self.file_history.set_stamp(&self.session_id, path, Some(stamp));
+    }
+
+    /// 文件在上次查看或编辑之后被其他程序或Bash命令修改时返回错误，附带修改内容的差异
+    ///
+    /// 没有查看或编辑过的文件不做检查。
+    fn ensure_unchanged(&self, path: &Path, content: &str) -> Result<(), ToolError> {
+        let Some(stamp) = self.file_history.stamp(&self.session_id, path) else {
+            return Ok(());
+        };
+        if stamp.hash == content_hash(content) {
+            return Ok(());
+        }
+
+        let modified = self
+            .target
+            .metadata(path)
+            .ok()
+            .flatten()
+            .and_then(|metadata| metadata.modified)
+            .filter(|modified| Some(*modified) != stamp.modified)
+            .map(|modified| {
+                format!(
+                    "（修改时间 {}）",
+                    DateTime::<Local>::from(modified).format("%Y-%m-%d %H:%M:%S")
+                )
+            })
+            .unwrap_or_default();
+        let mut message = format!(
+            "文件 {} 在上次查看或编辑之后被外部修改{}，编辑未执行。请重新查看文件后再编辑。",
             path.display(),
-            self.make_output(&snippet, &format!("编辑后文件的片段"), start_snippet_line + 1)
+            modified
         );
-        
-        Ok(ToolResult::success(success_msg))
+        if let Some(previous) = &stamp.content {
+            message.push_str(&format!(
+                "\n\n外部修改的内容:\n{}",
+                unified_diff(path, Some(previous), Some(content))
+            ));
+        }
+        Err(ToolError::new(message))
     }
 
-    /// 撤销编辑
-    fn undo_edit(&self, path: &Path) -> Result<ToolResult, ToolError> {
-        let mut history = self.file_history.lock().unwrap();
-        let file_history = history.get_mut(&path.to_path_buf());
-        
-        if let Some(history) = file_history {
-            if let Some(old_text) = history.pop() {
-                self.write_file(path, &old_text)?;
-                
-                return Ok(ToolResult::success(format!(
-                    "文件 {} 的最后一次编辑已撤销。\n\n{}",
-                    path.display(),
-                    self.make_output(&old_text, &path.to_string_lossy(), 1)
-                )));
+    /// 撤销或重做前检查文件仍是编辑历史中最后记录的内容，避免覆盖没有记录的外部修改
+    fn ensure_in_sync(&self, path: &Path, expected: Option<&str>) -> Result<(), ToolError> {
+        let current = match self.target.metadata(path)? {
+            Some(_) => Some(self.read_file(path)?),
+            None => None,
+        };
+        // Bash命令的变更按原始文本记录，可能带有 BOM 和 CRLF 换行
+        let normalize = |text: Option<&str>| {
+            text.map(|text| text.strip_prefix('\u{feff}').unwrap_or(text).replace("\r\n", "\n"))
+        };
+        if normalize(current.as_deref()) == normalize(expected) {
+            return Ok(());
+        }
+        Err(ToolError::new(format!(
+            "文件 {} 在最后一次记录的编辑之后被外部修改，撤销或重做会覆盖这些修改，操作未执行。\n\n外部修改的内容:\n{}",
+            path.display(),
+            unified_diff(path, expected, current.as_deref())
+        )))
+    }
+
+    /// 按设置备份即将被修改的文件，返回新建的备份路径
+    ///
+    /// 备份保存原始字节，不经过编码转换；备份已存在时不覆盖，保留的是最早的内容。
+    fn backup(&self, path: &Path) -> Result<Option<PathBuf>, ToolError> {
+        let backup = match self.backup {
+            BackupMode::Off => return Ok(None),
+            BackupMode::Orig => {
+                let mut name = path.file_name().unwrap_or_default().to_os_string();
+                name.push(".orig");
+                path.with_file_name(name)
+            }
+            BackupMode::Session => {
+                let temp_dir = if self.target.is_local() {
+                    std::env::temp_dir()
+                } else {
+                    PathBuf::from("/tmp")
+                };
+                // 去掉根目录和盘符，保持原文件的路径结构
+                let relative: PathBuf = path
+                    .components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect();
+                let root = temp_dir.join(SESSION_BACKUP_DIR);
+                let session_dir = root.join(file_name_component(&self.session_id));
+                if self.target.metadata(&session_dir)?.is_none() {
+                    if let Err(e) = self.target.remove_stale_dirs(&root, SESSION_BACKUP_RETENTION) {
+                        warn!("清理过期的会话备份失败: {}", e);
+                    }
+                }
+                session_dir.join(relative)
             }
+        };
+        if self.target.metadata(&backup)?.is_some() {
+            return Ok(None);
         }
-        
-        Err(ToolError::new(format!("未找到文件 {} 的编辑历史", path.display())))
+
+        self.target
+            .read(path)
+            .and_then(|content| self.target.write(&backup, &content))
+            .map_err(|e| ToolError::new(format!("备份文件 {} 失败，文件未被修改: {}", path.display(), e)))?;
+        Ok(Some(backup))
+    }
+
+    /// 把文件恢复为指定内容，None 表示文件不存在；已存在的文件保持原来的编码和换行符
+    fn restore(&self, path: &Path, content: Option<&str>) -> Result<(), ToolError> {
+        let format = match self.target.metadata(path)? {
+            Some(_) => self.read_text(path).map(|(_, format)| format).unwrap_or_default(),
+            None => TextFormat::default(),
+        };
+        self.write_or_remove(path, content, &format)
+    }
+
+    /// 按指定格式写入文件，None 表示删除文件
+    fn write_or_remove(&self, path: &Path, content: Option<&str>, format: &TextFormat) -> Result<(), ToolError> {
+        match content {
+            Some(text) => self.write_file(path, text, format),
+            None if self.target.metadata(path)?.is_some() => {
+                self.target.remove(path)?;
+                self.file_history.set_stamp(&self.session_id, path, None);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// 列出文件的编辑历史
+    fn history(&self, path: &Path, limit: Option<u32>) -> Result<ToolResult, ToolError> {
+        let records = self.file_history.list(&self.session_id, path, limit)?;
+        if records.is_empty() {
+            return Ok(ToolResult::success(format!("文件 {} 没有编辑历史", path.display())));
+        }
+
+        let lines: Vec<String> = records
+            .iter()
+            .map(|record| {
+                let change = match (record.existed_before, record.exists_after) {
+                    (false, true) => "新建",
+                    (true, false) => "删除",
+                    _ => "修改",
+                };
+                format!(
+                    "#{} {} {}（{}）{}{}{}{}",
+                    record.id,
+                    record.created_at,
+                    record.operation,
+                    change,
+                    record
+                        .tool_use_id
+                        .as_deref()
+                        .map(|id| format!(" tool_use_id={}", id))
+                        .unwrap_or_default(),
+                    record.batch.map(|batch| format!(" 批次 #{}", batch)).unwrap_or_default(),
+                    if record.undone { " [已撤销]" } else { "" },
+                    if record.recorded { "" } else { " [内容过大，未保存，无法撤销]" }
+                )
+            })
+            .collect();
+        Ok(ToolResult::success(format!(
+            "文件 {} 的编辑历史（最新的在前）:\n{}",
+            path.display(),
+            lines.join("\n")
+        )))
+    }
+
+    /// 把一次修改写入编辑历史，没有完整记录时返回说明原因的消息
+    fn record(&self, path: &Path, operation: &str, before: Option<&str>, after: Option<&str>) -> Option<ToolError> {
+        let edit = NewEdit {
+            path,
+            operation,
+            tool_use_id: self.tool_use_id.as_deref(),
+            before,
+            after,
+        };
+        self.file_history.record(&self.session_id, edit).err()
     }
 
     /// 读取文件内容
     fn read_file(&self, path: &Path) -> Result<String, ToolError> {
-        // 检查文件大小
-        let metadata = fs::metadata(path)
-            .map_err(|e| ToolError::new(format!("获取文件元数据失败: {}", e)))?;
-        
-        if metadata.len() > MAX_FILE_SIZE as u64 {
+        self.read_text(path).map(|(text, _)| text)
+    }
+
+    /// 读取文件内容和文件的编码、换行符
+    fn read_text(&self, path: &Path) -> Result<(String, TextFormat), ToolError> {
+        // 检查文件类型和大小
+        let metadata = self
+            .target
+            .metadata(path)?
+            .ok_or_else(|| ToolError::new(format!("路径 {} 不存在。请提供有效的路径。", path.display())))?;
+        if !metadata.is_file {
+            return Err(ToolError::new(format!("路径 {} 不是普通文件", path.display())));
+        }
+        let len = metadata.len;
+
+        if len > MAX_FILE_SIZE as u64 {
             return Err(ToolError::new(format!(
                 "文件 {} 太大（{} 字节），超过了最大限制 {} 字节。请使用view_range参数查看特定行范围。",
-                path.display(), metadata.len(), MAX_FILE_SIZE
+                path.display(), len, MAX_FILE_SIZE
             )));
         }
-        
-        fs::read_to_string(path).map_err(|e| ToolError::new(format!("读取文件 {} 时出错: {}", path.display(), e)))
+
+        encoding::decode(&self.target.read(path)?)
+            .map_err(|e| ToolError::new(format!("读取文件 {} 时出错: {}", path.display(), e.message)))
     }
 
-    /// 写入文件内容
-    fn write_file(&self, path: &Path, content: &str) -> Result<(), ToolError> {
-        // 确保目录存在
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(|e| {
-                ToolError::new(format!("创建目录 {} 时出错: {}", parent.display(), e))
-            })?;
-        }
-        
-        fs::write(path, content).map_err(|e| ToolError::new(format!("写入文件 {} 时出错: {}", path.display(), e)))
+    /// 按文件原来的编码和换行符写入文件内容
+    fn write_file(&self, path: &Path, content: &str, format: &TextFormat) -> Result<(), ToolError> {
+        let bytes = encoding::encode(content, format)?;
+        // 目标会在需要时创建上级目录
+        self.target.write(path, &bytes)?;
+        self.remember(path, content);
+        Ok(())
     }
 
     /// 生成输出格式
     fn make_output(&self, content: &str, file_descriptor: &str, init_line: usize) -> String {
+        format!(
+            "以下是对 {} 运行 `cat -n` 的结果:\n{}\n",
+            file_descriptor,
+            self.number_lines(content, init_line)
+        )
+    }
+
+    /// 为内容加上从 `init_line` 开始的行号，行数过多时截断中间部分
+    fn number_lines(&self, content: &str, init_line: usize) -> String {
         // 如果内容行数过多，截断显示
         let lines: Vec<&str> = content.split('\n').collect();
-        let content_with_line_numbers = if lines.len() > MAX_DISPLAY_LINES {
+        if lines.len() > MAX_DISPLAY_LINES {
             let first_half = MAX_DISPLAY_LINES / 2;
             let second_half = MAX_DISPLAY_LINES - first_half;
             
@@ -425,12 +1492,642 @@ impl EditTool {
                 .map(|(i, line)| format!("{:6}\t{}", i + init_line, line))
                 .collect::<Vec<_>>()
                 .join("\n")
-        };
-        
-        format!(
-            "以下是对 {} 运行 `cat -n` 的结果:\n{}\n",
-            file_descriptor,
-            content_with_line_numbers
+        }
+    }
+}
+
+/// 把补丁中的路径解析到根目录下，拒绝绝对路径和跳出根目录的 `..`
+fn patch_path(root: &Path, path: &str) -> Result<PathBuf, ToolError> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if relative.pop() => {}
+            _ => {
+                return Err(ToolError::new(format!(
+                    "补丁中的路径 {} 不在根目录 {} 之内，补丁未应用",
+                    path,
+                    root.display()
+                )))
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(ToolError::new(format!("补丁中的路径 {} 不是文件，补丁未应用", path)));
+    }
+    Ok(root.join(relative))
+}
+
+/// 描述块的应用结果
+fn describe_hunk(status: &HunkStatus) -> String {
+    match status {
+        HunkStatus::Applied {
+            line,
+            offset,
+            fuzz,
+            whitespace,
+        } => {
+            let mut text = format!("已应用于第 {} 行", line);
+            if *offset != 0 {
+                text.push_str(&format!("，偏移 {:+} 行", offset));
+            }
+            if *fuzz > 0 {
+                text.push_str(&format!("，忽略了 {} 行上下文", fuzz));
+            }
+            if *whitespace {
+                text.push_str("，忽略了空白差异");
+            }
+            text
+        }
+        HunkStatus::Failed => "未能应用，找不到匹配的上下文".to_string(),
+    }
+}
+
+/// 修改没有完整记录到编辑历史时，在结果的系统消息中说明
+fn with_history_note(result: ToolResult, note: Option<ToolError>) -> ToolResult {
+    match note {
+        Some(note) => result.with_system(note.to_string()),
+        None => result,
+    }
+}
+
+/// 在撤销或重做的消息后附上同批次中一起处理的其他文件
+fn append_members(message: &mut String, members: &[EditSnapshot], action: &str) {
+    let paths: BTreeSet<&str> = members.iter().map(|member| member.path.as_str()).collect();
+    if !paths.is_empty() {
+        message.push_str(&format!(
+            "，同一批次中的 {} 也已{}",
+            paths.into_iter().collect::<Vec<_>>().join("、"),
+            action
+        ));
+    }
+}
+
+/// 按升序排列的字节偏移所在的行号，只扫描文本一遍
+fn line_numbers(text: &str, offsets: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut line = 1;
+    let mut last = 0;
+    offsets
+        .into_iter()
+        .map(|offset| {
+            line += text[last..offset].matches('\n').count();
+            last = offset;
+            line
+        })
+        .collect()
+}
+
+/// 把行号格式化为 `3, 7-9` 的形式
+fn format_lines(lines: &BTreeSet<usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 编译 glob 列表，列表为空时返回 None
+fn build_globs(patterns: &[String]) -> Result<Option<GlobSet>, ToolError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| ToolError::new(format!("无效的 glob `{}`: {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| ToolError::new(format!("无效的 glob: {}", e)))
+}
+
+/// 截断过长的行，避免压缩过的文件占满输出
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_MATCH_LINE_CHARS) {
+        Some((index, _)) => format!("{}…", &line[..index]),
+        None => line.to_string(),
+    }
+}
+
+/// 文件内容的哈希值，只在本进程内比较
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 把字节数格式化为便于阅读的大小
+fn format_size(len: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if len < 1024 {
+        return format!("{} B", len);
+    }
+    let mut size = len as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试用的临时目录，结束时删除
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("maestro-edit-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn write(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, content).unwrap();
+            path
+        }
+
+        fn read(&self, name: &str) -> Option<String> {
+            std::fs::read_to_string(self.0.join(name)).ok()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// `execute` 的参数，未提供的为 None
+    #[derive(Default)]
+    struct Args {
+        old_str: Option<String>,
+        new_str: Option<String>,
+        steps: Option<u32>,
+        edits: Option<Vec<StrEdit>>,
+        patch: Option<String>,
+        pattern: Option<String>,
+    }
+
+    async fn run(tool: &EditTool, command: EditCommand, path: &Path, args: Args) -> Result<ToolResult, ToolError> {
+        tool.execute(
+            command,
+            path.to_string_lossy().into_owned(),
+            None,
+            None,
+            args.old_str,
+            args.new_str,
+            None,
+            args.steps,
+            args.edits,
+            args.patch,
+            args.pattern,
         )
+        .await
+    }
+
+    #[tokio::test]
+    async fn dry_run_returns_the_diff_without_writing() {
+        let dir = TempDir::new();
+        let path = dir.write("a.txt", "one\ntwo\n");
+        let tool = EditTool::new().with_dry_run(true);
+
+        let args = Args { old_str: Some("two".to_string()), new_str: Some("2".to_string()), ..Default::default() };
+        let result = run(&tool, EditCommand::StrReplace, &path, args).await.unwrap();
+        assert!(result.diff.as_deref().unwrap().contains("-two\n+2\n"), "{:?}", result.diff);
+        assert_eq!(result.system.as_deref(), Some("这是预览，文件未被修改"));
+        assert_eq!(dir.read("a.txt").as_deref(), Some("one\ntwo\n"));
+        assert!(tool.file_history.list("default", &path, None).unwrap().is_empty());
+
+        let error = run(&tool, EditCommand::View, &path, Args::default()).await.unwrap_err();
+        assert!(error.to_string().contains("dry_run 只能用于"), "{}", error);
+    }
+
+    fn str_edit(old_str: &str, new_str: &str, replace_all: bool) -> StrEdit {
+        StrEdit {
+            old_str: old_str.to_string(),
+            new_str: Some(new_str.to_string()),
+            replace_all,
+        }
+    }
+
+    #[tokio::test]
+    async fn multi_edit_applies_all_edits_as_one_change() {
+        let dir = TempDir::new();
+        let path = dir.write("a.txt", "fn a() {}\nfn b() {}\nlet x = 1;\nlet x = 1;\n");
+        let tool = EditTool::new();
+
+        let edits = vec![str_edit("fn a", "fn first", false), str_edit("x = 1", "x = 2", true)];
+        let result = run(&tool, EditCommand::MultiEdit, &path, Args { edits: Some(edits), ..Default::default() })
+            .await
+            .unwrap();
+        assert!(result.output.unwrap().contains("2 处修改共替换了 3 处"));
+        assert_eq!(dir.read("a.txt").as_deref(), Some("fn first() {}\nfn b() {}\nlet x = 2;\nlet x = 2;\n"));
+
+        let history = tool.file_history.list("default", &path, None).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].operation, "multi_edit");
+        run(&tool, EditCommand::UndoEdit, &path, Args::default()).await.unwrap();
+        assert_eq!(dir.read("a.txt").as_deref(), Some("fn a() {}\nfn b() {}\nlet x = 1;\nlet x = 1;\n"));
+    }
+
+    #[tokio::test]
+    async fn multi_edit_changes_nothing_when_an_edit_fails() {
+        let dir = TempDir::new();
+        let original = "alpha beta\ngamma\ngamma\n";
+        let path = dir.write("a.txt", original);
+        let tool = EditTool::new();
+        let attempt = |edits: Vec<StrEdit>| {
+            run(&tool, EditCommand::MultiEdit, &path, Args { edits: Some(edits), ..Default::default() })
+        };
+
+        // 第一处修改有效，其余的错误全部报告
+        let edits = vec![
+            str_edit("alpha", "A", false),
+            str_edit("missing", "M", false),
+            str_edit("gamma", "G", false),
+        ];
+        let error = attempt(edits).await.unwrap_err().to_string();
+        assert!(error.contains("第 2 处修改的原字符串 `missing` 未找到"), "{}", error);
+        assert!(error.contains("第 3 处修改的原字符串 `gamma` 在行 [2, 3] 中有多处匹配"), "{}", error);
+
+        let error = attempt(vec![str_edit("alpha beta", "AB", false), str_edit("beta", "B", false)])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("第 1 处和第 2 处修改的范围重叠"), "{}", error);
+
+        let error = attempt(Vec::new()).await.unwrap_err();
+        assert!(error.to_string().contains("修改列表为空"), "{}", error);
+
+        assert_eq!(dir.read("a.txt").as_deref(), Some(original));
+        assert!(tool.file_history.list("default", &path, None).unwrap().is_empty());
+    }
+
+    fn replace(old_str: &str, new_str: &str) -> Args {
+        Args {
+            old_str: Some(old_str.to_string()),
+            new_str: Some(new_str.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn str_replace_expands_regex_captures() {
+        let dir = TempDir::new();
+        let path = dir.write("a.rs", "let a = foo(1);\nlet b = foo(2);\nbar(3);\n");
+        let tool = EditTool::new().with_replace_options(ReplaceOptions {
+            regex: true,
+            expected_count: Some(2),
+            ..Default::default()
+        });
+
+        let result = run(&tool, EditCommand::StrReplace, &path, replace(r"foo\((?<arg>\d)\)", "baz($1, ${arg})"))
+            .await
+            .unwrap();
+        assert!(result.output.unwrap().contains("替换了 2 处，修改的行: 1-2"));
+        assert_eq!(dir.read("a.rs").as_deref(), Some("let a = baz(1, 1);\nlet b = baz(2, 2);\nbar(3);\n"));
+    }
+
+    #[tokio::test]
+    async fn str_replace_checks_the_number_of_matches() {
+        let dir = TempDir::new();
+        let original = "x\ny\nx\nx\n";
+        let path = dir.write("a.txt", original);
+
+        let error = run(&EditTool::new(), EditCommand::StrReplace, &path, replace("x", "z")).await.unwrap_err();
+        assert!(error.to_string().contains("在行 [1, 3, 4] 中有多处匹配"), "{}", error);
+
+        let tool = EditTool::new().with_replace_options(ReplaceOptions {
+            expected_count: Some(2),
+            ..Default::default()
+        });
+        let error = run(&tool, EditCommand::StrReplace, &path, replace("x", "z")).await.unwrap_err();
+        let error = error.to_string();
+        assert!(error.contains("期望匹配 2 处，原字符串 `x` 实际在行 [1, 3, 4] 中匹配了 3 处"), "{}", error);
+
+        let tool = EditTool::new().with_replace_options(ReplaceOptions {
+            regex: true,
+            ..Default::default()
+        });
+        let error = run(&tool, EditCommand::StrReplace, &path, replace("(", "z")).await.unwrap_err();
+        assert!(error.to_string().contains("无效的正则表达式"), "{}", error);
+        assert_eq!(dir.read("a.txt").as_deref(), Some(original));
+
+        let tool = EditTool::new().with_replace_options(ReplaceOptions {
+            replace_all: true,
+            ..Default::default()
+        });
+        let result = run(&tool, EditCommand::StrReplace, &path, replace("x", "1\n2")).await.unwrap();
+        assert!(result.output.unwrap().contains("替换了 3 处，修改的行: 1-2, 4-7"));
+        assert_eq!(dir.read("a.txt").as_deref(), Some("1\n2\ny\n1\n2\n1\n2\n"));
     }
-} 
\ No newline at end of file
+
+    const PATCH: &str = "\
+diff --git a/src/a.txt b/src/a.txt
+--- a/src/a.txt
++++ b/src/a.txt
+@@ -5,3 +5,3 @@
+ two
+-three
++THREE
+ four
+diff --git a/old.txt b/new.txt
+rename from old.txt
+rename to new.txt
+--- a/old.txt
++++ b/new.txt
+@@ -1,2 +1,2 @@
+ keep
+-me
++you
+--- /dev/null
++++ b/created/c.txt
+@@ -0,0 +1,2 @@
++hello
++world
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+";
+
+    fn patch_fixture() -> TempDir {
+        let dir = TempDir::new();
+        dir.write("src/a.txt", "one\ntwo\nthree\nfour\n");
+        dir.write("old.txt", "keep\nme\n");
+        dir.write("gone.txt", "bye\n");
+        dir
+    }
+
+    #[tokio::test]
+    async fn apply_patch_changes_all_files_and_undoes_them_together() {
+        let dir = patch_fixture();
+        let tool = EditTool::new();
+
+        let args = Args { patch: Some(PATCH.to_string()), ..Default::default() };
+        let output = run(&tool, EditCommand::ApplyPatch, &dir.0, args).await.unwrap().output.unwrap();
+        assert!(output.contains("修改了 5 个文件"), "{}", output);
+        // 块头的行号偏了 3 行
+        assert!(output.contains("第 1 块已应用于第 2 行，偏移 -3 行"), "{}", output);
+        assert_eq!(dir.read("src/a.txt").as_deref(), Some("one\ntwo\nTHREE\nfour\n"));
+        assert_eq!(dir.read("old.txt"), None);
+        assert_eq!(dir.read("new.txt").as_deref(), Some("keep\nyou\n"));
+        assert_eq!(dir.read("created/c.txt").as_deref(), Some("hello\nworld\n"));
+        assert_eq!(dir.read("gone.txt"), None);
+
+        // 从任意一个文件撤销都会还原整个补丁
+        run(&tool, EditCommand::UndoEdit, &dir.0.join("new.txt"), Args::default()).await.unwrap();
+        assert_eq!(dir.read("src/a.txt").as_deref(), Some("one\ntwo\nthree\nfour\n"));
+        assert_eq!(dir.read("old.txt").as_deref(), Some("keep\nme\n"));
+        assert_eq!(dir.read("new.txt"), None);
+        assert_eq!(dir.read("created/c.txt"), None);
+        assert_eq!(dir.read("gone.txt").as_deref(), Some("bye\n"));
+
+        run(&tool, EditCommand::RedoEdit, &dir.0.join("gone.txt"), Args::default()).await.unwrap();
+        assert_eq!(dir.read("src/a.txt").as_deref(), Some("one\ntwo\nTHREE\nfour\n"));
+        assert_eq!(dir.read("created/c.txt").as_deref(), Some("hello\nworld\n"));
+        assert_eq!(dir.read("gone.txt"), None);
+    }
+
+    #[tokio::test]
+    async fn apply_patch_changes_nothing_when_a_hunk_fails() {
+        let dir = patch_fixture();
+        let tool = EditTool::new();
+
+        let patch = PATCH.replace("-bye", "-hello");
+        let error = run(&tool, EditCommand::ApplyPatch, &dir.0, Args { patch: Some(patch), ..Default::default() })
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("补丁未应用，没有文件被修改"), "{}", error);
+        assert_eq!(dir.read("src/a.txt").as_deref(), Some("one\ntwo\nthree\nfour\n"));
+        assert_eq!(dir.read("old.txt").as_deref(), Some("keep\nme\n"));
+        assert_eq!(dir.read("new.txt"), None);
+        assert_eq!(dir.read("created/c.txt"), None);
+        assert_eq!(dir.read("gone.txt").as_deref(), Some("bye\n"));
+
+        let args = Args { patch: Some(PATCH.to_string()), ..Default::default() };
+        let error = run(&tool, EditCommand::ApplyPatch, &dir.0.join("old.txt"), args).await.unwrap_err();
+        assert!(error.to_string().contains("不是目录"), "{}", error);
+    }
+
+    fn search_fixture() -> TempDir {
+        let dir = TempDir::new();
+        dir.write(".gitignore", "target/\n");
+        dir.write("src/main.rs", "fn main() {\n    todo!();\n}\n");
+        dir.write("src/lib.rs", "// TODO: docs\npub fn lib() {}\n");
+        dir.write("vendor/dep.rs", "todo\n");
+        dir.write("target/out.rs", "todo\n");
+        dir.write(".hidden.rs", "todo\n");
+        dir.write("notes.md", "TODO later\n");
+        std::fs::write(dir.0.join("data.bin"), b"todo\0\x01").unwrap();
+        dir
+    }
+
+    async fn search(options: SearchOptions, path: &Path, pattern: &str) -> String {
+        let tool = EditTool::new().with_search_options(options);
+        let args = Args { pattern: Some(pattern.to_string()), ..Default::default() };
+        run(&tool, EditCommand::Search, path, args).await.unwrap().output.unwrap()
+    }
+
+    #[tokio::test]
+    async fn search_filters_files_and_shows_context() {
+        let dir = search_fixture();
+
+        // 跳过隐藏文件、被忽略的目录和二进制文件
+        let output = search(SearchOptions::default(), &dir.0, "todo").await;
+        assert!(output.contains("找到 2 个匹配（2 个文件）"), "{}", output);
+        assert!(output.contains(&format!("{}:2:5\n", dir.0.join("src/main.rs").display())), "{}", output);
+        assert!(output.contains(&format!("{}:1:1\n", dir.0.join("vendor/dep.rs").display())), "{}", output);
+
+        let options = SearchOptions {
+            case_insensitive: true,
+            include: vec!["**/*.rs".to_string()],
+            exclude: vec!["vendor".to_string()],
+            context: 1,
+            ..Default::default()
+        };
+        let output = search(options, &dir.0, "todo").await;
+        assert!(output.contains("找到 2 个匹配（2 个文件）"), "{}", output);
+        assert!(output.contains(&format!("{}:1:4\n", dir.0.join("src/lib.rs").display())), "{}", output);
+        assert!(output.contains("     1\tfn main() {\n     2\t    todo!();\n     3\t}"), "{}", output);
+        assert!(!output.contains("notes.md") && !output.contains("dep.rs"), "{}", output);
+
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let output = search(options, &dir.0.join("src/main.rs"), r"fn \w+\(\)").await;
+        assert!(output.contains("找到 1 个匹配（1 个文件）"), "{}", output);
+
+        let output = search(SearchOptions::default(), &dir.0, "missing").await;
+        assert!(output.contains("没有找到 `missing` 的匹配"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn search_stops_at_max_results() {
+        let dir = TempDir::new();
+        dir.write("a.txt", &"match\n".repeat(5));
+        let options = SearchOptions {
+            max_results: Some(3),
+            ..Default::default()
+        };
+        let output = search(options, &dir.0, "match").await;
+        assert!(output.contains("找到 3 个匹配（1 个文件）"), "{}", output);
+        assert!(output.contains("<结果已截断，只显示了前 3 个匹配。"), "{}", output);
+
+        let tool = EditTool::new().with_search_options(SearchOptions {
+            regex: true,
+            ..Default::default()
+        });
+        let args = Args { pattern: Some("(".to_string()), ..Default::default() };
+        let error = run(&tool, EditCommand::Search, &dir.0, args).await.unwrap_err();
+        assert!(error.to_string().contains("无效的正则表达式"), "{}", error);
+    }
+
+    async fn list(options: ListOptions, command: EditCommand, path: &Path, pattern: Option<&str>) -> String {
+        let tool = EditTool::new().with_list_options(options);
+        let args = Args { pattern: pattern.map(str::to_string), ..Default::default() };
+        run(&tool, command, path, args).await.unwrap().output.unwrap()
+    }
+
+    #[tokio::test]
+    async fn view_lists_directories_as_a_tree() {
+        let dir = search_fixture();
+
+        let output = list(ListOptions::default(), EditCommand::View, &dir.0, None).await;
+        assert!(output.contains("（深度 2，不包括隐藏文件和被 .gitignore 忽略的文件）"), "{}", output);
+        assert!(
+            output.ends_with(
+                "data.bin (6 B)\nnotes.md (11 B)\nsrc/\n  lib.rs (30 B)\n  main.rs (27 B)\nvendor/\n  dep.rs (5 B)"
+            ),
+            "{}",
+            output
+        );
+
+        let options = ListOptions {
+            depth: Some(1),
+            hidden: true,
+            ..Default::default()
+        };
+        let output = list(options, EditCommand::View, &dir.0, None).await;
+        assert!(
+            output.ends_with(".gitignore (8 B)\n.hidden.rs (5 B)\ndata.bin (6 B)\nnotes.md (11 B)\nsrc/\nvendor/"),
+            "{}",
+            output
+        );
+
+        let options = ListOptions {
+            max_entries: Some(2),
+            ..Default::default()
+        };
+        let output = list(options, EditCommand::View, &dir.0, None).await;
+        assert!(output.contains("notes.md (11 B)\n\n<列表已截断，只显示了前 2 个条目。"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn glob_matches_relative_paths() {
+        let dir = search_fixture();
+        let paths = |names: &[&str]| -> String {
+            names.iter().map(|name| dir.0.join(name).display().to_string()).collect::<Vec<_>>().join("\n")
+        };
+
+        let output = list(ListOptions::default(), EditCommand::Glob, &dir.0, Some("**/*.rs")).await;
+        assert!(output.contains("找到 3 个匹配 `**/*.rs` 的路径"), "{}", output);
+        assert!(output.ends_with(&paths(&["src/lib.rs", "src/main.rs", "vendor/dep.rs"])), "{}", output);
+
+        // `*` 不匹配 `/`，目录以 `/` 结尾
+        let output = list(ListOptions::default(), EditCommand::Glob, &dir.0, Some("*")).await;
+        let expected = format!("{}\n{}/\n{}/", paths(&["data.bin", "notes.md"]), paths(&["src"]), paths(&["vendor"]));
+        assert!(output.ends_with(&expected), "{}", output);
+
+        let pattern = dir.0.join("src/m*.rs").display().to_string();
+        let output = list(ListOptions::default(), EditCommand::Glob, &dir.0, Some(&pattern)).await;
+        assert!(output.ends_with(&paths(&["src/main.rs"])), "{}", output);
+
+        let output = list(ListOptions::default(), EditCommand::Glob, &dir.0, Some("*.py")).await;
+        assert!(output.contains("没有找到匹配 `*.py` 的路径"), "{}", output);
+
+        let tool = EditTool::new();
+        for pattern in ["/elsewhere/*.rs", "src/[a"] {
+            let args = Args { pattern: Some(pattern.to_string()), ..Default::default() };
+            let error = run(&tool, EditCommand::Glob, &dir.0, args).await.unwrap_err();
+            assert!(error.to_string().contains(pattern), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn session_backups_stay_in_the_backup_directory() {
+        let dir = TempDir::new();
+        let path = dir.write("a.txt", "old\n");
+        let session_id = format!("../../maestro-escape-{}", uuid::Uuid::new_v4());
+        let tool = EditTool::new()
+            .with_history(Arc::new(EditHistory::new()), session_id.clone())
+            .with_backup(BackupMode::Session);
+
+        let result = run(&tool, EditCommand::StrReplace, &path, replace("old", "new")).await.unwrap();
+        let backup_root = std::env::temp_dir().join(SESSION_BACKUP_DIR);
+        let session_dir = backup_root.join(file_name_component(&session_id));
+        let backup: PathBuf = session_dir.join(path.strip_prefix("/").unwrap());
+        let content = std::fs::read_to_string(&backup);
+        let _ = std::fs::remove_dir_all(&session_dir);
+
+        let output = result.output.unwrap();
+        assert!(output.contains(&format!("原文件已备份到 {}", backup.display())), "{}", output);
+        assert_eq!(content.unwrap(), "old\n");
+        assert_eq!(session_dir.parent(), Some(backup_root.as_path()));
+    }
+
+    #[tokio::test]
+    async fn apply_patch_rejects_paths_outside_the_root() {
+        let dir = TempDir::new();
+        let root = dir.0.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        dir.write("outside.txt", "keep\n");
+        let tool = EditTool::new();
+
+        let patch = "--- a/../outside.txt\n+++ b/../outside.txt\n@@ -1 +1 @@\n-keep\n+changed\n".to_string();
+        let error = run(&tool, EditCommand::ApplyPatch, &root, Args { patch: Some(patch), ..Default::default() })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("不在根目录"), "{}", error);
+        assert_eq!(dir.read("outside.txt").as_deref(), Some("keep\n"));
+
+        for target in ["b/../escape.txt", "b/sub/../../escape.txt", "/tmp/escape.txt"] {
+            let patch = format!("--- /dev/null\n+++ {}\n@@ -0,0 +1 @@\n+escaped\n", target);
+            let error = run(&tool, EditCommand::ApplyPatch, &root, Args { patch: Some(patch), ..Default::default() })
+                .await
+                .unwrap_err();
+            assert!(error.to_string().contains("不在根目录"), "{}: {}", target, error);
+        }
+        assert_eq!(dir.read("escape.txt"), None);
+
+        let patch = "--- /dev/null\n+++ b/sub/../inside.txt\n@@ -0,0 +1 @@\n+inside\n".to_string();
+        run(&tool, EditCommand::ApplyPatch, &root, Args { patch: Some(patch), ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(dir.read("root/inside.txt").as_deref(), Some("inside\n"));
+    }
+}
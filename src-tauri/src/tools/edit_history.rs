@@ -0,0 +1,449 @@
+use crate::tools::base::ToolError;
+use chrono::{SecondsFormat, Utc};
+use log::info;
+use rusqlite::{params, Connection, Params, Row, Transaction};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Mutex, time::SystemTime};
+
+/// 每个文件最多保留的历史记录数，超出时删除最早的记录
+const MAX_ENTRIES_PER_FILE: u32 = 100;
+/// 修改前或修改后的内容超过这个大小（字节）时不保存这次修改的内容
+const MAX_RECORDED_SIZE: usize = 2 * 1024 * 1024;
+/// 每个会话保存的内容总量上限（字节），超出时删除最早的记录
+const MAX_SESSION_SIZE: u64 = 256 * 1024 * 1024;
+/// 超过这么多天没有新修改的会话，打开数据库时删除其历史
+const SESSION_RETENTION_DAYS: i64 = 30;
+/// 列出历史时默认返回的条数
+const DEFAULT_LIST_LIMIT: u32 = 20;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS edit_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    tool_use_id TEXT,
+    before TEXT,
+    after TEXT,
+    undone INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    batch INTEGER,
+    recorded INTEGER NOT NULL DEFAULT 1
+);
+CREATE INDEX IF NOT EXISTS idx_edit_history_file ON edit_history (session_id, path, id);
+";
+
+/// 编辑历史中的一次文件修改
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditRecord {
+    pub id: i64,
+    pub session_id: String,
+    pub path: String,
+    /// 修改来源，如 create、str_replace、insert 或 bash
+    pub operation: String,
+    /// 造成修改的工具调用
+    pub tool_use_id: Option<String>,
+    /// 修改前文件是否存在
+    pub existed_before: bool,
+    /// 修改后文件是否存在
+    pub exists_after: bool,
+    /// 是否已被撤销，已撤销的修改可以重做
+    pub undone: bool,
+    /// 修改时间（UTC，RFC 3339）
+    pub created_at: String,
+    /// 同一次调用中一起修改的多个文件共用的批次号，撤销和重做时一起处理
+    pub batch: Option<i64>,
+    /// 是否保存了修改的内容，内容过大时为 false，撤销不能越过这次修改
+    pub recorded: bool,
+}
+
+/// 要写入历史的一次修改，内容为 None 表示文件不存在
+pub struct NewEdit<'a> {
+    pub path: &'a Path,
+    pub operation: &'a str,
+    pub tool_use_id: Option<&'a str>,
+    pub before: Option<&'a str>,
+    pub after: Option<&'a str>,
+}
+
+/// 撤销或重做时需要写回的内容
+pub struct EditSnapshot {
+    pub id: i64,
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub batch: Option<i64>,
+    /// 是否保存了修改的内容，为 false 时 `before` 和 `after` 没有意义
+    pub recorded: bool,
+}
+
+/// 编辑工具最后一次查看或写入文件时文件的状态，用于发现外部修改
+#[derive(Debug, Clone)]
+pub struct FileStamp {
+    /// 文件内容的哈希值
+    pub hash: u64,
+    /// 文件的修改时间
+    pub modified: Option<SystemTime>,
+    /// 较小文件的内容，用于显示外部修改的差异
+    pub content: Option<String>,
+}
+
+/// 按会话保存在 SQLite 中的文件编辑历史，用于多级撤销和重做
+///
+/// 编辑工具和Bash命令的文件变更记录在同一份历史中，`undo_edit` 可以撤销两者中最近的修改。
+/// 每条记录保存修改前后的完整内容；撤销的记录保留到同一文件出现新的修改为止，在此之前可以重做。
+/// 删除旧记录时同批次的记录一起删除，批次总是完整地撤销或重做。
+pub struct EditHistory {
+    conn: Mutex<Connection>,
+    /// 按会话和路径保存的文件状态，只保存在内存中
+    stamps: Mutex<HashMap<(String, String), FileStamp>>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EditHistory {
+    /// 创建只保存在内存中的编辑历史
+    pub fn new() -> Self {
+        let conn = Connection::open_in_memory().expect("打开内存数据库失败");
+        conn.execute_batch(SCHEMA).expect("初始化编辑历史表失败");
+        Self {
+            conn: Mutex::new(conn),
+            stamps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 打开数据库文件，必要时创建表
+    pub fn open(path: &Path) -> Result<Self, ToolError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ToolError::new(format!("创建数据库目录失败: {}", e)))?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| ToolError::new(format!("打开数据库 {} 失败: {}", path.display(), e)))?;
+        conn.execute_batch(SCHEMA)
+            .and_then(|_| migrate(&conn))
+            .map_err(|e| ToolError::new(format!("初始化编辑历史表失败: {}", e)))?;
+        prune_sessions(&conn).map_err(|e| ToolError::new(format!("清理编辑历史失败: {}", e)))?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            stamps: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 编辑工具最后一次查看或写入文件时文件的状态
+    pub fn stamp(&self, session_id: &str, path: &Path) -> Option<FileStamp> {
+        let key = (session_id.to_string(), path.to_string_lossy().into_owned());
+        self.stamps.lock().unwrap().get(&key).cloned()
+    }
+
+    /// 更新文件的状态，None 表示文件已被删除
+    pub fn set_stamp(&self, session_id: &str, path: &Path, stamp: Option<FileStamp>) {
+        let key = (session_id.to_string(), path.to_string_lossy().into_owned());
+        let mut stamps = self.stamps.lock().unwrap();
+        match stamp {
+            Some(stamp) => stamps.insert(key, stamp),
+            None => stamps.remove(&key),
+        };
+    }
+
+    /// 记录一次修改，同一文件已撤销的记录不再能重做
+    ///
+    /// 内容超过 `MAX_RECORDED_SIZE` 时只记录发生过修改，不保存内容，撤销会停在这次修改之后；
+    /// 这时返回说明原因的错误，但之前的历史仍然保留。
+    pub fn record(&self, session_id: &str, edit: NewEdit) -> Result<(), ToolError> {
+        self.record_batch(session_id, std::slice::from_ref(&edit))
+    }
+
+    /// 把一次调用对多个文件的修改记录为一个批次，撤销和重做时作为一步处理
+    pub fn record_batch(&self, session_id: &str, edits: &[NewEdit]) -> Result<(), ToolError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn
+            .transaction()
+            .map_err(|e| ToolError::new(format!("记录编辑历史失败: {}", e)))?;
+
+        // 批次中有一个文件过大时整个批次都不保存内容，撤销时批次作为一步处理
+        let too_large = |text: Option<&str>| text.is_some_and(|text| text.len() > MAX_RECORDED_SIZE);
+        let oversized = edits.iter().find(|edit| too_large(edit.before) || too_large(edit.after));
+
+        let mut ids = Vec::with_capacity(edits.len());
+        for edit in edits {
+            ids.push(
+                insert(&tx, session_id, edit, oversized.is_none())
+                    .map_err(|e| ToolError::new(format!("记录编辑历史失败: {}", e)))?,
+            );
+        }
+        // 批次号使用第一条记录的 id，单个修改不属于批次
+        if let (Some(batch), true) = (ids.first(), ids.len() > 1) {
+            for id in &ids {
+                tx.execute("UPDATE edit_history SET batch = ?1 WHERE id = ?2", params![batch, id])
+                    .map_err(|e| ToolError::new(format!("记录编辑历史失败: {}", e)))?;
+            }
+        }
+        tx.commit()
+            .map_err(|e| ToolError::new(format!("记录编辑历史失败: {}", e)))?;
+
+        match oversized {
+            Some(edit) => Err(ToolError::new(format!(
+                "{} 的内容超过 {} 字节，编辑历史没有保存这次修改的内容，撤销只能回到这次修改之后的状态",
+                edit.path.display(),
+                MAX_RECORDED_SIZE
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// 最近的 `steps` 次未撤销的修改，最新的在前
+    pub fn undo_candidates(&self, session_id: &str, path: &Path, steps: u32) -> Result<Vec<EditSnapshot>, ToolError> {
+        self.snapshots(
+            "SELECT id, path, before, after, batch, recorded FROM edit_history
+             WHERE session_id = ?1 AND path = ?2 AND undone = 0 ORDER BY id DESC LIMIT ?3",
+            params![session_id, path.to_string_lossy(), steps],
+        )
+    }
+
+    /// 最早的 `steps` 次已撤销的修改，即下一次重做的顺序
+    pub fn redo_candidates(&self, session_id: &str, path: &Path, steps: u32) -> Result<Vec<EditSnapshot>, ToolError> {
+        self.snapshots(
+            "SELECT id, path, before, after, batch, recorded FROM edit_history
+             WHERE session_id = ?1 AND path = ?2 AND undone = 1 ORDER BY id ASC LIMIT ?3",
+            params![session_id, path.to_string_lossy(), steps],
+        )
+    }
+
+    /// 同一批次中的所有修改
+    pub fn batch(&self, batch: i64) -> Result<Vec<EditSnapshot>, ToolError> {
+        self.snapshots(
+            "SELECT id, path, before, after, batch, recorded FROM edit_history WHERE batch = ?1 ORDER BY id ASC",
+            params![batch],
+        )
+    }
+
+    /// 撤销（或重做）这条修改之前，同一文件上必须先撤销（或重做）的修改
+    pub fn blocking(&self, session_id: &str, snapshot: &EditSnapshot, undo: bool) -> Result<Vec<i64>, ToolError> {
+        let sql = if undo {
+            "SELECT id FROM edit_history WHERE session_id = ?1 AND path = ?2 AND undone = 0 AND id > ?3"
+        } else {
+            "SELECT id FROM edit_history WHERE session_id = ?1 AND path = ?2 AND undone = 1 AND id < ?3"
+        };
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(sql)
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))?;
+        let rows = stmt
+            .query_map(params![session_id, snapshot.path, snapshot.id], |row| row.get(0))
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))
+    }
+
+    fn snapshots(&self, sql: &str, params: impl Params) -> Result<Vec<EditSnapshot>, ToolError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(sql)
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok(EditSnapshot {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                    before: row.get(2)?,
+                    after: row.get(3)?,
+                    batch: row.get(4)?,
+                    recorded: row.get(5)?,
+                })
+            })
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))
+    }
+
+    /// 标记一条修改已撤销或已重做
+    pub fn set_undone(&self, id: i64, undone: bool) -> Result<(), ToolError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE edit_history SET undone = ?1 WHERE id = ?2", params![undone, id])
+            .map_err(|e| ToolError::new(format!("更新编辑历史失败: {}", e)))?;
+        Ok(())
+    }
+
+    /// 列出文件的编辑历史，最新的在前，默认最多 20 条
+    pub fn list(&self, session_id: &str, path: &Path, limit: Option<u32>) -> Result<Vec<EditRecord>, ToolError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, session_id, path, operation, tool_use_id, before IS NOT NULL, after IS NOT NULL,
+                        undone, created_at, batch, recorded
+                 FROM edit_history WHERE session_id = ?1 AND path = ?2 ORDER BY id DESC LIMIT ?3",
+            )
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))?;
+        let rows = stmt
+            .query_map(
+                params![session_id, path.to_string_lossy(), limit.unwrap_or(DEFAULT_LIST_LIMIT)],
+                record_from_row,
+            )
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ToolError::new(format!("读取编辑历史失败: {}", e)))
+    }
+}
+
+fn record_from_row(row: &Row) -> rusqlite::Result<EditRecord> {
+    Ok(EditRecord {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        path: row.get(2)?,
+        operation: row.get(3)?,
+        tool_use_id: row.get(4)?,
+        existed_before: row.get(5)?,
+        exists_after: row.get(6)?,
+        undone: row.get(7)?,
+        created_at: row.get(8)?,
+        batch: row.get(9)?,
+        recorded: row.get(10)?,
+    })
+}
+
+/// 写入一条修改，返回记录的 id；`recorded` 为 false 时不保存内容
+///
+/// 同一文件已撤销的修改，以及与它们同批次的修改，都不再能重做。
+fn insert(tx: &Transaction, session_id: &str, edit: &NewEdit, recorded: bool) -> rusqlite::Result<i64> {
+    let path = edit.path.to_string_lossy();
+    delete_with_batches(
+        tx,
+        "SELECT id, batch FROM edit_history WHERE session_id = ?1 AND path = ?2 AND undone = 1",
+        params![session_id, path],
+    )?;
+    tx.execute(
+        "INSERT INTO edit_history (session_id, path, operation, tool_use_id, before, after, created_at, recorded)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            session_id,
+            path,
+            edit.operation,
+            edit.tool_use_id,
+            edit.before.filter(|_| recorded),
+            edit.after.filter(|_| recorded),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            recorded,
+        ],
+    )?;
+    let id = tx.last_insert_rowid();
+    delete_with_batches(
+        tx,
+        "SELECT id, batch FROM edit_history WHERE session_id = ?1 AND path = ?2
+         ORDER BY id DESC LIMIT -1 OFFSET ?3",
+        params![session_id, path, MAX_ENTRIES_PER_FILE],
+    )?;
+    // 从最新的记录开始累计内容大小，超出上限的旧记录被删除
+    delete_with_batches(
+        tx,
+        "SELECT id, batch FROM (
+            SELECT id, batch, SUM(IFNULL(LENGTH(CAST(before AS BLOB)), 0) + IFNULL(LENGTH(CAST(after AS BLOB)), 0))
+                OVER (ORDER BY id DESC) AS total
+            FROM edit_history WHERE session_id = ?1
+         ) WHERE total > ?2",
+        params![session_id, MAX_SESSION_SIZE],
+    )?;
+    Ok(id)
+}
+
+/// 删除 `selected` 查询出的记录（`id, batch`）以及与它们同批次的记录
+fn delete_with_batches(tx: &Transaction, selected: &str, params: impl Params) -> rusqlite::Result<()> {
+    tx.execute(
+        &format!(
+            "WITH selected AS ({})
+             DELETE FROM edit_history WHERE id IN (SELECT id FROM selected)
+                OR batch IN (SELECT batch FROM selected WHERE batch IS NOT NULL)",
+            selected
+        ),
+        params,
+    )?;
+    Ok(())
+}
+
+/// 删除超过 `SESSION_RETENTION_DAYS` 天没有新修改的会话的历史
+fn prune_sessions(conn: &Connection) -> rusqlite::Result<()> {
+    let cutoff = (Utc::now() - chrono::Duration::days(SESSION_RETENTION_DAYS))
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    let deleted = conn.execute(
+        "DELETE FROM edit_history WHERE session_id IN (
+            SELECT session_id FROM edit_history GROUP BY session_id HAVING MAX(created_at) < ?1
+         )",
+        params![cutoff],
+    )?;
+    if deleted > 0 {
+        info!("删除了 {} 条过期的编辑历史", deleted);
+    }
+    Ok(())
+}
+
+/// 为旧版本创建的表添加批次列和内容是否保存的标记
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let has_column = |name: &str| -> rusqlite::Result<bool> {
+        conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('edit_history') WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+    };
+    if !has_column("batch")? {
+        conn.execute_batch("ALTER TABLE edit_history ADD COLUMN batch INTEGER")?;
+    }
+    if !has_column("recorded")? {
+        conn.execute_batch("ALTER TABLE edit_history ADD COLUMN recorded INTEGER NOT NULL DEFAULT 1")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit<'a>(path: &'a Path, before: &'a str, after: &'a str) -> NewEdit<'a> {
+        NewEdit {
+            path,
+            operation: "str_replace",
+            tool_use_id: None,
+            before: Some(before),
+            after: Some(after),
+        }
+    }
+
+    #[test]
+    fn trimming_removes_whole_batches() {
+        let history = EditHistory::new();
+        let (a, b) = (Path::new("/w/a"), Path::new("/w/b"));
+        history.record_batch("s", &[edit(a, "0", "1"), edit(b, "0", "1")]).unwrap();
+        for i in 0..MAX_ENTRIES_PER_FILE {
+            history.record("s", edit(a, &i.to_string(), &(i + 1).to_string())).unwrap();
+        }
+
+        assert_eq!(history.list("s", a, Some(1000)).unwrap().len(), MAX_ENTRIES_PER_FILE as usize);
+        assert!(history.list("s", b, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn oversized_edit_keeps_earlier_history() {
+        let history = EditHistory::new();
+        let path = Path::new("/w/a");
+        history.record("s", edit(path, "a", "b")).unwrap();
+
+        let large = "x".repeat(MAX_RECORDED_SIZE + 1);
+        assert!(history.record("s", edit(path, "b", &large)).is_err());
+        let records = history.list("s", path, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].recorded);
+        assert!(records[1].recorded);
+
+        let candidates = history.undo_candidates("s", path, 2).unwrap();
+        assert!(!candidates[0].recorded);
+        assert_eq!(candidates[0].before, None);
+        assert_eq!(candidates[1].before.as_deref(), Some("a"));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::tools::base::ToolError;
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8};
+
+/// 文件使用的换行符
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`，混用多种换行符的文件也按原样保留
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+/// 文件的编码和换行符，写回文件时保持不变
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFormat {
+    pub encoding: &'static Encoding,
+    /// 文件以字节顺序标记开头
+    pub bom: bool,
+    pub line_ending: LineEnding,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+impl TextFormat {
+    /// 是否为不带 BOM、使用 `\n` 换行的 UTF-8
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 描述编码和换行符，如 `GBK，CRLF 换行`
+    pub fn describe(&self) -> String {
+        format!(
+            "{}{}，{} 换行",
+            self.encoding.name(),
+            if self.bom { "（带 BOM）" } else { "" },
+            match self.line_ending {
+                LineEnding::Lf => "LF",
+                LineEnding::CrLf => "CRLF",
+            }
+        )
+    }
+}
+
+/// 识别文件的编码和换行符，返回解码后的文本
+///
+/// 依次检查 BOM、UTF-8、GBK 和 Shift-JIS。只使用 `\r\n` 换行的文件在返回的文本中
+/// 统一为 `\n`，写回时由 [`encode`] 还原。
+pub fn decode(bytes: &[u8]) -> Result<(String, TextFormat), ToolError> {
+    let (encoding, bom, body) = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_len)) => (encoding, true, &bytes[bom_len..]),
+        None => (detect(bytes)?, false, bytes),
+    };
+    let text = encoding
+        .decode_without_bom_handling_and_without_replacement(body)
+        .ok_or_else(|| ToolError::new(format!("文件内容不是有效的 {} 文本", encoding.name())))?
+        .into_owned();
+
+    let crlf = text.matches("\r\n").count();
+    let (text, line_ending) = if crlf > 0 && crlf == text.matches('\n').count() {
+        (text.replace("\r\n", "\n"), LineEnding::CrLf)
+    } else {
+        (text, LineEnding::Lf)
+    };
+
+    Ok((
+        text,
+        TextFormat {
+            encoding,
+            bom,
+            line_ending,
+        },
+    ))
+}
+
+/// 按文件原来的编码和换行符编码文本
+///
+/// 编码无法表示文本中的某些字符时返回错误，不会写入替换字符。
+pub fn encode(text: &str, format: &TextFormat) -> Result<Vec<u8>, ToolError> {
+    let text = match format.line_ending {
+        LineEnding::Lf => text.to_string(),
+        LineEnding::CrLf => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() + 3);
+    // encoding_rs 不支持编码为 UTF-16，需要手动转换
+    if format.encoding == UTF_16LE || format.encoding == UTF_16BE {
+        let little_endian = format.encoding == UTF_16LE;
+        if format.bom {
+            bytes.extend_from_slice(if little_endian { &[0xFF, 0xFE] } else { &[0xFE, 0xFF] });
+        }
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&if little_endian {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            });
+        }
+        return Ok(bytes);
+    }
+
+    if format.bom && format.encoding == UTF_8 {
+        bytes.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+    }
+    let (encoded, _, had_errors) = format.encoding.encode(&text);
+    if had_errors {
+        return Err(ToolError::new(format!(
+            "新内容中有 {} 编码无法表示的字符，文件未被修改",
+            format.encoding.name()
+        )));
+    }
+    bytes.extend_from_slice(&encoded);
+    Ok(bytes)
+}
+
+/// 识别没有 BOM 的文件的编码
+///
+/// 不是有效 UTF-8 的文件只有在无效字节成片出现时才按 GBK 或 Shift-JIS 识别：夹在 ASCII
+/// 之间的单个无效字节，或者有效的 UTF-8 字符多于无效字节，说明文件是损坏的 UTF-8，
+/// 这时返回错误而不是按其他编码解码出乱码。
+///
+/// GBK 和 Shift-JIS 的字节范围大量重叠，两者都能解码时，只有 Shift-JIS 的结果包含假名
+/// 且没有半角片假名才认为是 Shift-JIS，否则按 GBK 处理。
+fn detect(bytes: &[u8]) -> Result<&'static Encoding, ToolError> {
+    let mut rest = bytes;
+    let mut offset = 0;
+    // 无效字节的位置和数量，以及有效的非 ASCII 字符数
+    let mut first_error = None;
+    let mut errors = 0;
+    let mut valid_chars = 0;
+    let non_ascii = |text: &str| text.chars().filter(|c| !c.is_ascii()).count();
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                valid_chars += non_ascii(text);
+                break;
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+                valid_chars += std::str::from_utf8(&rest[..valid]).map_or(0, non_ascii);
+                let position = offset + valid;
+                if is_isolated(bytes, position) {
+                    return Err(ToolError::new(format!(
+                        "文件不是有效的 UTF-8 文本，偏移 {} 处的字节无效，文件可能已损坏",
+                        position
+                    )));
+                }
+                first_error.get_or_insert(position);
+                errors += 1;
+                let skip = valid + e.error_len().unwrap_or(rest.len() - valid);
+                offset += skip;
+                rest = &rest[skip..];
+            }
+        }
+    }
+    let Some(first_error) = first_error else {
+        return Ok(UTF_8);
+    };
+    if valid_chars > errors {
+        return Err(ToolError::new(format!(
+            "文件不是有效的 UTF-8 文本，从偏移 {} 开始有 {} 处无效的字节，文件可能已损坏",
+            first_error, errors
+        )));
+    }
+
+    let gbk = GBK.decode_without_bom_handling_and_without_replacement(bytes);
+    let shift_jis = SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes);
+    match (gbk, shift_jis) {
+        (Some(_), Some(text)) if looks_japanese(&text) => Ok(SHIFT_JIS),
+        (Some(_), _) => Ok(GBK),
+        (None, Some(_)) => Ok(SHIFT_JIS),
+        (None, None) => Err(ToolError::new(
+            "无法识别文件的编码，只支持 UTF-8、UTF-16、GBK 和 Shift-JIS 文本文件",
+        )),
+    }
+}
+
+/// `position` 处的字节前后都是 ASCII 字节，不可能是双字节编码中的字符
+fn is_isolated(bytes: &[u8], position: usize) -> bool {
+    let high = |index: Option<usize>| index.and_then(|index| bytes.get(index)).is_some_and(|byte| *byte >= 0x80);
+    !high(position.checked_sub(1)) && !high(Some(position + 1))
+}
+
+fn looks_japanese(text: &str) -> bool {
+    let has_kana = text.chars().any(|c| ('\u{3040}'..='\u{30FF}').contains(&c));
+    let has_halfwidth_kana = text.chars().any(|c| ('\u{FF61}'..='\u{FF9F}').contains(&c));
+    has_kana && !has_halfwidth_kana
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_legacy_encodings() {
+        let (text, format) = decode(&GBK.encode("// 中文注释\nlet x = 1;\n").0).unwrap();
+        assert_eq!(text, "// 中文注释\nlet x = 1;\n");
+        assert_eq!(format.encoding, GBK);
+
+        let (text, format) = decode(&SHIFT_JIS.encode("こんにちは、世界\n").0).unwrap();
+        assert_eq!(text, "こんにちは、世界\n");
+        assert_eq!(format.encoding, SHIFT_JIS);
+    }
+
+    #[test]
+    fn rejects_damaged_utf8() {
+        // 夹在 ASCII 之间的单个无效字节
+        assert!(decode(b"caf\xe9 au lait\n").is_err());
+        // 大部分是有效的 UTF-8 字符
+        let mut bytes = "中文内容，".repeat(10).into_bytes();
+        bytes.extend_from_slice(b"\xff\xfe");
+        bytes.extend_from_slice("结尾\n".as_bytes());
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn keeps_bom_and_line_endings() {
+        let (text, format) = decode(b"\xef\xbb\xbfa\r\nb\r\n").unwrap();
+        assert_eq!(text, "a\nb\n");
+        assert!(format.bom);
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert_eq!(encode(&text, &format).unwrap(), b"\xef\xbb\xbfa\r\nb\r\n");
+
+        let bytes = [0xFF, 0xFE, b'h', 0, b'i', 0, b'\n', 0];
+        let (text, format) = decode(&bytes).unwrap();
+        assert_eq!(text, "hi\n");
+        assert_eq!(format.encoding, UTF_16LE);
+        assert_eq!(encode(&text, &format).unwrap(), bytes);
+
+        // 混用换行符时原样保留
+        let (text, format) = decode(b"a\r\nb\n").unwrap();
+        assert_eq!(text, "a\r\nb\n");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn refuses_characters_the_encoding_cannot_represent() {
+        let format = TextFormat {
+            encoding: SHIFT_JIS,
+            ..Default::default()
+        };
+        assert!(encode("한국어", &format).is_err());
+    }
+}
@@ -1,10 +1,23 @@
 pub mod computer;
 pub mod bash;
 pub mod edit;
+pub mod edit_history;
+pub mod diff;
+pub mod patch;
+pub mod walk;
+pub mod encoding;
 pub mod base;
+pub mod target;
 
 pub use computer::{ComputerTool, ComputerAction, ScrollDirection};
-pub use bash::BashTool;
-pub use edit::EditTool;
+pub use bash::{
+    BashAction, BashTool, ChangeKind, CommandHistory, CommandPolicy, CommandSignal, EnvProfile, EnvVar,
+    FileChange, HistoryEntry, HistoryQuery, Interpreter, LimitHit, LimitKind, PolicyAction, PolicyRule,
+    PolicyVerdict, ResourceLimits, SandboxConfig, SandboxMode, SessionState, SessionStateStore,
+    DATABASE_FILE, terminate_all_processes,
+};
+pub use edit::{BackupMode, EditTool, ListOptions, ReplaceOptions, SearchOptions, StrEdit};
+pub use edit_history::{EditHistory, EditRecord};
+pub use target::{ContainerRuntime, ContainerTarget, ExecutionTarget, SshTarget};
 pub use base::{ToolResult, ToolError};
 pub use edit::EditCommand; 
\ No newline at end of file
@@ -0,0 +1,640 @@
+use crate::tools::base::ToolError;
+
+/// 匹配失败时最多忽略的首尾上下文行数，与 GNU patch 的默认模糊因子相同
+const MAX_FUZZ: usize = 2;
+/// 文件不存在时使用的路径
+const DEV_NULL: &str = "/dev/null";
+/// `git format-patch` 在补丁末尾的签名分隔行，之后是 git 的版本号
+const SIGNATURE_SEPARATOR: &str = "-- ";
+
+/// 补丁中一个文件的修改，路径为 None 表示文件不存在
+///
+/// 旧路径为 None 时新建文件，新路径为 None 时删除文件，两者不同时重命名文件。
+#[derive(Debug, Clone)]
+pub struct FilePatch {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// 补丁中的一个块
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// 块在原文件中的起始行号，块头中没有行号时为 None
+    pub old_start: Option<usize>,
+    pub lines: Vec<HunkLine>,
+    /// 原文件在块的末尾没有换行符
+    pub old_no_newline: bool,
+    /// 新文件在块的末尾没有换行符
+    pub new_no_newline: bool,
+}
+
+/// 块中的一行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// 块的应用结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkStatus {
+    /// 块应用在原文件的 `line` 行，`offset` 是相对于块头行号的偏移，
+    /// `fuzz` 是忽略的首尾上下文行数，`whitespace` 表示匹配时忽略了空白差异
+    Applied {
+        line: usize,
+        offset: isize,
+        fuzz: usize,
+        whitespace: bool,
+    },
+    /// 找不到匹配的位置
+    Failed,
+}
+
+/// 块中属于原文件的行
+fn old_lines(lines: &[HunkLine]) -> impl Iterator<Item = &str> {
+    lines.iter().filter_map(|line| match line {
+        HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+        HunkLine::Add(_) => None,
+    })
+}
+
+impl Hunk {
+    /// 开头和结尾的上下文行数
+    fn context_bounds(&self) -> (usize, usize) {
+        let is_context = |line: &&HunkLine| matches!(line, HunkLine::Context(_));
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = self.lines.iter().rev().take_while(is_context).count();
+        if leading == self.lines.len() {
+            (leading, 0)
+        } else {
+            (leading, trailing)
+        }
+    }
+}
+
+/// 解析统一差异或 `git diff` 格式的补丁
+///
+/// 块头中有行数时按行数读取块，其中以 `--- `、`+++ ` 开头的行同样是块的内容；块头中
+/// 没有行数时以实际内容为准。块中的空行视为空白的上下文行。
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>, ToolError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut patches = Vec::new();
+    let mut current: Option<FilePatch> = None;
+    // git 文件头之后还没有出现 `---`/`+++` 行
+    let mut git_header = false;
+    // 上一个块之后还没有出现新的文件头
+    let mut after_hunk = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            patches.extend(current.take());
+            after_hunk = false;
+            let (old, new) = parse_git_paths(rest);
+            current = Some(FilePatch {
+                old_path: Some(old),
+                new_path: Some(new),
+                hunks: Vec::new(),
+            });
+            git_header = true;
+            i += 1;
+        } else if is_file_header(&lines, i) {
+            let old_path = parse_header_path(&line[4..]);
+            let new_path = parse_header_path(&lines[i + 1][4..]);
+            match current.as_mut() {
+                Some(patch) if git_header => {
+                    patch.old_path = old_path;
+                    patch.new_path = new_path;
+                }
+                _ => {
+                    patches.extend(current.take());
+                    current = Some(FilePatch {
+                        old_path,
+                        new_path,
+                        hunks: Vec::new(),
+                    });
+                }
+            }
+            git_header = false;
+            after_hunk = false;
+            i += 2;
+        } else if line.starts_with("@@") {
+            let patch = current
+                .as_mut()
+                .ok_or_else(|| ToolError::new(format!("补丁第 {} 行的块之前缺少文件头", i + 1)))?;
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            patch.hunks.push(hunk);
+            git_header = false;
+            after_hunk = true;
+            i = next;
+        } else if line == SIGNATURE_SEPARATOR {
+            after_hunk = false;
+            i += 1;
+        } else if after_hunk && line.starts_with([' ', '-', '+']) {
+            // 块头中的行数比实际内容少，继续应用会漏掉这些行
+            return Err(ToolError::new(format!(
+                "补丁第 {} 行不属于任何块，块头中的行数可能与内容不符",
+                i + 1
+            )));
+        } else {
+            if let Some(patch) = current.as_mut().filter(|_| git_header) {
+                if line.starts_with("new file mode") {
+                    patch.old_path = None;
+                } else if line.starts_with("deleted file mode") {
+                    patch.new_path = None;
+                } else if let Some(path) = line.strip_prefix("rename from ") {
+                    patch.old_path = Some(path.to_string());
+                } else if let Some(path) = line.strip_prefix("rename to ") {
+                    patch.new_path = Some(path.to_string());
+                }
+            }
+            if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                return Err(ToolError::new("不支持二进制文件的补丁"));
+            }
+            i += 1;
+        }
+    }
+    patches.extend(current);
+
+    Ok(patches)
+}
+
+/// 第 `i` 行是否是 `---`/`+++` 文件头
+fn is_file_header(lines: &[&str], i: usize) -> bool {
+    lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|next| next.starts_with("+++ "))
+}
+
+/// 解析 `diff --git a/<旧路径> b/<新路径>` 中的路径
+fn parse_git_paths(rest: &str) -> (String, String) {
+    let rest = rest.trim();
+    match rest.find(" b/") {
+        Some(index) => (
+            strip_prefix(&rest[..index]).to_string(),
+            strip_prefix(&rest[index + 1..]).to_string(),
+        ),
+        None => (strip_prefix(rest).to_string(), strip_prefix(rest).to_string()),
+    }
+}
+
+/// 解析 `---`/`+++` 行中的路径，去掉时间戳和 `a/`、`b/` 前缀
+fn parse_header_path(header: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or(header).trim();
+    let path = path
+        .strip_prefix('"')
+        .and_then(|path| path.strip_suffix('"'))
+        .unwrap_or(path);
+    if path == DEV_NULL {
+        None
+    } else {
+        Some(strip_prefix(path).to_string())
+    }
+}
+
+fn strip_prefix(path: &str) -> &str {
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+}
+
+/// 解析从第 `start` 行开始的块，返回块和块之后的行号
+fn parse_hunk(lines: &[&str], start: usize) -> Result<(Hunk, usize), ToolError> {
+    let mut hunk = Hunk {
+        old_start: parse_old_start(lines[start]),
+        lines: Vec::new(),
+        old_no_newline: false,
+        new_no_newline: false,
+    };
+    let Some((mut old_count, mut new_count)) = parse_counts(lines[start]) else {
+        let next = parse_uncounted_hunk(lines, start + 1, &mut hunk);
+        return Ok((hunk, next));
+    };
+    let mismatch = |i: usize| {
+        ToolError::new(format!(
+            "补丁第 {} 行的块与块头 {} 中的行数不符",
+            i + 1,
+            lines[start]
+        ))
+    };
+    let mut i = start + 1;
+
+    while old_count > 0 || new_count > 0 {
+        let line = *lines.get(i).ok_or_else(|| mismatch(i))?;
+        let parsed = match line.chars().next() {
+            Some(' ') | None if old_count > 0 && new_count > 0 => {
+                old_count -= 1;
+                new_count -= 1;
+                HunkLine::Context(line.get(1..).unwrap_or_default().to_string())
+            }
+            Some('-') if old_count > 0 => {
+                old_count -= 1;
+                HunkLine::Remove(line[1..].to_string())
+            }
+            Some('+') if new_count > 0 => {
+                new_count -= 1;
+                HunkLine::Add(line[1..].to_string())
+            }
+            Some('\\') => {
+                mark_no_newline(&mut hunk);
+                i += 1;
+                continue;
+            }
+            _ => return Err(mismatch(i)),
+        };
+        hunk.lines.push(parsed);
+        i += 1;
+    }
+    // 最后一行之后的 `\ No newline at end of file`
+    if lines.get(i).is_some_and(|line| line.starts_with('\\')) {
+        mark_no_newline(&mut hunk);
+        i += 1;
+    }
+
+    Ok((hunk, i))
+}
+
+/// 解析块头中没有行数的块，读取到下一个块或文件头为止，返回块之后的行号
+fn parse_uncounted_hunk(lines: &[&str], start: usize, hunk: &mut Hunk) -> usize {
+    // 末尾的空行通常是补丁之间的分隔，不属于块
+    let mut trailing_blank = 0;
+    let mut i = start;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if line.starts_with("@@")
+            || line.starts_with("diff --git ")
+            || line == SIGNATURE_SEPARATOR
+            || is_file_header(lines, i)
+        {
+            break;
+        }
+        let parsed = match line.chars().next() {
+            Some(' ') => HunkLine::Context(line[1..].to_string()),
+            Some('-') => HunkLine::Remove(line[1..].to_string()),
+            Some('+') => HunkLine::Add(line[1..].to_string()),
+            Some('\\') => {
+                mark_no_newline(hunk);
+                i += 1;
+                continue;
+            }
+            None => HunkLine::Context(String::new()),
+            Some(_) => break,
+        };
+        trailing_blank = if line.is_empty() { trailing_blank + 1 } else { 0 };
+        hunk.lines.push(parsed);
+        i += 1;
+    }
+    hunk.lines.truncate(hunk.lines.len() - trailing_blank);
+    i
+}
+
+/// 处理 `\ No newline at end of file`，它作用于前一行
+fn mark_no_newline(hunk: &mut Hunk) {
+    match hunk.lines.last() {
+        Some(HunkLine::Remove(_)) => hunk.old_no_newline = true,
+        Some(HunkLine::Add(_)) => hunk.new_no_newline = true,
+        Some(HunkLine::Context(_)) => {
+            hunk.old_no_newline = true;
+            hunk.new_no_newline = true;
+        }
+        None => {}
+    }
+}
+
+/// 解析块头 `@@ -<行号>,<行数> +<行号>,<行数> @@` 中的原文件行号
+fn parse_old_start(header: &str) -> Option<usize> {
+    let range = header.trim_start_matches('@').trim().strip_prefix('-')?;
+    let start = range.split([',', ' ']).next()?;
+    start.parse().ok()
+}
+
+/// 解析块头中原文件和新文件的行数，省略的行数为 1；块头不完整时返回 None
+fn parse_counts(header: &str) -> Option<(usize, usize)> {
+    let ranges = header.strip_prefix("@@ ")?;
+    let ranges = &ranges[..ranges.find(" @@")?];
+    let mut ranges = ranges.split(' ');
+    let count = |range: &str| -> Option<usize> {
+        match range.split_once(',') {
+            Some((start, count)) => {
+                start.parse::<usize>().ok()?;
+                count.parse().ok()
+            }
+            None => range.parse::<usize>().ok().map(|_| 1),
+        }
+    };
+    let old = count(ranges.next()?.strip_prefix('-')?)?;
+    let new = count(ranges.next()?.strip_prefix('+')?)?;
+    if ranges.next().is_some() {
+        return None;
+    }
+    Some((old, new))
+}
+
+/// 把块依次应用到文本上，返回修改后的文本和每个块的结果，失败的块被跳过
+///
+/// 块先在块头的行号附近精确匹配，找不到时忽略空白差异，再逐步忽略首尾的上下文行。
+pub fn apply_hunks(content: &str, hunks: &[Hunk]) -> (String, Vec<HunkStatus>) {
+    let lines: Vec<&str> = if content.is_empty() {
+        Vec::new()
+    } else {
+        content.strip_suffix('\n').unwrap_or(content).split('\n').collect()
+    };
+    let mut eol = content.is_empty() || content.ends_with('\n');
+    let mut result: Vec<&str> = Vec::with_capacity(lines.len());
+    let mut statuses = Vec::with_capacity(hunks.len());
+    // 原文件中已处理到的行
+    let mut cursor = 0;
+    // 前面的块相对于块头行号的偏移，后面的块按同样的偏移查找
+    let mut offset: isize = 0;
+
+    for hunk in hunks {
+        let Some(found) = locate(&lines, hunk, cursor, offset) else {
+            statuses.push(HunkStatus::Failed);
+            continue;
+        };
+
+        result.extend_from_slice(&lines[cursor..found.start]);
+        let mut index = found.start;
+        for line in &hunk.lines[found.body.clone()] {
+            match line {
+                HunkLine::Context(_) => {
+                    result.push(lines[index]);
+                    index += 1;
+                }
+                HunkLine::Remove(_) => index += 1,
+                HunkLine::Add(text) => result.push(text),
+            }
+        }
+        cursor = found.end;
+        if found.end == lines.len() && found.body.end == hunk.lines.len() {
+            if hunk.new_no_newline {
+                eol = false;
+            } else if hunk.old_no_newline {
+                eol = true;
+            }
+        }
+
+        offset = found.offset;
+        statuses.push(HunkStatus::Applied {
+            line: found.start + 1,
+            offset: if hunk.old_start.is_some() { found.offset } else { 0 },
+            fuzz: found.fuzz,
+            whitespace: found.whitespace,
+        });
+    }
+    result.extend_from_slice(&lines[cursor..]);
+
+    let mut text = result.join("\n");
+    if eol && !result.is_empty() {
+        text.push('\n');
+    }
+    (text, statuses)
+}
+
+/// 块在原文件中匹配的位置
+struct Match {
+    start: usize,
+    end: usize,
+    /// 实际使用的块中的行
+    body: std::ops::Range<usize>,
+    /// 相对于块头行号的偏移，块头没有行号时沿用之前的偏移
+    offset: isize,
+    fuzz: usize,
+    whitespace: bool,
+}
+
+/// 在 `min_start` 之后查找块的位置，离预期位置近的优先
+fn locate(lines: &[&str], hunk: &Hunk, min_start: usize, offset: isize) -> Option<Match> {
+    // 块头行号从 1 开始；只有新增行的块的行号指向插入位置之前的一行
+    let has_old = old_lines(&hunk.lines).next().is_some();
+    let raw = hunk
+        .old_start
+        .map(|start| if has_old { start.saturating_sub(1) } else { start });
+    let expected = raw
+        .map(|start| (start as isize + offset).max(0) as usize)
+        .unwrap_or(min_start);
+    let (leading, trailing) = hunk.context_bounds();
+    let mut previous = None;
+
+    for fuzz in 0..=MAX_FUZZ {
+        let skip_start = fuzz.min(leading);
+        let skip_end = fuzz.min(trailing);
+        if previous == Some((skip_start, skip_end)) {
+            // 上下文已经全部忽略，增大模糊因子不会改变结果
+            break;
+        }
+        previous = Some((skip_start, skip_end));
+        let offset_at = |start: usize| {
+            raw.map(|raw| start as isize - skip_start as isize - raw as isize)
+                .unwrap_or(offset)
+        };
+        let body = skip_start..hunk.lines.len() - skip_end;
+        let old: Vec<&str> = old_lines(&hunk.lines[body.clone()]).collect();
+        let target = expected + skip_start;
+
+        if old.is_empty() {
+            let start = target.clamp(min_start, lines.len());
+            return Some(Match {
+                start,
+                end: start,
+                body,
+                offset: offset_at(start),
+                fuzz,
+                whitespace: false,
+            });
+        }
+        if old.len() > lines.len().saturating_sub(min_start) {
+            continue;
+        }
+
+        for whitespace in [false, true] {
+            let matches = |start: usize| {
+                old.iter().zip(&lines[start..start + old.len()]).all(|(expected, actual)| {
+                    if whitespace {
+                        expected.split_whitespace().eq(actual.split_whitespace())
+                    } else {
+                        expected == actual
+                    }
+                })
+            };
+            let last = lines.len() - old.len();
+            if let Some(start) = candidates(target, min_start, last).find(|start| matches(*start)) {
+                return Some(Match {
+                    start,
+                    end: start + old.len(),
+                    body,
+                    offset: offset_at(start),
+                    fuzz,
+                    whitespace,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// 从 `target` 开始向两侧交替的候选位置，范围是 `[first, last]`
+fn candidates(target: usize, first: usize, last: usize) -> impl Iterator<Item = usize> {
+    let target = target.clamp(first, last);
+    let distance = (target - first).max(last - target);
+    (0..=distance).flat_map(move |step| {
+        let below = target.checked_sub(step).filter(|start| *start >= first && step > 0);
+        let above = Some(target + step).filter(|start| *start <= last);
+        above.into_iter().chain(below)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(text: &str) -> HunkLine {
+        HunkLine::Context(text.to_string())
+    }
+
+    fn remove(text: &str) -> HunkLine {
+        HunkLine::Remove(text.to_string())
+    }
+
+    fn add(text: &str) -> HunkLine {
+        HunkLine::Add(text.to_string())
+    }
+
+    fn hunk(old_start: usize, lines: Vec<HunkLine>) -> Hunk {
+        Hunk {
+            old_start: Some(old_start),
+            lines,
+            old_no_newline: false,
+            new_no_newline: false,
+        }
+    }
+
+    #[test]
+    fn parses_format_patch_with_signature() {
+        let patch = "From 1234 Mon Sep 17 00:00:00 2001\n\
+                     Subject: [PATCH] change\n\
+                     ---\n \
+                     a.txt | 2 +-\n\
+                     \n\
+                     diff --git a/a.txt b/a.txt\n\
+                     index 111..222 100644\n\
+                     --- a/a.txt\n\
+                     +++ b/a.txt\n\
+                     @@ -1,2 +1,2 @@\n \
+                     one\n\
+                     -two\n\
+                     +TWO\n\
+                     -- \n\
+                     2.43.0\n";
+        let patches = parse_patch(patch).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].old_path.as_deref(), Some("a.txt"));
+        assert_eq!(patches[0].hunks[0].lines, vec![context("one"), remove("two"), add("TWO")]);
+    }
+
+    #[test]
+    fn counted_hunk_keeps_lines_that_look_like_file_headers() {
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n--- old\n+++ new\n keep\n";
+        let patches = parse_patch(patch).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(
+            patches[0].hunks[0].lines,
+            vec![remove("-- old"), add("++ new"), context("keep")]
+        );
+    }
+
+    #[test]
+    fn rejects_hunks_that_disagree_with_their_header() {
+        // 块头中的行数比内容少
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1,1 +1,1 @@\n-one\n+ONE\n-two\n+TWO\n";
+        assert!(parse_patch(patch).is_err());
+        // 块头中的行数比内容多
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1,3 +1,3 @@\n-one\n+ONE\n";
+        assert!(parse_patch(patch).is_err());
+    }
+
+    #[test]
+    fn parses_no_newline_markers_and_uncounted_hunks() {
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-one\n\\ No newline at end of file\n+one\n";
+        let hunk = &parse_patch(patch).unwrap()[0].hunks[0];
+        assert!(hunk.old_no_newline);
+        assert!(!hunk.new_no_newline);
+        assert_eq!(apply_hunks("one", std::slice::from_ref(hunk)).0, "one\n");
+
+        let patch = "--- a/a.txt\n+++ b/a.txt\n@@\n-one\n+two\n\n";
+        let hunk = &parse_patch(patch).unwrap()[0].hunks[0];
+        assert_eq!(hunk.old_start, None);
+        assert_eq!(hunk.lines, vec![remove("one"), add("two")]);
+    }
+
+    #[test]
+    fn parses_git_file_operations() {
+        let patch = "diff --git a/old.txt b/new.txt\n\
+                     similarity index 100%\n\
+                     rename from old.txt\n\
+                     rename to new.txt\n\
+                     diff --git a/gone.txt b/gone.txt\n\
+                     deleted file mode 100644\n\
+                     --- a/gone.txt\n\
+                     +++ /dev/null\n\
+                     @@ -1 +0,0 @@\n\
+                     -bye\n";
+        let patches = parse_patch(patch).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].old_path.as_deref(), Some("old.txt"));
+        assert_eq!(patches[0].new_path.as_deref(), Some("new.txt"));
+        assert_eq!(patches[1].old_path.as_deref(), Some("gone.txt"));
+        assert_eq!(patches[1].new_path, None);
+    }
+
+    #[test]
+    fn applies_hunks_with_offset() {
+        let content = "a\nb\nc\nd\ne\nf\n";
+        let hunks = [
+            hunk(1, vec![context("c"), remove("d"), add("D")]),
+            hunk(3, vec![context("e"), remove("f"), add("F")]),
+        ];
+        let (text, statuses) = apply_hunks(content, &hunks);
+        assert_eq!(text, "a\nb\nc\nD\ne\nF\n");
+        assert_eq!(
+            statuses,
+            vec![
+                HunkStatus::Applied {
+                    line: 3,
+                    offset: 2,
+                    fuzz: 0,
+                    whitespace: false,
+                },
+                // 沿用前一个块的偏移，在预期位置找到
+                HunkStatus::Applied {
+                    line: 5,
+                    offset: 2,
+                    fuzz: 0,
+                    whitespace: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_hunks_with_fuzz_and_whitespace() {
+        let content = "one\ntwo\nthree\nfour\n";
+        let hunks = [hunk(1, vec![context("ONE"), remove("two"), add("2"), context("three")])];
+        let (text, statuses) = apply_hunks(content, &hunks);
+        assert_eq!(text, "one\n2\nthree\nfour\n");
+        assert!(matches!(statuses[0], HunkStatus::Applied { fuzz: 1, .. }));
+
+        let hunks = [hunk(3, vec![context("three"), remove("four  "), add("4")])];
+        let (text, statuses) = apply_hunks(content, &hunks);
+        assert_eq!(text, "one\ntwo\nthree\n4\n");
+        assert!(matches!(statuses[0], HunkStatus::Applied { fuzz: 0, whitespace: true, .. }));
+
+        let hunks = [hunk(1, vec![remove("missing"), add("x")])];
+        let (text, statuses) = apply_hunks(content, &hunks);
+        assert_eq!(text, content);
+        assert_eq!(statuses, vec![HunkStatus::Failed]);
+    }
+}
@@ -0,0 +1,683 @@
+use crate::tools::base::ToolError;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// SSH 连接复用的控制套接字所在目录（位于系统临时目录下）
+const SSH_CONTROL_DIR: &str = "maestro-ssh";
+/// 最后一个连接关闭后，SSH 主连接继续保留的时间
+const SSH_CONTROL_PERSIST: &str = "10m";
+const SSH_CONNECT_TIMEOUT_SECONDS: u32 = 10;
+/// 在执行目标上运行一个文件操作脚本的超时时间
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 列出目录条目的脚本，每行输出 `<类型> <字节数> 名称`
+///
+/// 类型为 `d`（目录）、`f`（普通文件）或 `o`（其他文件），符号链接按指向的目标判断并在
+/// 类型后加 `l`。只读取普通文件的大小，FIFO 等文件不会被打开。
+const LIST_DIR_SCRIPT: &str = r#"cd "$1" || exit 1
+for f in * .[!.]* ..?*; do
+    [ -e "$f" ] || [ -L "$f" ] || continue
+    link=; [ -L "$f" ] && link=l
+    if [ -d "$f" ]; then echo "d$link 0 $f"
+    elif [ -f "$f" ]; then size=$(wc -c < "$f" 2>/dev/null); echo "f$link $((${size:-0})) $f"
+    else echo "o$link 0 $f"; fi
+done"#;
+
+/// 查询文件类型、大小和修改时间的脚本，输出 `d`、`o` 或 `f <字节数> <修改时间戳>`，不存在时没有输出
+const METADATA_SCRIPT: &str = r#"if [ -d "$1" ]; then echo d
+elif [ -f "$1" ]; then echo "f $(wc -c < "$1") $(stat -c %Y -- "$1" 2>/dev/null || stat -f %m -- "$1" 2>/dev/null)"
+elif [ -e "$1" ]; then echo o
+fi"#;
+
+/// 写入文件的脚本，内容从标准输入读取，必要时创建上级目录
+///
+/// 先写入同一目录中的临时文件再重命名，保留原文件的所有者和权限；
+/// 符号链接写入其指向的文件。`stat` 先按 GNU 的参数调用，失败时按 BSD 的参数调用。
+/// 与本地写入一样，无法修改所有者时只保留所属组，无法读取或设置权限时不写入。
+const WRITE_SCRIPT: &str = r#"target=$1
+if [ -L "$target" ]; then target=$(readlink -f -- "$1") || target=$1; fi
+dir=$(dirname -- "$target")
+mkdir -p -- "$dir" || exit 1
+tmp=$(mktemp "$dir/.$(basename -- "$target").XXXXXX") || exit 1
+trap 'rm -f -- "$tmp"' EXIT
+cat > "$tmp" || exit 1
+if [ -e "$target" ]; then
+    owner=$(stat -c %u:%g -- "$target" 2>/dev/null || stat -f %u:%g -- "$target" 2>/dev/null)
+    mode=$(stat -c %a -- "$target" 2>/dev/null || stat -f %Lp -- "$target" 2>/dev/null)
+    if [ -z "$owner" ] || [ -z "$mode" ]; then echo "无法读取原文件的所有者和权限" >&2; exit 1; fi
+    # 修改所有者会清除 setuid 位，必须在设置权限之前
+    chown -- "$owner" "$tmp" 2>/dev/null || chgrp -- "${owner#*:}" "$tmp" 2>/dev/null
+    chmod -- "$mode" "$tmp" || { echo "无法设置文件权限 $mode" >&2; exit 1; }
+else
+    chmod "$(printf '%o' $((0666 & ~0$(umask))))" -- "$tmp"
+fi
+sync -- "$tmp" 2>/dev/null
+mv -f -- "$tmp" "$target" || exit 1
+trap - EXIT"#;
+
+/// 向进程的所有子孙进程发送信号的脚本，输出收到信号的进程数
+///
+/// 参数: $1 进程pid，$2 信号名（不带 SIG 前缀），$3 为 1 时同时向进程自身发送。
+const SIGNAL_SCRIPT: &str = r#"collect() { for p in $(pgrep -P "$1"); do echo "$p"; collect "$p"; done; }
+pids=$(collect "$1")
+[ "$3" = 1 ] && pids="$pids $1"
+[ -n "$pids" ] && kill -s "$2" $pids 2>/dev/null
+echo $pids | wc -w"#;
+
+/// 命令和文件操作所在的执行目标
+///
+/// Bash会话、后台任务和编辑工具的文件操作都在同一个执行目标上进行。
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionTarget {
+    /// 本机
+    #[default]
+    Local,
+    /// 通过系统的 ssh 客户端连接的主机
+    Ssh(SshTarget),
+    /// 通过 `docker exec` 或 `podman exec` 进入的容器
+    Container(ContainerTarget),
+}
+
+/// SSH 执行目标
+///
+/// 使用 ControlMaster 复用连接，Bash会话、后台任务和文件操作共用同一个主连接。
+/// 认证必须无需交互（密钥或 ssh-agent），主机也可以是 `~/.ssh/config` 中的别名。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SshTarget {
+    pub host: String,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    /// 私钥文件路径
+    pub identity_file: Option<String>,
+}
+
+/// 容器运行时
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    #[default]
+    Docker,
+    Podman,
+}
+
+/// 容器执行目标，容器必须已经在运行
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContainerTarget {
+    #[serde(default)]
+    pub runtime: ContainerRuntime,
+    /// 容器名称或ID
+    pub container: String,
+    /// 在容器中执行命令的用户
+    pub user: Option<String>,
+}
+
+/// 文件或目录的基本信息
+#[derive(Debug, Clone, Copy)]
+pub struct FileMetadata {
+    pub is_dir: bool,
+    /// 是否是普通文件，目录、FIFO、设备和套接字为 false
+    pub is_file: bool,
+    /// 文件大小（字节），目录为 0
+    pub len: u64,
+    /// 修改时间，无法获取时为 None；远程目标只精确到秒
+    pub modified: Option<SystemTime>,
+}
+
+/// 目录中的一个条目
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// 是否是目录，符号链接按指向的目标判断
+    pub is_dir: bool,
+    /// 是否是普通文件，符号链接按指向的目标判断
+    pub is_file: bool,
+    /// 条目本身是否是符号链接
+    pub is_symlink: bool,
+    /// 普通文件的大小（字节），其他条目为 0
+    pub len: u64,
+}
+
+impl ExecutionTarget {
+    pub fn is_local(&self) -> bool {
+        matches!(self, ExecutionTarget::Local)
+    }
+
+    /// 检查配置是否有效
+    pub fn validate(&self) -> Result<(), ToolError> {
+        match self {
+            ExecutionTarget::Local => Ok(()),
+            ExecutionTarget::Ssh(ssh) if ssh.host.is_empty() || ssh.host.starts_with('-') => {
+                Err(ToolError::new(format!("无效的 SSH 主机: {}", ssh.host)))
+            }
+            ExecutionTarget::Container(container)
+                if container.container.is_empty() || container.container.starts_with('-') =>
+            {
+                Err(ToolError::new(format!("无效的容器名称: {}", container.container)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 描述执行目标，用于返回给模型
+    pub fn describe(&self) -> String {
+        match self {
+            ExecutionTarget::Local => "命令在本机执行".to_string(),
+            ExecutionTarget::Ssh(ssh) => format!("命令通过 SSH 在 {} 上执行", ssh.destination()),
+            ExecutionTarget::Container(container) => format!(
+                "命令在 {} 容器 {} 中执行",
+                container.runtime.program(),
+                container.container
+            ),
+        }
+    }
+
+    /// 构造在目标上从 `cwd` 运行 `program` 的命令
+    ///
+    /// 远程目标上未指定 `cwd` 时从登录用户的主目录（容器的默认工作目录）开始。
+    pub fn command(&self, program: &[&str], cwd: Option<&Path>) -> Command {
+        match self {
+            ExecutionTarget::Local => {
+                let mut command = Command::new(program[0]);
+                command.args(&program[1..]);
+                if let Some(cwd) = cwd {
+                    command.current_dir(cwd);
+                }
+                command
+            }
+            ExecutionTarget::Ssh(ssh) => {
+                // 远程命令由登录shell解释，需要逐个转义
+                let program = program
+                    .iter()
+                    .map(|arg| shell_escape::unix::escape((*arg).into()))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let remote_command = match cwd {
+                    Some(cwd) => format!(
+                        "cd {} && exec {}",
+                        shell_escape::unix::escape(cwd.to_string_lossy()),
+                        program
+                    ),
+                    None => format!("exec {}", program),
+                };
+                let mut command = ssh.command();
+                command.arg(remote_command);
+                command
+            }
+            ExecutionTarget::Container(container) => {
+                let mut command = Command::new(container.runtime.program());
+                command.args(["exec", "-i"]);
+                if let Some(user) = &container.user {
+                    command.args(["-u", user]);
+                }
+                if let Some(cwd) = cwd {
+                    command.arg("-w").arg(cwd);
+                }
+                command.arg(&container.container).args(program);
+                command
+            }
+        }
+    }
+
+    /// 获取文件或目录的信息，路径不存在时返回 None
+    pub fn metadata(&self, path: &Path) -> Result<Option<FileMetadata>, ToolError> {
+        if self.is_local() {
+            return match fs::metadata(path) {
+                Ok(metadata) => Ok(Some(FileMetadata {
+                    is_dir: metadata.is_dir(),
+                    is_file: metadata.is_file(),
+                    len: if metadata.is_file() { metadata.len() } else { 0 },
+                    modified: metadata.modified().ok(),
+                })),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(ToolError::new(format!("获取文件元数据失败: {}", e))),
+            };
+        }
+
+        let output = self.run_script(METADATA_SCRIPT, &[&path.to_string_lossy()], None)?;
+        let output = String::from_utf8_lossy(&output);
+        let line = output.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let mut fields = line.split_whitespace();
+        let kind = fields.next().unwrap_or_default();
+        let len = fields.next().and_then(|len| len.parse().ok()).unwrap_or(0);
+        let modified = fields
+            .next()
+            .and_then(|seconds| seconds.parse().ok())
+            .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds));
+        Ok(Some(FileMetadata {
+            is_dir: kind == "d",
+            is_file: kind == "f",
+            len,
+            modified,
+        }))
+    }
+
+    /// 读取文件的全部内容
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, ToolError> {
+        if self.is_local() {
+            return fs::read(path).map_err(|e| ToolError::new(format!("读取文件 {} 时出错: {}", path.display(), e)));
+        }
+        self.run_script(r#"cat -- "$1""#, &[&path.to_string_lossy()], None)
+            .map_err(|e| ToolError::new(format!("读取文件 {} 时出错: {}", path.display(), e)))
+    }
+
+    /// 写入文件，必要时创建上级目录
+    ///
+    /// 内容先写入同一目录中的临时文件，同步到磁盘后再重命名为目标文件，写入中途失败
+    /// 不会留下不完整的文件。已存在的文件保留原来的权限和所有者，本地文件还保留扩展属性；
+    /// 无法读取已存在文件的所有者和权限时不写入。
+    pub fn write(&self, path: &Path, content: &[u8]) -> Result<(), ToolError> {
+        if self.is_local() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    ToolError::new(format!("创建目录 {} 时出错: {}", parent.display(), e))
+                })?;
+            }
+            return write_atomic(path, content)
+                .map_err(|e| ToolError::new(format!("写入文件 {} 时出错: {}", path.display(), e)));
+        }
+        self.run_script(WRITE_SCRIPT, &[&path.to_string_lossy()], Some(content))
+            .map(|_| ())
+            .map_err(|e| ToolError::new(format!("写入文件 {} 时出错: {}", path.display(), e)))
+    }
+
+    /// 删除文件
+    pub fn remove(&self, path: &Path) -> Result<(), ToolError> {
+        if self.is_local() {
+            return fs::remove_file(path)
+                .map_err(|e| ToolError::new(format!("删除文件 {} 时出错: {}", path.display(), e)));
+        }
+        self.run_script(r#"rm -- "$1""#, &[&path.to_string_lossy()], None)
+            .map(|_| ())
+            .map_err(|e| ToolError::new(format!("删除文件 {} 时出错: {}", path.display(), e)))
+    }
+
+    /// 删除目录中修改时间早于 `max_age` 之前的子目录，不跟随符号链接；目录不存在时什么也不做
+    pub fn remove_stale_dirs(&self, path: &Path, max_age: Duration) -> Result<(), ToolError> {
+        let error = |e: &dyn std::fmt::Display| ToolError::new(format!("清理目录 {} 时出错: {}", path.display(), e));
+        if self.is_local() {
+            let entries = match fs::read_dir(path) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(error(&e)),
+            };
+            let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(UNIX_EPOCH);
+            for entry in entries.flatten() {
+                // DirEntry::metadata 不跟随符号链接
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() && metadata.modified().is_ok_and(|modified| modified < cutoff) {
+                    fs::remove_dir_all(entry.path()).map_err(|e| error(&e))?;
+                }
+            }
+            return Ok(());
+        }
+        let minutes = (max_age.as_secs() / 60).to_string();
+        self.run_script(
+            r#"[ ! -d "$1" ] || find "$1" -mindepth 1 -maxdepth 1 -type d -mmin +"$2" -exec rm -rf -- {} +"#,
+            &[&path.to_string_lossy(), &minutes],
+            None,
+        )
+        .map(|_| ())
+        .map_err(|e| error(&e))
+    }
+
+    /// 列出目录中的条目（包括隐藏文件），不保证顺序
+    pub fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, ToolError> {
+        if self.is_local() {
+            let entries = fs::read_dir(path).map_err(|e| ToolError::new(format!("读取目录失败: {}", e)))?;
+            return entries
+                .map(|entry| {
+                    let entry = entry.map_err(|e| ToolError::new(format!("读取目录条目失败: {}", e)))?;
+                    let is_symlink = entry.file_type().is_ok_and(|file_type| file_type.is_symlink());
+                    // 类型跟随符号链接，与 fs::metadata 一致
+                    let metadata = fs::metadata(entry.path()).ok();
+                    let is_file = metadata.as_ref().is_some_and(|metadata| metadata.is_file());
+                    Ok(DirEntry {
+                        name: entry.file_name().to_string_lossy().into_owned(),
+                        is_dir: metadata.as_ref().is_some_and(|metadata| metadata.is_dir()),
+                        is_file,
+                        is_symlink,
+                        len: match metadata {
+                            Some(metadata) if is_file => metadata.len(),
+                            _ => 0,
+                        },
+                    })
+                })
+                .collect();
+        }
+
+        let output = self
+            .run_script(LIST_DIR_SCRIPT, &[&path.to_string_lossy()], None)
+            .map_err(|e| ToolError::new(format!("读取目录失败: {}", e)))?;
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .filter_map(|line| {
+                let (kind, rest) = line.split_once(' ')?;
+                let (len, name) = rest.split_once(' ')?;
+                Some(DirEntry {
+                    name: name.to_string(),
+                    is_dir: kind.starts_with('d'),
+                    is_file: kind.starts_with('f'),
+                    is_symlink: kind.ends_with('l'),
+                    len: len.parse().unwrap_or(0),
+                })
+            })
+            .collect())
+    }
+
+    /// 向目标上进程 `pid` 的所有子孙进程发送信号，返回收到信号的进程数
+    ///
+    /// `include_self` 为 true 时同时向 `pid` 自身发送。目标上需要有 `pgrep`。
+    pub(crate) fn signal_tree(&self, pid: u32, signal: &str, include_self: bool) -> Result<usize, ToolError> {
+        let pid = pid.to_string();
+        let include_self = if include_self { "1" } else { "0" };
+        let output = self.run_script(SIGNAL_SCRIPT, &[&pid, signal, include_self], None)?;
+        Ok(String::from_utf8_lossy(&output).trim().parse().unwrap_or(0))
+    }
+
+    /// 用 `sh -c` 在目标上执行脚本，返回标准输出
+    ///
+    /// 脚本以非 0 退出码结束时，返回包含标准错误的错误；
+    /// 超过 `SCRIPT_TIMEOUT` 没有结束时终止连接并返回错误。
+    fn run_script(&self, script: &str, args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>, ToolError> {
+        let mut program = vec!["sh", "-c", script, "sh"];
+        program.extend(args);
+
+        let mut child = self
+            .command(&program, None)
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| ToolError::new(format!("连接执行目标失败: {}", e)))?;
+
+        // 在单独的线程中写入，避免输入较大时与读取输出互相阻塞
+        let writer = match (input, child.stdin.take()) {
+            (Some(input), Some(mut stdin)) => {
+                let input = input.to_vec();
+                Some(thread::spawn(move || stdin.write_all(&input)))
+            }
+            _ => None,
+        };
+        let stdout = child.stdout.take().map(read_in_background);
+        let stderr = child.stderr.take().map(read_in_background);
+
+        let deadline = Instant::now() + SCRIPT_TIMEOUT;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                Ok(None) => {
+                    // 读取线程可能被仍持有管道的进程阻塞，不再等待它们
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(ToolError::new(format!(
+                        "执行目标在 {} 秒内没有完成操作",
+                        SCRIPT_TIMEOUT.as_secs()
+                    )));
+                }
+                Err(e) => return Err(ToolError::new(format!("连接执行目标失败: {}", e))),
+            }
+        };
+        if let Some(Ok(Err(e))) = writer.map(|writer| writer.join()) {
+            return Err(ToolError::new(format!("发送数据失败: {}", e)));
+        }
+        let join = |reader: Option<thread::JoinHandle<Vec<u8>>>| {
+            reader.map(|reader| reader.join().unwrap_or_default()).unwrap_or_default()
+        };
+        let (stdout, stderr) = (join(stdout), join(stderr));
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(ToolError::new(match stderr.trim() {
+                "" => format!("退出码为 {}", status.code().unwrap_or(-1)),
+                message => message.to_string(),
+            }));
+        }
+        Ok(stdout)
+    }
+}
+
+/// 在单独的线程中读取管道的全部内容
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        let _ = pipe.read_to_end(&mut data);
+        data
+    })
+}
+
+impl SshTarget {
+    /// 形如 `user@host:port` 的描述
+    fn destination(&self) -> String {
+        let mut destination = self.host.clone();
+        if let Some(user) = &self.user {
+            destination = format!("{}@{}", user, destination);
+        }
+        if let Some(port) = self.port {
+            destination = format!("{}:{}", destination, port);
+        }
+        destination
+    }
+
+    /// 带连接复用选项的 ssh 命令，调用者需要追加远程命令
+    fn command(&self) -> Command {
+        let control_dir = std::env::temp_dir().join(SSH_CONTROL_DIR);
+        if let Err(e) = create_private_dir(&control_dir) {
+            warn!("创建 SSH 控制目录 {} 失败: {}", control_dir.display(), e);
+        }
+
+        let mut command = Command::new("ssh");
+        command
+            .arg("-T")
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", &format!("ConnectTimeout={}", SSH_CONNECT_TIMEOUT_SECONDS)])
+            .args(["-o", "ControlMaster=auto"])
+            .arg("-o")
+            .arg(format!("ControlPath={}/%C", control_dir.display()))
+            .args(["-o", &format!("ControlPersist={}", SSH_CONTROL_PERSIST)]);
+        if let Some(user) = &self.user {
+            command.args(["-l", user]);
+        }
+        if let Some(port) = self.port {
+            command.args(["-p", &port.to_string()]);
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command.args(["-i", identity_file]);
+        }
+        command.args(["--", &self.host]);
+        command
+    }
+}
+
+impl ContainerRuntime {
+    fn program(self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+}
+
+/// 通过临时文件和重命名替换本地文件，符号链接写入其指向的文件
+fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    // 悬空的符号链接无法解析，直接替换链接本身
+    let path = if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    } else {
+        path.to_path_buf()
+    };
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| {
+        let mut file = fs::OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        file.write_all(content)?;
+        // 原文件存在但读不到属性时不替换它，否则新文件会悄悄使用 umask 决定的权限
+        match fs::metadata(&path) {
+            Ok(metadata) => copy_attributes(&path, &metadata, &file)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(std::io::Error::new(e.kind(), format!("无法读取原文件的所有者和权限: {}", e))),
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    // 同步目录，确保重命名在断电后依然有效
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// 把原文件的所有者、权限和扩展属性复制到新文件
+///
+/// 普通用户无法把文件交给其他用户，所有者和扩展属性尽量复制，失败时忽略。
+/// 属性按路径读取，原文件没有读权限时同样可以复制。
+fn copy_attributes(from: &Path, metadata: &fs::Metadata, to: &fs::File) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{fchown, MetadataExt};
+        // 修改所有者会清除 setuid 位，必须在设置权限之前
+        if fchown(to, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+            let _ = fchown(to, None, Some(metadata.gid()));
+        }
+    }
+    to.set_permissions(metadata.permissions())?;
+    #[cfg(target_os = "linux")]
+    copy_xattrs(from, to);
+    Ok(())
+}
+
+/// 复制文件的扩展属性（包括 SELinux 标签和 ACL），失败时忽略
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &fs::File) {
+    use std::os::unix::{ffi::OsStrExt, io::AsRawFd};
+
+    let Ok(from) = std::ffi::CString::new(from.as_os_str().as_bytes()) else {
+        return;
+    };
+    let (from, to) = (from.as_ptr(), to.as_raw_fd());
+    // SAFETY: 路径和文件描述符在调用期间有效，缓冲区长度与传入的长度一致
+    unsafe {
+        let len = libc::listxattr(from, std::ptr::null_mut(), 0);
+        if len <= 0 {
+            return;
+        }
+        let mut names = vec![0u8; len as usize];
+        let len = libc::listxattr(from, names.as_mut_ptr().cast(), names.len());
+        if len <= 0 {
+            return;
+        }
+        names.truncate(len as usize);
+
+        for name in names.split(|&byte| byte == 0).filter(|name| !name.is_empty()) {
+            let Ok(name) = std::ffi::CString::new(name) else {
+                continue;
+            };
+            let len = libc::getxattr(from, name.as_ptr(), std::ptr::null_mut(), 0);
+            if len < 0 {
+                continue;
+            }
+            let mut value = vec![0u8; len as usize];
+            let len = libc::getxattr(from, name.as_ptr(), value.as_mut_ptr().cast(), value.len());
+            if len < 0 {
+                continue;
+            }
+            libc::fsetxattr(to, name.as_ptr(), value.as_ptr().cast(), len as usize, 0);
+        }
+    }
+}
+
+/// 创建只有当前用户可以访问的目录，控制套接字不能被其他用户使用
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+    }
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn write_keeps_the_mode_of_unreadable_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("maestro-target-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o200)).unwrap();
+
+        let result = ExecutionTarget::Local.write(&path, b"new");
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let entries = fs::read_dir(&dir).unwrap().count();
+        let _ = fs::remove_dir_all(&dir);
+
+        result.unwrap();
+        assert_eq!(mode, 0o200);
+        assert_eq!(content, "new");
+        assert_eq!(entries, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_fails_when_the_original_cannot_be_inspected() {
+        let dir = std::env::temp_dir().join(format!("maestro-target-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        // 循环的符号链接存在，但无法读取它指向的文件的属性
+        std::os::unix::fs::symlink(dir.join("b"), dir.join("a")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a"), dir.join("b")).unwrap();
+
+        let error = ExecutionTarget::Local.write(&dir.join("a"), b"new").unwrap_err();
+        let mut entries: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        let is_symlink = fs::symlink_metadata(dir.join("a")).unwrap().file_type().is_symlink();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(error.to_string().contains("无法读取原文件的所有者和权限"), "{}", error);
+        assert_eq!(entries, ["a", "b"]);
+        assert!(is_symlink);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::tools::{
+    base::ToolError,
+    target::{DirEntry, ExecutionTarget},
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::warn;
+use std::{
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
+
+/// 遍历时总是跳过的目录
+const SKIPPED_DIRS: &[&str] = &[".git"];
+/// 最大遍历深度
+const MAX_DEPTH: usize = 64;
+
+/// 遍历目录的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkOptions {
+    /// 包括以 `.` 开头的隐藏文件和目录
+    pub hidden: bool,
+    /// 跳过被 .gitignore 忽略的文件和目录
+    pub gitignore: bool,
+    /// 最大深度，根目录中的条目深度为 1
+    pub max_depth: Option<usize>,
+}
+
+/// 遍历到的一个条目
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    /// 相对于根目录的路径，使用 `/` 分隔
+    pub relative: String,
+    /// 是否是目录，符号链接按指向的目标判断
+    pub is_dir: bool,
+    /// 是否是普通文件，符号链接按指向的目标判断；FIFO、设备和套接字为 false
+    pub is_file: bool,
+    /// 条目本身是否是符号链接
+    pub is_symlink: bool,
+    /// 普通文件的大小（字节），其他条目为 0
+    pub len: u64,
+    pub depth: usize,
+}
+
+/// 访问一个条目后的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Continue,
+    /// 不进入这个目录
+    Skip,
+    /// 结束遍历
+    Stop,
+}
+
+/// 深度优先遍历执行目标上的目录，同一目录中的条目按名称排序
+///
+/// 通过执行目标读取目录和 .gitignore，本地和远程目标的结果一致。根目录所在仓库中
+/// 上级目录的 .gitignore 同样生效；无法读取的子目录会被跳过。指向目录的符号链接会被
+/// 访问但不会进入，避免符号链接形成的环重复列出同样的内容。
+pub fn walk(
+    target: &ExecutionTarget,
+    root: &Path,
+    options: WalkOptions,
+    mut visit: impl FnMut(&WalkEntry) -> Visit,
+) -> Result<(), ToolError> {
+    let mut ignores = if options.gitignore {
+        parent_ignores(target, root)
+    } else {
+        Vec::new()
+    };
+    let entries = target.read_dir(root)?;
+    let _ = walk_dir(target, root, "", 1, entries, options, &mut ignores, &mut visit);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn walk_dir(
+    target: &ExecutionTarget,
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    mut entries: Vec<DirEntry>,
+    options: WalkOptions,
+    ignores: &mut Vec<Gitignore>,
+    visit: &mut impl FnMut(&WalkEntry) -> Visit,
+) -> ControlFlow<()> {
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut pushed = false;
+    if options.gitignore && entries.iter().any(|entry| entry.name == ".gitignore" && !entry.is_dir) {
+        if let Some(ignore) = load_gitignore(target, dir) {
+            ignores.push(ignore);
+            pushed = true;
+        }
+    }
+
+    let result = (|| {
+        for entry in entries {
+            if !options.hidden && entry.name.starts_with('.') {
+                continue;
+            }
+            if entry.is_dir && SKIPPED_DIRS.contains(&entry.name.as_str()) {
+                continue;
+            }
+            let path = dir.join(&entry.name);
+            if options.gitignore && is_ignored(ignores, &path, entry.is_dir) {
+                continue;
+            }
+
+            let walk_entry = WalkEntry {
+                relative: if prefix.is_empty() {
+                    entry.name.clone()
+                } else {
+                    format!("{}/{}", prefix, entry.name)
+                },
+                path,
+                is_dir: entry.is_dir,
+                is_file: entry.is_file,
+                is_symlink: entry.is_symlink,
+                len: entry.len,
+                depth,
+            };
+            match visit(&walk_entry) {
+                Visit::Stop => return ControlFlow::Break(()),
+                Visit::Skip => continue,
+                Visit::Continue => {}
+            }
+
+            let descend = walk_entry.is_dir
+                && !walk_entry.is_symlink
+                && depth < MAX_DEPTH
+                && options.max_depth.map_or(true, |max_depth| depth < max_depth);
+            if !descend {
+                continue;
+            }
+            // 无法读取的子目录直接跳过
+            let Ok(children) = target.read_dir(&walk_entry.path) else {
+                continue;
+            };
+            walk_dir(
+                target,
+                &walk_entry.path,
+                &walk_entry.relative,
+                depth + 1,
+                children,
+                options,
+                ignores,
+                visit,
+            )?;
+        }
+        ControlFlow::Continue(())
+    })();
+
+    if pushed {
+        ignores.pop();
+    }
+    result
+}
+
+/// 路径是否被忽略，内层目录的规则优先
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    for ignore in ignores.iter().rev() {
+        let matched = ignore.matched(path, is_dir);
+        if matched.is_ignore() {
+            return true;
+        }
+        if matched.is_whitelist() {
+            return false;
+        }
+    }
+    false
+}
+
+/// 根目录位于仓库的子目录中时，上级目录直到仓库根目录的 .gitignore，外层的在前
+fn parent_ignores(target: &ExecutionTarget, root: &Path) -> Vec<Gitignore> {
+    let is_repository = |dir: &Path| matches!(target.metadata(&dir.join(".git")), Ok(Some(_)));
+    if is_repository(root) {
+        return Vec::new();
+    }
+
+    let mut ignores = Vec::new();
+    for dir in root.ancestors().skip(1) {
+        if matches!(target.metadata(&dir.join(".gitignore")), Ok(Some(metadata)) if !metadata.is_dir) {
+            ignores.extend(load_gitignore(target, dir));
+        }
+        if is_repository(dir) {
+            ignores.reverse();
+            return ignores;
+        }
+    }
+    // 不在仓库中时上级目录的 .gitignore 不生效
+    Vec::new()
+}
+
+/// 读取目录中的 .gitignore，无法读取时返回 None
+fn load_gitignore(target: &ExecutionTarget, dir: &Path) -> Option<Gitignore> {
+    let content = target.read(&dir.join(".gitignore")).ok()?;
+    let mut builder = GitignoreBuilder::new(dir);
+    for line in String::from_utf8_lossy(&content).lines() {
+        if let Err(e) = builder.add_line(None, line) {
+            warn!("忽略 {} 中无效的规则: {}", dir.join(".gitignore").display(), e);
+        }
+    }
+    builder.build().ok()
+}
@@ -7,7 +7,7 @@ import { ChatSession, ChatSessionList } from '@/components/ui/ChatSessionList';
 import { MainNavigation } from '@/components/ui/MainNavigation';
 import { MobileNavigation } from '@/components/ui/MobileNavigation';
 import { SettingsData, SettingsPanel } from '@/components/ui/SettingsPanel';
-import { core, event } from '@tauri-apps/api';
+import { core, event, path } from '@tauri-apps/api';
 import { Menu, Plus, Sparkles } from 'lucide-react';
 import { useEffect, useRef, useState } from 'react';
 import { v4 as uuidv4 } from 'uuid';
@@ -78,6 +78,7 @@ export default function ChatPage() {
     enableComputerTool: true,
     enableBashTool: true,
     enableEditTool: true,
+    workspaceRoot: '',
 
     // 输出配置
     maxOutputTokens: 16384,
@@ -99,6 +100,8 @@ export default function ChatPage() {
   const [isProcessing, setIsProcessing] = useState(false);
   const [currentResponseId, setCurrentResponseId] = useState<string | null>(null);
   const [apiClient, setApiClient] = useState<ClaudeApiClient | null>(null);
+  // 已经告诉后端的各会话工作区根目录
+  const appliedWorkspaceRoots = useRef(new Map<string, string>());
 
   // 滚动到底部函数
   const scrollToBottom = () => {
@@ -528,6 +531,22 @@ export default function ChatPage() {
         }
       }
 
+      // 工具执行之前设置会话的工作区根目录
+      if (currentSessionId) {
+        try {
+          await applyWorkspaceRoot(currentSessionId);
+        } catch (error) {
+          console.error('设置工作区根目录失败:', error);
+          setTools(prev => [...prev, {
+            id: uuidv4(),
+            type: 'error',
+            title: '设置工作区根目录失败',
+            content: String(error),
+            timestamp: new Date(),
+          }]);
+        }
+      }
+
       // 调用Claude API
       const result = await callClaudeAPI(
         updatedClaudeMessages,
@@ -547,6 +566,7 @@ export default function ChatPage() {
           enableEditTool: settings.enableEditTool,
           toolVersion: settings.toolVersion,
           computerToolOptions: settings.computerToolOptions,
+          sessionId: currentSessionId ?? undefined,
         },
         handleContentBlock,
         handleToolResult
@@ -606,6 +626,17 @@ export default function ChatPage() {
     }
   };
 
+  // 设置会话的工作区根目录，Bash 会话从这里启动；目录没有变化时不重复设置，以免改变 shell 的当前目录
+  const applyWorkspaceRoot = async (sessionId: string) => {
+    const session = sessions.find(s => s.id === sessionId);
+    const workspaceRoot = session?.workspaceRoot || settings.workspaceRoot || await path.homeDir();
+    if (appliedWorkspaceRoots.current.get(sessionId) === workspaceRoot) {
+      return;
+    }
+    await invoke<ToolResult>('set_workspace_root', { sessionId, workspaceRoot });
+    appliedWorkspaceRoots.current.set(sessionId, workspaceRoot);
+  };
+
   // 创建新会话
   const handleCreateSession = () => {
     const newSession: ChatSession = {
@@ -614,6 +645,7 @@ export default function ChatPage() {
       lastMessage: '新建会话',
       timestamp: new Date(),
       isActive: true,
+      workspaceRoot: settings.workspaceRoot || undefined,
     };
 
     setSessions(prev => prev.map(s => ({ ...s, isActive: false })).concat(newSession));
@@ -8,6 +8,8 @@ export interface ChatSession {
   lastMessage?: string;
   timestamp: Date;
   isActive: boolean;
+  // 会话的工作区根目录，Bash 会话从这里启动，未设置时使用设置中的默认目录
+  workspaceRoot?: string;
 }
 
 interface ChatSessionListProps {
@@ -24,6 +24,8 @@ export interface SettingsData {
   enableComputerTool: boolean;
   enableBashTool: boolean;
   enableEditTool: boolean;
+  // 新会话的工作区根目录，留空时使用用户主目录
+  workspaceRoot: string;
   
   // 输出配置
   maxOutputTokens: number;
@@ -271,6 +273,24 @@ export const SettingsPanel: React.FC<SettingsProps> = ({
                 </div>
               </div>
             </div>
+
+            <div className="space-y-2">
+              <label htmlFor="workspaceRoot" className="block text-sm font-medium">
+                工作区根目录
+              </label>
+              <input
+                type="text"
+                id="workspaceRoot"
+                name="workspaceRoot"
+                value={settings.workspaceRoot}
+                onChange={handleChange}
+                className="w-full px-4 py-2 border rounded-md focus:ring-2 focus:ring-[hsl(var(--primary))] focus:border-[hsl(var(--primary))] bg-[hsl(var(--background))]"
+                placeholder="/home/user/project"
+              />
+              <p className="text-xs text-[hsl(var(--muted-foreground))]">
+                新会话的 Bash 命令从这个绝对路径开始执行，留空时使用用户主目录
+              </p>
+            </div>
           </div>
           
           {/* 输出配置 */}
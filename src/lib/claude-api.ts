@@ -1,6 +1,7 @@
 import { Anthropic } from '@anthropic-ai/sdk';
 import { core } from '@tauri-apps/api';
 import { ContentBlock, ImageBlock, Message, TextBlock, Tool, ToolResult, ToolResultBlock, ToolUseBlock } from './claude';
+import { toApiTool } from './tools';
 
 // Claude API 客户端
 export class ClaudeApiClient {
@@ -51,54 +52,21 @@ export class ClaudeApiClient {
       onlyNMostRecentImages?: number;
       tokenEfficientToolsBeta?: boolean;
       promptCaching?: boolean;
+      sessionId?: string;
     }
   ): Promise<Message[]> {
     // 创建新的AbortController
     this.abortController = new AbortController();
     const signal = this.abortController.signal;
 
+    // 工具在当前聊天会话的 Bash 会话和编辑历史中执行
+    const sessionId = options?.sessionId;
+
     // 转换消息格式为Anthropic API兼容格式
     const anthropicMessages = this.convertToAnthropicMessages(messages);
 
     // 准备工具
-    const anthropicTools = tools.length > 0 ? tools.map(tool => {
-      // 基本工具定义
-      if (tool.name === 'computer') {
-        // 对于计算机工具，使用Anthropic定义的工具格式
-        const toolVersion = "computer_20250124";
-        
-        // 根据文档，计算机工具应该只有type和name，其他参数作为单独的顶级属性
-        return {
-          type: toolVersion,
-          name: tool.name,
-          display_width_px: tool.options?.display_width_px,
-          display_height_px: tool.options?.display_height_px,
-          display_number: tool.options?.display_number ?? 1
-        };
-      } else if (tool.name === 'bash') {
-        // 对于bash工具，使用Anthropic定义的工具格式
-        const toolVersion = 'bash_20250124';
-        return {
-          type: toolVersion,
-          name: tool.name
-        };
-      } else if (tool.name === 'edit') {
-        // 对于编辑工具，使用Anthropic定义的工具格式
-        const toolVersion = 'text_editor_20250124';
-        return {
-          type: toolVersion,
-          name: 'str_replace_editor'  // 使用正确的工具名称
-        };
-      } else {
-        // 其他自定义工具
-        return {
-          type: 'custom',
-          name: tool.name,
-          description: tool.description,
-          input_schema: tool.input_schema,
-        };
-      }
-    }) : undefined;
+    const anthropicTools = tools.length > 0 ? tools.map(toApiTool) : undefined;
 
     // 准备额外参数
     const extraParams: Record<string, any> = {};
@@ -316,7 +284,7 @@ export class ClaudeApiClient {
               try {
                 // 添加超时处理
                 const bashPromise = core.invoke<ToolResult>('execute_bash_command', {
-                  args: { ...toolInput }
+                  args: { ...toolInput, session_id: sessionId, tool_use_id: toolUseId }
                 });
 
                 // 设置超时
@@ -331,19 +299,13 @@ export class ClaudeApiClient {
 
                 console.log('Bash命令执行结果:', JSON.stringify(result, null, 2));
 
-                // 检查是否需要重启会话
-                if (result.system && result.system.includes('重启')) {
-                  console.log('Bash会话需要重启，自动重启中...');
-                  try {
-                    // 尝试重启会话
-                    await core.invoke<ToolResult>('execute_bash_command', {
-                      args: { restart: true }
-                    });
-                    console.log('Bash会话已重启');
-                  } catch (restartError) {
-                    console.error('重启Bash会话失败:', restartError);
-                  }
+                // 命令需要用户批准，由用户决定后再执行
+                if (result.approval_token) {
+                  result = await this.requestApproval(result, toolInput.command ?? toolInput.input, sessionId);
                 }
+
+                // bash 进程退出后，后端会在下一条命令时自动启动新的会话，这里不根据提示文本重启，
+                // 否则仍在运行或等待输入的命令会被终止
               } catch (error) {
                 console.error('Bash命令执行失败:', error);
 
@@ -357,7 +319,7 @@ export class ClaudeApiClient {
                 try {
                   console.log('尝试自动重启Bash会话...');
                   await core.invoke<ToolResult>('execute_bash_command', {
-                    args: { restart: true }
+                    args: { restart: true, session_id: sessionId }
                   });
                   console.log('Bash会话已重启');
                 } catch (restartError) {
@@ -396,10 +358,27 @@ export class ClaudeApiClient {
 
               // 执行文件编辑
               result = await core.invoke<ToolResult>('execute_edit_command', {
-                args: { ...transformedEditInput }
+                args: { ...transformedEditInput, session_id: sessionId, tool_use_id: toolUseId }
               });
               break;
 
+            case 'repl':
+              // 在 Python 或 Node.js REPL 中执行代码
+              console.log('执行REPL代码:', JSON.stringify(toolInput, null, 2));
+              result = await core.invoke<ToolResult>('execute_repl_command', {
+                interpreter: toolInput.language,
+                args: {
+                  command: toolInput.code,
+                  restart: toolInput.restart,
+                  session_id: sessionId,
+                  tool_use_id: toolUseId
+                }
+              });
+              if (result.approval_token) {
+                result = await this.requestApproval(result, toolInput.code, sessionId, toolInput.language);
+              }
+              break;
+
             default:
               // 不支持的工具
               result = {
@@ -467,6 +446,22 @@ export class ClaudeApiClient {
     }
   }
 
+  // 请用户批准等待执行的命令，批准令牌只在这里使用，不会出现在发送给模型的工具结果中
+  private async requestApproval(
+    result: ToolResult,
+    command: string | undefined,
+    sessionId: string | undefined,
+    interpreter?: string
+  ): Promise<ToolResult> {
+    const approved = window.confirm(`${result.error ?? '命令需要批准'}\n\n${command ?? ''}\n\n是否允许执行？`);
+    return core.invoke<ToolResult>('approve_bash_command', {
+      sessionId,
+      interpreter,
+      token: result.approval_token,
+      approved
+    });
+  }
+
   // 转换为Anthropic消息格式
   private convertToAnthropicMessages(messages: Message[]) {
     // 打印转换前的消息
@@ -1,17 +1,13 @@
 import { core } from '@tauri-apps/api';
 import { ClaudeApiClient } from './claude-api';
+import { AVAILABLE_TOOLS, ComputerToolOptions, Tool, withEnvProfiles } from './tools';
+
+export * from './tools';
 
 // 工具版本类型
 export type ToolVersion = 'computer_use_20250124' | 'computer_use_20241022';
 export type BetaFlag = 'computer-use-2024-10-22' | 'computer-use-2025-01-24';
 
-// 计算机工具选项接口
-export interface ComputerToolOptions {
-  display_width_px: number;
-  display_height_px: number;
-  display_number?: number;
-}
-
 // 工具组定义
 export interface ToolGroup {
   version: ToolVersion;
@@ -92,6 +88,8 @@ export interface ToolResult {
   error?: string;
   base64_image?: string;
   system?: string;
+  // 命令等待用户批准时的批准令牌，只在界面中使用，不发送给模型
+  approval_token?: string;
 }
 
 // 处理工具结果，确保图片数据正确传递
@@ -125,112 +123,18 @@ export interface ClaudeConfig {
   toolVersion: ToolVersion;
   promptCaching?: boolean;
   computerToolOptions?: ComputerToolOptions;
+  // 当前聊天会话ID，工具在这个会话的 Bash 会话和编辑历史中执行
+  sessionId?: string;
 }
 
-// 工具定义
-export interface Tool {
-  name: string;
-  description: string;
-  input_schema: {
-    type: string;
-    properties: Record<string, any>;
-    required?: string[];
-    additionalProperties?: boolean;
-  };
-  options?: ComputerToolOptions;
-}
-
-// 可用工具
-export const AVAILABLE_TOOLS: Tool[] = [
-  {
-    name: 'computer',
-    description: '控制计算机执行各种操作，如截图、点击等',
-    input_schema: {
-      type: 'object',
-      properties: {
-        action: {
-          type: 'string',
-          enum: [
-            'screenshot', 
-            'mouse_move', 
-            'left_click', 
-            'right_click', 
-            'middle_click', 
-            'double_click', 
-            'triple_click', 
-            'type', 
-            'key', 
-            'cursor_position', 
-            'left_mouse_down', 
-            'left_mouse_up', 
-            'scroll', 
-            'hold_key', 
-            'wait'
-          ],
-        },
-        coordinate: { 
-          type: 'array',
-          items: { type: 'number' },
-          minItems: 2,
-          maxItems: 2
-        },
-        text: { type: 'string' },
-        key: { type: 'string' },
-        scroll_direction: { type: 'string', enum: ['up', 'down', 'left', 'right'] },
-        scroll_amount: { type: 'number' },
-        duration: { type: 'number' },
-      },
-      required: ['action'],
-      additionalProperties: false,
-    },
-  },
-  {
-    name: 'bash',
-    description: '执行 Bash 命令',
-    input_schema: {
-      type: 'object',
-      properties: {
-        command: { type: 'string' },
-        restart: { type: 'boolean' },
-      },
-      required: ['command'],
-      additionalProperties: false,
-    },
-  },
-  {
-    name: 'edit',
-    description: '编辑文件',
-    input_schema: {
-      type: 'object',
-      properties: {
-        command: {
-          type: 'string',
-          enum: ['view', 'create', 'str_replace', 'insert', 'undo_edit'],
-        },
-        path: { type: 'string' },
-        file_text: { type: 'string' },
-        view_range: { 
-          type: 'array',
-          items: { type: 'number' },
-          minItems: 2,
-          maxItems: 2
-        },
-        old_str: { type: 'string' },
-        new_str: { type: 'string' },
-        insert_line: { type: 'number' },
-      },
-      required: ['command', 'path'],
-      additionalProperties: false,
-    },
-  },
-];
-
 // 计算机工具
 export const COMPUTER_TOOL = AVAILABLE_TOOLS.find(t => t.name === 'computer')!;
 // Bash工具
 export const BASH_TOOL = AVAILABLE_TOOLS.find(t => t.name === 'bash')!;
 // 编辑工具
 export const EDIT_TOOL = AVAILABLE_TOOLS.find(t => t.name === 'edit')!;
+// REPL 工具
+export const REPL_TOOL = AVAILABLE_TOOLS.find(t => t.name === 'repl')!;
 
 // 获取系统架构
 function getSystemArchitecture(): string {
@@ -245,7 +149,7 @@ const DEFAULT_SYSTEM_PROMPT = `<SYSTEM_CAPABILITY>
 * You can feel free to install applications with your bash tool. Use curl instead of wget.
 * To open a browser, please just click on the browser icon or use the bash tool to launch it.
 * Using bash tool you can start GUI applications, but they may take some time to appear. Take a screenshot to confirm it did.
-* When using your bash tool with commands that are expected to output very large quantities of text, redirect into a tmp file and use str_replace_editor or \`grep -n -B <lines before> -A <lines after> <query> <filename>\` to confirm output.
+* When using your bash tool with commands that are expected to output very large quantities of text, redirect into a tmp file and use the edit tool's search command or \`grep -n -B <lines before> -A <lines after> <query> <filename>\` to confirm output.
 * When viewing a page it can be helpful to zoom out so that you can see everything on the page. Either that, or make sure you scroll down to see everything before deciding something isn't available.
 * When using your computer function calls, they take a while to run and send back to you. Where possible/feasible, try to chain multiple of these calls all into one function calls request.
 * The current date is ${new Date().toLocaleDateString('en-US', { weekday: 'long', year: 'numeric', month: 'long', day: 'numeric' })}.
@@ -281,15 +185,24 @@ export async function callClaudeAPI(
     tools.push(computerTool);
   }
   
-  // 添加Bash工具
+  // 添加Bash工具，告诉模型设置中有哪些环境变量配置
   if (config.enableBashTool && toolGroup.tools.includes('bash')) {
-    tools.push(BASH_TOOL);
+    const profiles = await core.invoke<{ name: string }[]>('get_env_profiles').catch(() => []);
+    tools.push(withEnvProfiles(BASH_TOOL, profiles.map(profile => profile.name)));
   }
   
   // 添加编辑工具
   if (config.enableEditTool && toolGroup.tools.includes('edit')) {
     tools.push(EDIT_TOOL);
   }
+
+  // 添加 REPL 工具，命令策略无法检查其中的代码，只在设置中启用后提供
+  if (config.enableBashTool && toolGroup.tools.includes('bash')) {
+    const replEnabled = await core.invoke<boolean>('get_repl_tools').catch(() => false);
+    if (replEnabled) {
+      tools.push(REPL_TOOL);
+    }
+  }
   
   // 创建API客户端
   const client = new ClaudeApiClient(config.apiKey);
@@ -300,7 +213,8 @@ export async function callClaudeAPI(
     thinkingBudget: config.thinkingBudget,
     onlyNMostRecentImages: config.onlyNMostRecentImages,
     tokenEfficientToolsBeta: config.tokenEfficientToolsBeta,
-    promptCaching: false // 不再需要，我们已经默认为所有消息添加缓存控制
+    promptCaching: false, // 不再需要，我们已经默认为所有消息添加缓存控制
+    sessionId: config.sessionId
   };
   
   // 打印配置和工具信息，用于调试
@@ -0,0 +1,53 @@
+import { describe, expect, test } from 'bun:test';
+import { AVAILABLE_TOOLS, toApiTool, withEnvProfiles } from './tools';
+
+// 后端 Bash 工具支持的操作及其使用的参数，见 src-tauri/src/commands.rs 中的 BashCommandArgs
+const BASH_ACTIONS: Record<string, string[]> = {
+  run: ['command', 'restart', 'run_in_background', 'idle_timeout', 'env_profile'],
+  job_status: ['job_id'],
+  job_output: ['job_id', 'since_offset'],
+  job_kill: ['job_id'],
+  stdin: ['input', 'idle_timeout'],
+  signal: ['signal'],
+  env_set: ['env_name', 'env_value', 'secret'],
+  env_unset: ['env_name'],
+  env_list: [],
+};
+
+const bashTool = AVAILABLE_TOOLS.find(tool => tool.name === 'bash')!;
+
+describe('bash 工具定义', () => {
+  test('以自定义工具发送完整的参数定义', () => {
+    const apiTool = toApiTool(bashTool);
+    expect(apiTool.type).toBe('custom');
+    expect(apiTool).toHaveProperty('input_schema', bashTool.input_schema);
+  });
+
+  test('声明后端支持的所有操作', () => {
+    expect(bashTool.input_schema.properties.action.enum).toEqual(Object.keys(BASH_ACTIONS));
+  });
+
+  test('声明每个操作使用的参数', () => {
+    const declared = Object.keys(bashTool.input_schema.properties);
+    for (const params of Object.values(BASH_ACTIONS)) {
+      expect(declared).toEqual(expect.arrayContaining(params));
+    }
+  });
+
+  test('每个参数都有说明', () => {
+    const undocumented = Object.entries(bashTool.input_schema.properties)
+      .filter(([, property]) => !property.description)
+      .map(([name]) => name);
+    expect(undocumented).toEqual([]);
+    // 除 run 以外的操作不需要命令
+    expect(bashTool.input_schema.required ?? []).not.toContain('command');
+  });
+
+  test('列出设置中的环境变量配置', () => {
+    const withProfiles = withEnvProfiles(bashTool, ['node18', 'proxy-off']);
+    expect(withProfiles.input_schema.properties.env_profile.enum).toEqual(['node18', 'proxy-off']);
+    expect(withEnvProfiles(bashTool, []).input_schema.properties).not.toHaveProperty('env_profile');
+    // 不修改共享的工具定义
+    expect(bashTool.input_schema.properties.env_profile).not.toHaveProperty('enum');
+  });
+});
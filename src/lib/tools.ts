@@ -0,0 +1,211 @@
+// 发送给模型的工具定义
+
+// 计算机工具选项接口
+export interface ComputerToolOptions {
+  display_width_px: number;
+  display_height_px: number;
+  display_number?: number;
+}
+
+// 工具定义
+export interface Tool {
+  name: string;
+  description: string;
+  input_schema: {
+    type: string;
+    properties: Record<string, any>;
+    required?: string[];
+    additionalProperties?: boolean;
+  };
+  options?: ComputerToolOptions;
+}
+
+// 可用工具
+export const AVAILABLE_TOOLS: Tool[] = [
+  {
+    name: 'computer',
+    description: '控制计算机执行各种操作，如截图、点击等',
+    input_schema: {
+      type: 'object',
+      properties: {
+        action: {
+          type: 'string',
+          enum: [
+            'screenshot', 
+            'mouse_move', 
+            'left_click', 
+            'right_click', 
+            'middle_click', 
+            'double_click', 
+            'triple_click', 
+            'type', 
+            'key', 
+            'cursor_position', 
+            'left_mouse_down', 
+            'left_mouse_up', 
+            'scroll', 
+            'hold_key', 
+            'wait'
+          ],
+        },
+        coordinate: { 
+          type: 'array',
+          items: { type: 'number' },
+          minItems: 2,
+          maxItems: 2
+        },
+        text: { type: 'string' },
+        key: { type: 'string' },
+        scroll_direction: { type: 'string', enum: ['up', 'down', 'left', 'right'] },
+        scroll_amount: { type: 'number' },
+        duration: { type: 'number' },
+      },
+      required: ['action'],
+      additionalProperties: false,
+    },
+  },
+  {
+    name: 'bash',
+    description: '在持久的 Bash 会话中执行命令，工作目录和变量在多次调用之间保留。开发服务器、构建等长时间运行的命令用 run_in_background 在后台执行，再用 job_status、job_output 和 job_kill 查看和终止。命令等待输入而提前返回后，用 stdin 输入内容或用 signal 中断它。env_set、env_unset 设置的环境变量在会话重启后依然生效',
+    input_schema: {
+      type: 'object',
+      properties: {
+        action: {
+          type: 'string',
+          enum: ['run', 'job_status', 'job_output', 'job_kill', 'stdin', 'signal', 'env_set', 'env_unset', 'env_list'],
+          description: '要执行的操作，默认为 run',
+        },
+        command: { type: 'string', description: 'run 执行的命令' },
+        restart: { type: 'boolean', description: '重启 Bash 会话，正在执行的命令和后台任务会被终止' },
+        run_in_background: { type: 'boolean', description: 'run 时在后台执行命令，立即返回任务 ID' },
+        job_id: { type: 'string', description: 'job_status、job_output、job_kill 的任务 ID，job_status 省略时列出所有任务' },
+        since_offset: { type: 'number', description: 'job_output 从这个字节偏移开始读取，传入上次结果中的偏移只读取新的输出' },
+        input: { type: 'string', description: 'stdin 写入正在执行的命令的内容，没有以换行结尾时自动加上换行；为空时只继续等待输出' },
+        signal: {
+          type: 'string',
+          enum: ['SIGINT', 'SIGTERM', 'SIGTSTP', 'SIGCONT'],
+          description: 'signal 发送给正在执行的命令的信号',
+        },
+        idle_timeout: { type: 'number', description: 'run 和 stdin 时命令超过这么多秒没有输出并在等待输入就提前返回，默认 5 秒，0 表示不检测' },
+        env_name: { type: 'string', description: 'env_set、env_unset 的环境变量名' },
+        env_value: { type: 'string', description: 'env_set 的环境变量值' },
+        secret: { type: 'boolean', description: 'env_set 的值是敏感值（如令牌、密码），不会出现在日志和工具输出中' },
+        env_profile: { type: 'string', description: 'run 之前应用设置中保存的环境变量配置，与 restart 一起使用时在新会话中应用' },
+      },
+      additionalProperties: false,
+    },
+  },
+  {
+    name: 'edit',
+    description: '查看、创建和编辑文件，在目录中搜索文本和列出文件，撤销或重做之前的修改。路径必须是绝对路径',
+    input_schema: {
+      type: 'object',
+      properties: {
+        command: {
+          type: 'string',
+          enum: [
+            'view',
+            'create',
+            'str_replace',
+            'insert',
+            'multi_edit',
+            'apply_patch',
+            'search',
+            'glob',
+            'undo_edit',
+            'redo_edit',
+            'history'
+          ],
+        },
+        path: { type: 'string', description: '文件或目录的绝对路径；apply_patch 时为补丁中路径的根目录' },
+        file_text: { type: 'string', description: 'create 的文件内容' },
+        view_range: { 
+          type: 'array',
+          items: { type: 'number' },
+          minItems: 2,
+          maxItems: 2,
+          description: 'view 文件时显示的行范围，-1 表示到文件末尾'
+        },
+        old_str: { type: 'string', description: 'str_replace 的原字符串' },
+        new_str: { type: 'string', description: 'str_replace 的新字符串或 insert 插入的文本' },
+        insert_line: { type: 'number', description: 'insert 在这一行之后插入' },
+        regex: { type: 'boolean', description: 'str_replace 的原字符串或 search 的模式是正则表达式' },
+        replace_all: { type: 'boolean', description: 'str_replace 替换所有匹配' },
+        expected_count: { type: 'number', description: 'str_replace 期望的匹配数，不一致时不修改文件' },
+        edits: {
+          type: 'array',
+          description: 'multi_edit 的修改列表，全部成功才写入文件',
+          items: {
+            type: 'object',
+            properties: {
+              old_str: { type: 'string' },
+              new_str: { type: 'string' },
+              replace_all: { type: 'boolean' },
+            },
+            required: ['old_str'],
+            additionalProperties: false,
+          },
+        },
+        patch: { type: 'string', description: 'apply_patch 的统一差异补丁' },
+        dry_run: { type: 'boolean', description: '只返回修改的差异，不写入文件' },
+        pattern: { type: 'string', description: 'search 的模式或 glob 的模式' },
+        case_insensitive: { type: 'boolean', description: 'search 时忽略大小写' },
+        include: { type: 'array', items: { type: 'string' }, description: 'search 只搜索匹配这些 glob 的文件' },
+        exclude: { type: 'array', items: { type: 'string' }, description: 'search 跳过匹配这些 glob 的文件和目录' },
+        context: { type: 'number', description: 'search 结果中每个匹配前后显示的行数' },
+        max_results: { type: 'number', description: 'search 最多返回的匹配数' },
+        depth: { type: 'number', description: 'view 目录时列出的深度' },
+        hidden: { type: 'boolean', description: 'view 目录和 glob 时包括隐藏文件' },
+        max_entries: { type: 'number', description: 'view 目录和 glob 最多列出的条目数' },
+        steps: { type: 'number', description: 'undo_edit、redo_edit 的步数或 history 列出的条数' },
+      },
+      required: ['command', 'path'],
+      additionalProperties: false,
+    },
+  },
+  {
+    name: 'repl',
+    description: '在持久的 Python 或 Node.js 解释器中执行代码，变量和导入的模块在多次调用之间保留',
+    input_schema: {
+      type: 'object',
+      properties: {
+        language: { type: 'string', enum: ['python', 'node'] },
+        code: { type: 'string', description: '要执行的代码' },
+        restart: { type: 'boolean', description: '放弃当前解释器的状态，启动新的解释器' },
+      },
+      required: ['language'],
+      additionalProperties: false,
+    },
+  },
+];
+
+
+// 在 bash 工具定义中列出设置中保存的环境变量配置，没有配置时不提供 env_profile 参数
+export function withEnvProfiles(tool: Tool, names: string[]): Tool {
+  const { env_profile, ...properties } = tool.input_schema.properties;
+  if (names.length > 0) {
+    properties.env_profile = { ...env_profile, enum: names };
+  }
+  return { ...tool, input_schema: { ...tool.input_schema, properties } };
+}
+
+// 转换为 API 请求中的工具定义
+export function toApiTool(tool: Tool) {
+  if (tool.name === 'computer') {
+    // 计算机工具使用 Anthropic 定义的格式，只有 type 和 name，其他参数作为单独的顶级属性
+    return {
+      type: 'computer_20250124',
+      name: tool.name,
+      display_width_px: tool.options?.display_width_px,
+      display_height_px: tool.options?.display_height_px,
+      display_number: tool.options?.display_number ?? 1
+    };
+  }
+  // Bash 和编辑工具支持的操作和选项比 Anthropic 定义的工具多，和其他工具一样使用自定义格式
+  return {
+    type: 'custom',
+    name: tool.name,
+    description: tool.description,
+    input_schema: tool.input_schema,
+  };
+}